@@ -0,0 +1,561 @@
+// Compare the trees against `BTreeMap` on the same workloads:
+//
+//   cargo bench --bench trees
+//   cargo bench --bench trees -- 'get/10000'
+//
+// Every benchmark is named `workload/size/tree`, e.g. `insert_random/10000/
+// bplus(8)`, so the trees of one workload and size are next to each other in
+// the report. The B Tree has a fixed `MINIMUM_DEGREE`, the B+ Tree is run
+// with each of `MAX_DEGREES`.
+//
+// To measure a change to one tree, save a baseline before it and compare to
+// it after:
+//
+//   cargo bench --bench trees -- 'btree\(2\)' --save-baseline before
+//   cargo bench --bench trees -- 'btree\(2\)' --baseline before
+//
+// `range` goes down to the first key of the range in every tree, then
+// iterates `RANGE_LEN` keys.
+use std::collections::BTreeMap;
+use std::hint::black_box;
+use std::ops::Deref;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Zipf};
+
+use trees::binary_search_tree::BSTree;
+use trees::bplustree::BPlusTree;
+use trees::btree::{BTree, MINIMUM_DEGREE};
+use trees::concurrent_bplustree::ConcurrentBPlusTree;
+use trees::inline_vec::InlineVec;
+use trees::olc_bplustree::OlcBPlusTree;
+use trees::simd;
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+const MAX_DEGREES: [usize; 4] = [4, 8, 16, 32];
+
+// The BSTree doesn't rebalance, inserting sorted keys turns it into a list
+// and is quadratic.
+const BSTREE_MAX_SEQUENTIAL: usize = 10_000;
+
+// Number of keys in the node searched by `node_search`, the max degrees of a
+// B+ Tree minus one.
+const NODE_DEGREES: [usize; 6] = [16, 32, 64, 128, 192, 256];
+
+// Max number of keys in a B Tree node, and number of nodes in `node_ops`.
+const BTREE_MAX_KEYS: usize = 2 * MINIMUM_DEGREE - 1;
+const NODES: usize = 1_000;
+
+// Number of reader threads in `read_scaling`, and max degree of both trees.
+const READERS: [usize; 4] = [1, 2, 4, 8];
+const READ_SCALING_MAX_DEGREE: usize = 64;
+
+const GETS: usize = 1_000;
+const RANGES: usize = 100;
+const RANGE_LEN: u32 = 100;
+
+trait Tree {
+    fn insert(&mut self, key: u32);
+    fn get(&self, key: u32) -> bool;
+    fn remove(&mut self, key: u32);
+    // Number of keys in between `from` and `to`, both included.
+    fn range(&self, from: u32, to: u32) -> usize;
+}
+
+impl Tree for BTreeMap<u32, u32> {
+    fn insert(&mut self, key: u32) {
+        self.entry(key).or_insert(key);
+    }
+
+    fn get(&self, key: u32) -> bool {
+        self.contains_key(&key)
+    }
+
+    fn remove(&mut self, key: u32) {
+        self.remove(&key);
+    }
+
+    fn range(&self, from: u32, to: u32) -> usize {
+        self.range(from..=to).count()
+    }
+}
+
+impl Tree for BSTree {
+    fn insert(&mut self, key: u32) {
+        self.insert(key as i32);
+    }
+
+    fn get(&self, key: u32) -> bool {
+        self.get(&(key as i32)).is_some()
+    }
+
+    fn remove(&mut self, key: u32) {
+        self.remove(key as i32);
+    }
+
+    fn range(&self, from: u32, to: u32) -> usize {
+        self.range(from as i32..=to as i32).count()
+    }
+}
+
+impl Tree for BTree {
+    fn insert(&mut self, key: u32) {
+        self.insert(key);
+    }
+
+    fn get(&self, key: u32) -> bool {
+        self.get(&key).is_some()
+    }
+
+    fn remove(&mut self, key: u32) {
+        self.remove(&key);
+    }
+
+    fn range(&self, from: u32, to: u32) -> usize {
+        self.range(from..=to).count()
+    }
+}
+
+impl Tree for BPlusTree<u32, u32> {
+    fn insert(&mut self, key: u32) {
+        self.insert(key, key);
+    }
+
+    fn get(&self, key: u32) -> bool {
+        self.get(&key).is_some()
+    }
+
+    fn remove(&mut self, key: u32) {
+        self.remove(&key);
+    }
+
+    fn range(&self, from: u32, to: u32) -> usize {
+        self.range(from..=to).count()
+    }
+}
+
+struct Target {
+    name: String,
+    new: Box<dyn Fn() -> Box<dyn Tree>>,
+}
+
+fn targets(max_degrees: &[usize]) -> Vec<Target> {
+    let mut targets = vec![
+        Target {
+            name: "btreemap".to_string(),
+            new: Box::new(|| Box::new(BTreeMap::new())),
+        },
+        Target {
+            name: "bst".to_string(),
+            new: Box::new(|| Box::new(BSTree::new())),
+        },
+        Target {
+            name: format!("btree({MINIMUM_DEGREE})"),
+            new: Box::new(|| Box::new(BTree::new())),
+        },
+    ];
+    for &max_degree in max_degrees {
+        targets.push(Target {
+            name: format!("bplus({max_degree})"),
+            new: Box::new(move || Box::new(BPlusTree::<u32, u32>::with_max_degree(max_degree))),
+        });
+    }
+    targets
+}
+
+fn build(target: &Target, keys: &[u32]) -> Box<dyn Tree> {
+    let mut tree = (target.new)();
+    for &key in keys {
+        tree.insert(key);
+    }
+    tree
+}
+
+fn shuffled(size: usize, rng: &mut StdRng) -> Vec<u32> {
+    let mut keys: Vec<u32> = (0..size as u32).collect();
+    keys.shuffle(rng);
+    keys
+}
+
+// Keys with a zipfian popularity, the most popular ones scattered over the
+// key space instead of being the smallest.
+fn zipfian(size: usize, rng: &mut StdRng) -> Vec<u32> {
+    let keys = shuffled(size, rng);
+    let zipf = Zipf::new(size as u64, 1.1).unwrap();
+    (0..size)
+        .map(|_| keys[zipf.sample(rng) as usize - 1])
+        .collect()
+}
+
+fn insert(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+
+    for size in SIZES {
+        let workloads = [
+            ("insert_sequential", (0..size as u32).collect()),
+            ("insert_random", shuffled(size, &mut rng)),
+            ("insert_zipfian", zipfian(size, &mut rng)),
+        ];
+
+        for (workload, keys) in workloads {
+            let mut group = c.benchmark_group(format!("{workload}/{size}"));
+            group.throughput(Throughput::Elements(size as u64));
+            if size >= 100_000 {
+                group.sample_size(10);
+            }
+
+            for target in targets(&MAX_DEGREES) {
+                if target.name == "bst"
+                    && workload == "insert_sequential"
+                    && size > BSTREE_MAX_SEQUENTIAL
+                {
+                    continue;
+                }
+                group.bench_function(BenchmarkId::from_parameter(&target.name), |b| {
+                    b.iter_with_large_drop(|| build(&target, &keys))
+                });
+            }
+            group.finish();
+        }
+    }
+}
+
+fn get(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(1);
+
+    for size in SIZES {
+        let keys = shuffled(size, &mut rng);
+        // Half of them aren't in the tree.
+        let probes: Vec<u32> = (0..GETS)
+            .map(|_| rng.gen_range(0..2 * size as u32))
+            .collect();
+
+        let mut group = c.benchmark_group(format!("get/{size}"));
+        group.throughput(Throughput::Elements(GETS as u64));
+
+        for target in targets(&MAX_DEGREES) {
+            let tree = build(&target, &keys);
+            group.bench_function(BenchmarkId::from_parameter(&target.name), |b| {
+                b.iter(|| probes.iter().filter(|&&key| tree.get(key)).count())
+            });
+        }
+        group.finish();
+    }
+}
+
+fn range(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(2);
+
+    for size in SIZES {
+        let keys = shuffled(size, &mut rng);
+        let starts: Vec<u32> = (0..RANGES).map(|_| rng.gen_range(0..size as u32)).collect();
+
+        let mut group = c.benchmark_group(format!("range/{size}"));
+        group.throughput(Throughput::Elements(RANGES as u64));
+
+        for target in targets(&MAX_DEGREES) {
+            let tree = build(&target, &keys);
+            group.bench_function(BenchmarkId::from_parameter(&target.name), |b| {
+                b.iter(|| {
+                    starts
+                        .iter()
+                        .map(|&from| tree.range(from, from + RANGE_LEN - 1))
+                        .sum::<usize>()
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+fn remove_all(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(3);
+
+    for size in SIZES {
+        let keys = shuffled(size, &mut rng);
+        let removes = shuffled(size, &mut rng);
+
+        let mut group = c.benchmark_group(format!("remove_all/{size}"));
+        group.throughput(Throughput::Elements(size as u64));
+        if size >= 100_000 {
+            group.sample_size(10);
+        }
+
+        for target in targets(&MAX_DEGREES) {
+            group.bench_function(BenchmarkId::from_parameter(&target.name), |b| {
+                b.iter_batched(
+                    || build(&target, &keys),
+                    |mut tree| {
+                        for &key in &removes {
+                            tree.remove(key);
+                        }
+                        tree
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Insert(u32),
+    Get(u32),
+    Remove(u32),
+}
+
+// Half gets, 30% inserts and 20% removes, on a tree filled with half of the
+// key space.
+fn mixed(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(4);
+
+    for size in SIZES {
+        let keys: Vec<u32> = shuffled(2 * size, &mut rng)
+            .into_iter()
+            .take(size)
+            .collect();
+        let ops: Vec<Op> = (0..size)
+            .map(|_| {
+                let key = rng.gen_range(0..2 * size as u32);
+                match rng.gen_range(0..10) {
+                    0..=4 => Op::Get(key),
+                    5..=7 => Op::Insert(key),
+                    _ => Op::Remove(key),
+                }
+            })
+            .collect();
+
+        let mut group = c.benchmark_group(format!("mixed/{size}"));
+        group.throughput(Throughput::Elements(size as u64));
+        if size >= 100_000 {
+            group.sample_size(10);
+        }
+
+        for target in targets(&MAX_DEGREES) {
+            group.bench_function(BenchmarkId::from_parameter(&target.name), |b| {
+                b.iter_batched(
+                    || build(&target, &keys),
+                    |mut tree| {
+                        let mut found = 0;
+                        for &op in &ops {
+                            match op {
+                                Op::Insert(key) => tree.insert(key),
+                                Op::Get(key) => found += tree.get(key) as usize,
+                                Op::Remove(key) => tree.remove(key),
+                            }
+                        }
+                        (tree, found)
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+// Search a key in the sorted keys of a node, with `binary_search` or with
+// the SIMD search, to find the node size from which the trees use the SIMD
+// search (`SIMD_KEYS` in `src/simd.rs`).
+fn node_search(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(5);
+
+    for degree in NODE_DEGREES {
+        // Even keys, half of the probes aren't in the node.
+        let keys: Vec<u32> = (0..degree as u32 - 1).map(|i| 2 * i).collect();
+        let wide_keys: Vec<u64> = keys.iter().map(|&key| key as u64).collect();
+        let probes: Vec<u32> = (0..GETS)
+            .map(|_| rng.gen_range(0..2 * degree as u32))
+            .collect();
+
+        let mut group = c.benchmark_group(format!("node_search/{degree}"));
+        group.throughput(Throughput::Elements(GETS as u64));
+
+        group.bench_function("binary_search_u32", |b| {
+            b.iter(|| {
+                probes
+                    .iter()
+                    .filter(|&key| keys.binary_search(key).is_ok())
+                    .count()
+            })
+        });
+        group.bench_function("simd_u32", |b| {
+            b.iter(|| {
+                probes
+                    .iter()
+                    .filter(|&&key| simd::search_u32(&keys, key).is_ok())
+                    .count()
+            })
+        });
+        group.bench_function("binary_search_u64", |b| {
+            b.iter(|| {
+                probes
+                    .iter()
+                    .filter(|&&key| wide_keys.binary_search(&(key as u64)).is_ok())
+                    .count()
+            })
+        });
+        group.bench_function("simd_u64", |b| {
+            b.iter(|| {
+                probes
+                    .iter()
+                    .filter(|&&key| simd::search_u64(&wide_keys, key as u64).is_ok())
+                    .count()
+            })
+        });
+        group.finish();
+    }
+}
+
+// The keys of a B Tree node, in a `Vec` as they were before or in the
+// `InlineVec` of the tree now.
+trait NodeKeys: Default + Deref<Target = [u32]> {
+    fn insert(&mut self, index: usize, key: u32);
+    fn remove(&mut self, index: usize) -> u32;
+    fn pop(&mut self) -> Option<u32>;
+    fn split_off(&mut self, at: usize) -> Self;
+}
+
+impl NodeKeys for Vec<u32> {
+    fn insert(&mut self, index: usize, key: u32) {
+        Vec::insert(self, index, key)
+    }
+
+    fn remove(&mut self, index: usize) -> u32 {
+        Vec::remove(self, index)
+    }
+
+    fn pop(&mut self) -> Option<u32> {
+        Vec::pop(self)
+    }
+
+    fn split_off(&mut self, at: usize) -> Self {
+        Vec::split_off(self, at)
+    }
+}
+
+impl NodeKeys for InlineVec<u32, BTREE_MAX_KEYS> {
+    fn insert(&mut self, index: usize, key: u32) {
+        InlineVec::insert(self, index, key)
+    }
+
+    fn remove(&mut self, index: usize) -> u32 {
+        InlineVec::remove(self, index)
+    }
+
+    fn pop(&mut self) -> Option<u32> {
+        InlineVec::pop(self)
+    }
+
+    fn split_off(&mut self, at: usize) -> Self {
+        InlineVec::split_off(self, at)
+    }
+}
+
+// Fill a node key by key, split it the way `split_child` does, then remove a
+// key from the left node and insert the middle key in the right one.
+fn node_life<N: NodeKeys>(keys: &[u32]) -> usize {
+    let mut left = N::default();
+    for &key in keys {
+        left.insert(left.partition_point(|&other| other < key), key);
+    }
+    let mut right = left.split_off(MINIMUM_DEGREE);
+    let middle = left.pop().unwrap();
+    left.remove(0);
+    right.insert(0, middle);
+    left.len() + right.len()
+}
+
+// Insert, split and remove on `NODES` nodes with the keys of the `BTree` in a
+// `Vec` against the `InlineVec` which replaced it.
+fn node_ops(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(6);
+    let keys: Vec<[u32; BTREE_MAX_KEYS]> = (0..NODES).map(|_| rng.gen()).collect();
+
+    let mut group = c.benchmark_group("node_ops");
+    group.throughput(Throughput::Elements(NODES as u64));
+    group.bench_function("vec", |b| {
+        b.iter(|| {
+            keys.iter()
+                .map(|keys| node_life::<Vec<u32>>(black_box(keys)))
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("inline_vec", |b| {
+        b.iter(|| {
+            keys.iter()
+                .map(|keys| node_life::<InlineVec<u32, BTREE_MAX_KEYS>>(black_box(keys)))
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+// Gets from several threads at once on the latched B+ Tree, where readers
+// still write to the latch of every node on their way down, against the
+// optimistic one, where they don't write at all. Each of `READERS` threads
+// does one get per iteration, and the size is the number of threads instead
+// of keys. Throughput only grows with threads up to the number of cores.
+fn read_scaling(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(7);
+    let size = SIZES[SIZES.len() - 1];
+    let keys: Vec<u32> = shuffled(size, &mut rng);
+    let latched = ConcurrentBPlusTree::new(keys.clone(), READ_SCALING_MAX_DEGREE);
+    let optimistic = OlcBPlusTree::new(keys.clone(), READ_SCALING_MAX_DEGREE);
+
+    fn measure(
+        iters: u64,
+        threads: usize,
+        keys: &[u32],
+        get: impl Fn(&u32) -> bool + Sync,
+    ) -> Duration {
+        let start = Instant::now();
+        thread::scope(|scope| {
+            for id in 0..threads {
+                let get = &get;
+                scope.spawn(move || {
+                    let keys = keys.iter().cycle().skip(id * keys.len() / threads);
+                    for key in keys.take(iters as usize) {
+                        assert!(get(black_box(key)));
+                    }
+                });
+            }
+        });
+        start.elapsed()
+    }
+
+    for threads in READERS {
+        let mut group = c.benchmark_group(format!("read_scaling/{threads}"));
+        group.throughput(Throughput::Elements(threads as u64));
+
+        group.bench_function("latched", |b| {
+            b.iter_custom(|iters| measure(iters, threads, &keys, |key| latched.get(key).is_some()))
+        });
+        group.bench_function("olc", |b| {
+            b.iter_custom(|iters| {
+                measure(iters, threads, &keys, |key| optimistic.get(key).is_some())
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(
+    benches,
+    insert,
+    get,
+    range,
+    remove_all,
+    mixed,
+    node_search,
+    node_ops,
+    read_scaling
+);
+criterion_main!(benches);
@@ -0,0 +1,148 @@
+use std::ops::{Index, IndexMut};
+
+// Storage for the nodes of a tree, which reference each other by a `NodeId`,
+// the index of their slot, instead of owning their childrens:
+//
+//   slots:  [4, 7]  [1, 2, 3]  (free)  [8, 9]  [5, 6]
+//   ids:      0         1        2       3       4
+//
+//   node 0 childrens: [1, 4, 3]
+//
+// Moving a subtree from a node to another only moves its id, the nodes are in
+// a few large allocations instead of one each, and a leaf can point to its
+// next sibling without owning it. Freed slots are reused by the next node,
+// like the pages of a file.
+pub(crate) struct Arena<T> {
+    slots: Vec<Option<T>>,
+    free: Vec<NodeId>, // Freed slots, the last one is reused first.
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct NodeId(u32);
+
+impl NodeId {
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl<T> Arena<T> {
+    pub(crate) fn new() -> Self {
+        Arena {
+            slots: vec![],
+            free: vec![],
+        }
+    }
+
+    pub(crate) fn alloc(&mut self, node: T) -> NodeId {
+        match self.free.pop() {
+            Some(id) => {
+                self.slots[id.index()] = Some(node);
+                id
+            }
+            None => {
+                let id = u32::try_from(self.slots.len()).expect("too many nodes");
+                self.slots.push(Some(node));
+                NodeId(id)
+            }
+        }
+    }
+
+    // Take the node out of the arena, its id can then be given to another
+    // node.
+    pub(crate) fn free(&mut self, id: NodeId) -> T {
+        let node = self.slots[id.index()].take().expect("node already freed");
+        self.free.push(id);
+        node
+    }
+
+    // Number of nodes, not counting the freed slots.
+    pub(crate) fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    // Borrow two different nodes at once, e.g. to move keys from a node to
+    // its sibling.
+    pub(crate) fn pair_mut(&mut self, a: NodeId, b: NodeId) -> (&mut T, &mut T) {
+        assert_ne!(a, b, "can't borrow the same node twice");
+        let (low, high) = (a.index().min(b.index()), a.index().max(b.index()));
+        let (left, right) = self.slots.split_at_mut(high);
+        let (low, high) = (
+            left[low].as_mut().expect("node freed"),
+            right[0].as_mut().expect("node freed"),
+        );
+
+        if a.index() < b.index() {
+            (low, high)
+        } else {
+            (high, low)
+        }
+    }
+
+    // Bytes allocated for the slots, freed or not. The heap memory owned by
+    // the nodes themselves isn't counted.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.slots.capacity() * std::mem::size_of::<Option<T>>()
+            + self.free.capacity() * std::mem::size_of::<NodeId>()
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Index<NodeId> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: NodeId) -> &T {
+        self.slots[id.index()].as_ref().expect("node freed")
+    }
+}
+
+impl<T> IndexMut<NodeId> for Arena<T> {
+    fn index_mut(&mut self, id: NodeId) -> &mut T {
+        self.slots[id.index()].as_mut().expect("node freed")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Arena;
+
+    #[test]
+    fn reuse_freed_slots() {
+        let mut arena = Arena::new();
+        let a = arena.alloc("a");
+        let b = arena.alloc("b");
+        assert_eq!((arena[a], arena[b], arena.len()), ("a", "b", 2));
+
+        assert_eq!(arena.free(a), "a");
+        assert_eq!(arena.len(), 1);
+
+        let c = arena.alloc("c");
+        assert_eq!(c, a);
+        assert_eq!((arena[c], arena.len()), ("c", 2));
+    }
+
+    #[test]
+    fn pair_mut() {
+        let mut arena = Arena::new();
+        let a = arena.alloc(vec![1, 2]);
+        let b = arena.alloc(vec![3]);
+
+        let (right, left) = arena.pair_mut(b, a);
+        right.insert(0, left.pop().unwrap());
+        assert_eq!((&arena[a], &arena[b]), (&vec![1], &vec![2, 3]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn freed_node() {
+        let mut arena = Arena::new();
+        let a = arena.alloc(1);
+        arena.free(a);
+        let _ = arena[a];
+    }
+}
@@ -0,0 +1,533 @@
+// Replay a trace of operations against the trees, checking every result
+// against `BTreeMap`, and report the throughput, the latencies and the shape
+// of the tree at the end:
+//
+//   $ cargo run --release --bin replay -- trace.txt --tree btree,bplus --degree 4,8,16
+//
+// `--degree` is the max degree of the B+ Tree, the B Tree has a fixed minimum
+// degree. A text trace has one operation per line, skipping blank lines and
+// lines starting with `#`:
+//
+//   insert 5 50
+//   remove 5
+//   get 5
+//   range 1 9      both bounds included
+//
+// A binary trace is the magic "TRAC", a version byte, then a record for each
+// operation: the opcode as a u8 (1 insert, 2 remove, 3 get, 4 range) followed
+// by its u32 arguments, little endian.
+//
+//   $ cargo run --bin replay -- convert trace.txt trace.bin
+//
+// writes the binary trace of a text one.
+//
+// The trees without values store the key as its value, and like `insert` on
+// any tree, inserting a key already there keeps the first value.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::process;
+use std::time::{Duration, Instant};
+
+use trees::binary_search_tree::BSTree;
+use trees::bplustree::{BPlusTree, DEFAULT_MAX_DEGREE};
+use trees::btree::BTree;
+
+const MAGIC: [u8; 4] = *b"TRAC";
+const VERSION: u8 = 1;
+
+const USAGE: &str = "\
+usage: replay <trace> [--tree bst,btree,bplus] [--degree 3,4,...]
+       replay convert <text trace> <binary trace>";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Insert(u32, u32),
+    Remove(u32),
+    Get(u32),
+    Range(u32, u32),
+}
+
+impl Op {
+    const NAMES: [&'static str; 4] = ["insert", "remove", "get", "range"];
+
+    fn index(self) -> usize {
+        match self {
+            Op::Insert(..) => 0,
+            Op::Remove(_) => 1,
+            Op::Get(_) => 2,
+            Op::Range(..) => 3,
+        }
+    }
+}
+
+fn parse_text(trace: &str) -> Result<Vec<Op>, String> {
+    let mut ops = vec![];
+
+    for (number, line) in trace.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let args: Result<Vec<u32>, _> = words[1..].iter().map(|arg| arg.parse()).collect();
+        let op = match (words[0], args.as_deref()) {
+            ("insert", Ok(&[key, value])) => Op::Insert(key, value),
+            ("remove", Ok(&[key])) => Op::Remove(key),
+            ("get", Ok(&[key])) => Op::Get(key),
+            ("range", Ok(&[from, to])) => Op::Range(from, to),
+            _ => return Err(format!("line {}: invalid operation {line:?}", number + 1)),
+        };
+        ops.push(op);
+    }
+
+    Ok(ops)
+}
+
+fn write_binary(ops: &[Op]) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+
+    for op in ops {
+        let (opcode, args) = match *op {
+            Op::Insert(key, value) => (1, vec![key, value]),
+            Op::Remove(key) => (2, vec![key]),
+            Op::Get(key) => (3, vec![key]),
+            Op::Range(from, to) => (4, vec![from, to]),
+        };
+        bytes.push(opcode);
+        for arg in args {
+            bytes.extend(arg.to_le_bytes());
+        }
+    }
+
+    bytes
+}
+
+fn read_binary(bytes: &[u8]) -> Result<Vec<Op>, String> {
+    match bytes.get(MAGIC.len()) {
+        Some(&VERSION) => (),
+        Some(version) => return Err(format!("unsupported trace version {version}")),
+        None => return Err("truncated trace header".to_string()),
+    }
+
+    let mut ops = vec![];
+    let mut rest = &bytes[MAGIC.len() + 1..];
+    let next_u32 = |rest: &mut &[u8]| -> Result<u32, String> {
+        let (arg, tail) = rest
+            .split_first_chunk::<4>()
+            .ok_or_else(|| format!("truncated operation {}", offset(rest, bytes)))?;
+        *rest = tail;
+        Ok(u32::from_le_bytes(*arg))
+    };
+
+    while let Some((&opcode, tail)) = rest.split_first() {
+        rest = tail;
+        let op = match opcode {
+            1 => Op::Insert(next_u32(&mut rest)?, next_u32(&mut rest)?),
+            2 => Op::Remove(next_u32(&mut rest)?),
+            3 => Op::Get(next_u32(&mut rest)?),
+            4 => Op::Range(next_u32(&mut rest)?, next_u32(&mut rest)?),
+            _ => {
+                return Err(format!(
+                    "invalid opcode {opcode} at byte {}",
+                    offset(rest, bytes) - 1
+                ))
+            }
+        };
+        ops.push(op);
+    }
+
+    Ok(ops)
+}
+
+// Offset of `rest` in `bytes`, for the error messages.
+fn offset(rest: &[u8], bytes: &[u8]) -> usize {
+    bytes.len() - rest.len()
+}
+
+fn read_trace(bytes: &[u8]) -> Result<Vec<Op>, String> {
+    if bytes.starts_with(&MAGIC) {
+        read_binary(bytes)
+    } else {
+        let text = std::str::from_utf8(bytes).map_err(|_| "trace is neither text nor binary")?;
+        parse_text(text)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    BS,
+    B,
+    BPlus(usize),
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::BS => write!(f, "bst"),
+            Kind::B => write!(f, "btree"),
+            Kind::BPlus(max_degree) => write!(f, "bplus, max degree {max_degree}"),
+        }
+    }
+}
+
+enum Tree {
+    BS(BSTree),
+    B(BTree),
+    BPlus(BPlusTree<u32, u32>),
+}
+
+impl Tree {
+    fn new(kind: Kind) -> Self {
+        match kind {
+            Kind::BS => Tree::BS(BSTree::new()),
+            Kind::B => Tree::B(BTree::new()),
+            Kind::BPlus(max_degree) => Tree::BPlus(BPlusTree::with_max_degree(max_degree)),
+        }
+    }
+
+    // Returns what the operation returns on a `BTreeMap`, that keeps the first
+    // value on insert.
+    fn apply(&mut self, op: Op) -> Result<Vec<(u32, u32)>, String> {
+        let found =
+            |key: u32, value: Option<u32>| value.map(|value| (key, value)).into_iter().collect();
+
+        Ok(match (self, op) {
+            // The BSTree keeps duplicated values, so check first.
+            (Tree::BS(tree), Op::Insert(key, _)) => {
+                let key = to_i32(key)?;
+                if tree.get(&key).is_none() {
+                    tree.insert(key);
+                }
+                vec![]
+            }
+            (Tree::BS(tree), Op::Remove(key)) => {
+                let value = tree.get(&to_i32(key)?).map(|_| key);
+                tree.remove(to_i32(key)?);
+                found(key, value)
+            }
+            (Tree::BS(tree), Op::Get(key)) => found(key, tree.get(&to_i32(key)?).map(|_| key)),
+            // The keys of a bst are at most `i32::MAX`.
+            (Tree::BS(tree), Op::Range(from, to)) => match to.min(i32::MAX as u32) {
+                to if from > to => vec![],
+                to => tree
+                    .range(from as i32..=to as i32)
+                    .map(|&key| (key as u32, key as u32))
+                    .collect(),
+            },
+
+            (Tree::B(tree), Op::Insert(key, _)) => {
+                tree.insert(key);
+                vec![]
+            }
+            (Tree::B(tree), Op::Remove(key)) => found(key, tree.remove(&key)),
+            (Tree::B(tree), Op::Get(key)) => found(key, tree.get(&key).copied()),
+            (Tree::B(tree), Op::Range(from, to)) => {
+                tree.range(from..=to).map(|&key| (key, key)).collect()
+            }
+
+            (Tree::BPlus(tree), Op::Insert(key, value)) => {
+                tree.insert(key, value);
+                vec![]
+            }
+            (Tree::BPlus(tree), Op::Remove(key)) => found(key, tree.remove(&key)),
+            (Tree::BPlus(tree), Op::Get(key)) => found(key, tree.get(&key).copied()),
+            (Tree::BPlus(tree), Op::Range(from, to)) => tree
+                .range(from..=to)
+                .map(|(&key, &value)| (key, value))
+                .collect(),
+        })
+    }
+
+    fn entries(&self) -> Vec<(u32, u32)> {
+        match self {
+            Tree::BS(tree) => tree.iter().map(|&key| (key as u32, key as u32)).collect(),
+            Tree::B(tree) => tree.iter().map(|&key| (key, key)).collect(),
+            Tree::BPlus(tree) => tree.iter().map(|(&key, &value)| (key, value)).collect(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Tree::BS(tree) => tree.validate(),
+            Tree::B(tree) => tree.validate(),
+            Tree::BPlus(tree) => tree.validate(),
+        }
+    }
+
+    fn height(&self) -> usize {
+        match self {
+            Tree::BS(tree) => tree.height(),
+            Tree::B(tree) => tree.height(),
+            Tree::BPlus(tree) => tree.height(),
+        }
+    }
+
+    fn node_count(&self) -> usize {
+        match self {
+            Tree::BS(tree) => tree.node_count(),
+            Tree::B(tree) => tree.node_count(),
+            Tree::BPlus(tree) => tree.node_count(),
+        }
+    }
+}
+
+fn to_i32(key: u32) -> Result<i32, String> {
+    i32::try_from(key).map_err(|_| format!("{key} doesn't fit in a bst key"))
+}
+
+// Same as `Tree::apply`, on the reference.
+fn apply_model(model: &mut BTreeMap<u32, u32>, op: Op, has_values: bool) -> Vec<(u32, u32)> {
+    let found =
+        |key: u32, value: Option<u32>| value.map(|value| (key, value)).into_iter().collect();
+
+    match op {
+        Op::Insert(key, value) => {
+            model
+                .entry(key)
+                .or_insert(if has_values { value } else { key });
+            vec![]
+        }
+        Op::Remove(key) => found(key, model.remove(&key)),
+        Op::Get(key) => found(key, model.get(&key).copied()),
+        Op::Range(from, to) if from <= to => {
+            model.range(from..=to).map(|(&k, &v)| (k, v)).collect()
+        }
+        Op::Range(..) => vec![],
+    }
+}
+
+struct Report {
+    kind: Kind,
+    latencies: [Vec<Duration>; 4], // Indexed by `Op::index`, sorted.
+    height: usize,
+    node_count: usize,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operations: usize = self.latencies.iter().map(Vec::len).sum();
+        let elapsed: Duration = self.latencies.iter().flatten().sum();
+        let throughput = operations as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+
+        writeln!(
+            f,
+            "{}: {operations} operations in {elapsed:.2?}, {throughput:.0} ops/s, height {}, {} nodes",
+            self.kind, self.height, self.node_count
+        )?;
+
+        for (name, latencies) in Op::NAMES.iter().zip(&self.latencies) {
+            if let Some(max) = latencies.last() {
+                let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+                writeln!(
+                    f,
+                    "  {name:<6} {:>9} ops   p50 {:>9.2?}   p99 {:>9.2?}   max {:>9.2?}",
+                    latencies.len(),
+                    percentile(50),
+                    percentile(99),
+                    max
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Replay every operation, stopping at the first one that doesn't return the
+// same as on the reference. Only the tree operations are timed.
+fn replay(kind: Kind, ops: &[Op]) -> Result<Report, String> {
+    let mut tree = Tree::new(kind);
+    let mut model = BTreeMap::new();
+    let mut latencies: [Vec<Duration>; 4] = Default::default();
+    let has_values = matches!(kind, Kind::BPlus(_));
+
+    for (i, &op) in ops.iter().enumerate() {
+        let start = Instant::now();
+        let actual = tree.apply(op)?;
+        latencies[op.index()].push(start.elapsed());
+
+        let expected = apply_model(&mut model, op, has_values);
+        if actual != expected {
+            return Err(format!(
+                "{kind}: operation {} ({op:?}) returned {actual:?} instead of {expected:?}",
+                i + 1
+            ));
+        }
+    }
+
+    let expected: Vec<(u32, u32)> = model.into_iter().collect();
+    if tree.entries() != expected {
+        return Err(format!(
+            "{kind}: the tree doesn't have the same entries at the end"
+        ));
+    }
+    tree.validate()
+        .map_err(|error| format!("{kind}: invalid tree at the end: {error}"))?;
+
+    for latencies in &mut latencies {
+        latencies.sort_unstable();
+    }
+    Ok(Report {
+        kind,
+        latencies,
+        height: tree.height(),
+        node_count: tree.node_count(),
+    })
+}
+
+fn parse_list<T, F: Fn(&str) -> Option<T>>(
+    arg: Option<&String>,
+    parse: F,
+) -> Result<Vec<T>, String> {
+    let arg = arg.ok_or(USAGE)?;
+    arg.split(',')
+        .map(|item| parse(item).ok_or_else(|| format!("invalid {item:?}\n{USAGE}")))
+        .collect()
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    if let [command, text, binary] = args {
+        if command == "convert" {
+            let text = fs::read_to_string(text).map_err(|e| format!("{text}: {e}"))?;
+            let ops = parse_text(&text)?;
+            fs::write(binary, write_binary(&ops)).map_err(|e| format!("{binary}: {e}"))?;
+            println!("wrote {} operations to {binary}", ops.len());
+            return Ok(());
+        }
+    }
+
+    let (path, mut options) = args.split_first().ok_or(USAGE)?;
+    let mut kinds = vec!["bplus"];
+    let mut degrees = vec![DEFAULT_MAX_DEGREE];
+    let mut trees_arg = None;
+
+    while let [option, value, rest @ ..] = options {
+        match option.as_str() {
+            "--tree" => trees_arg = Some(value),
+            "--degree" => {
+                degrees = parse_list(Some(value), |degree| {
+                    degree.parse().ok().filter(|&degree: &usize| degree >= 3)
+                })?
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+        options = rest;
+    }
+    if !options.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    if let Some(arg) = trees_arg {
+        kinds = parse_list(Some(arg), |kind| {
+            ["bst", "btree", "bplus"]
+                .into_iter()
+                .find(|&name| name == kind)
+        })?;
+    }
+
+    let bytes = fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+    let ops = read_trace(&bytes)?;
+
+    let mut failed = false;
+    for kind in kinds {
+        let kinds = match kind {
+            "bst" => vec![Kind::BS],
+            "btree" => vec![Kind::B],
+            _ => degrees.iter().map(|&degree| Kind::BPlus(degree)).collect(),
+        };
+
+        for kind in kinds {
+            match replay(kind, &ops) {
+                Ok(report) => print!("{report}"),
+                Err(error) => {
+                    eprintln!("{error}");
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    if failed {
+        return Err("some replays didn't match BTreeMap".to_string());
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(error) = run(&args) {
+        eprintln!("{error}");
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_text, read_binary, read_trace, replay, write_binary, Kind, Op};
+
+    #[test]
+    fn parse_text_trace() {
+        let trace = "# warm up\ninsert 1 10\n\n  get 1\nremove 1\nrange 0 9\n";
+        assert_eq!(
+            parse_text(trace),
+            Ok(vec![
+                Op::Insert(1, 10),
+                Op::Get(1),
+                Op::Remove(1),
+                Op::Range(0, 9)
+            ])
+        );
+
+        assert_eq!(
+            parse_text("get 1\nget\n"),
+            Err("line 2: invalid operation \"get\"".to_string())
+        );
+        assert!(parse_text("insert 1").is_err());
+        assert!(parse_text("get -1").is_err());
+        assert!(parse_text("scan 1 2").is_err());
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let ops = vec![
+            Op::Insert(1, u32::MAX),
+            Op::Remove(7),
+            Op::Get(0),
+            Op::Range(3, 9),
+        ];
+        let bytes = write_binary(&ops);
+
+        assert_eq!(read_trace(&bytes), Ok(ops));
+        assert!(read_binary(&bytes[..bytes.len() - 1]).is_err());
+        assert!(read_binary(b"TRAC\x02").is_err());
+        assert!(read_binary(b"TRAC\x01\x09").is_err());
+    }
+
+    #[test]
+    fn replay_against_btreemap() {
+        let mut ops: Vec<Op> = (0..200).map(|i| Op::Insert(i * 7 % 200, i)).collect();
+        ops.extend((0..200).step_by(3).map(Op::Remove));
+        ops.extend((0..200).map(Op::Get));
+        ops.push(Op::Range(50, 150));
+        ops.push(Op::Insert(1, 1000));
+
+        for kind in [Kind::BS, Kind::B, Kind::BPlus(5)] {
+            let report = replay(kind, &ops).unwrap();
+            assert_eq!(
+                report.latencies.iter().map(Vec::len).sum::<usize>(),
+                ops.len()
+            );
+            assert!(report.height > 0);
+        }
+    }
+
+    #[test]
+    fn reject_keys_the_bstree_cant_hold() {
+        let ops = [Op::Insert(u32::MAX, 0)];
+        assert_eq!(
+            replay(Kind::BS, &ops).err(),
+            Some(format!("{} doesn't fit in a bst key", u32::MAX))
+        );
+    }
+}
@@ -0,0 +1,470 @@
+// Interactive shell to explore the trees, e.g. to reproduce a bug report:
+//
+//   $ cargo run --bin trees
+//   > use bplus 4
+//   > insert 1..20
+//   > delete 7
+//   > show
+//
+// Commands can also be piped in, one per line. Type `help` for every command.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+use trees::binary_search_tree::BSTree;
+use trees::bplustree::{BPlusTree, DEFAULT_MAX_DEGREE};
+use trees::btree::BTree;
+use trees::serialization::Error;
+use trees::stats::Stats;
+
+const HELP: &str = "\
+use bst|btree|bplus [max degree]   start over with an empty tree
+insert <keys>                      e.g. insert 1 5 9, insert 1..20, insert 1..=20
+delete <keys>                      same keys as insert
+get <key>
+range <from> <to>                  keys in between from and to, both included
+show                               draw the tree
+validate                           check the tree invariants
+stats                              height, nodes, fill factors and memory
+undo                               revert the last use, insert, delete or load
+save <file>
+load <file>
+help
+quit";
+
+enum Tree {
+    BS(BSTree),
+    B(BTree),
+    BPlus(BPlusTree),
+}
+
+impl Tree {
+    fn name(&self) -> &'static str {
+        match self {
+            Tree::BS(_) => "bst",
+            Tree::B(_) => "btree",
+            Tree::BPlus(_) => "bplus",
+        }
+    }
+
+    fn insert(&mut self, key: i64) -> Result<(), String> {
+        match self {
+            Tree::BS(tree) => {
+                tree.insert(to_i32(key)?);
+            }
+            Tree::B(tree) => tree.insert(to_u32(key)?),
+            Tree::BPlus(tree) => tree.insert(to_u32(key)?, to_u32(key)?),
+        }
+        Ok(())
+    }
+
+    // Returns whether the key was in the tree.
+    fn delete(&mut self, key: i64) -> Result<bool, String> {
+        Ok(match self {
+            Tree::BS(tree) => {
+                let key = to_i32(key)?;
+                let found = tree.get(&key).is_some();
+                tree.remove(key);
+                found
+            }
+            Tree::B(tree) => tree.remove(&to_u32(key)?).is_some(),
+            Tree::BPlus(tree) => tree.remove(&to_u32(key)?).is_some(),
+        })
+    }
+
+    fn get(&self, key: i64) -> Result<bool, String> {
+        Ok(match self {
+            Tree::BS(tree) => tree.get(&to_i32(key)?).is_some(),
+            Tree::B(tree) => tree.get(&to_u32(key)?).is_some(),
+            Tree::BPlus(tree) => tree.get(&to_u32(key)?).is_some(),
+        })
+    }
+
+    // Smallest and largest key the tree can hold.
+    fn bounds(&self) -> (i64, i64) {
+        match self {
+            Tree::BS(_) => (i32::MIN.into(), i32::MAX.into()),
+            _ => (0, u32::MAX.into()),
+        }
+    }
+
+    // Keys in between from and to, both included. The bounds are clamped to
+    // the keys the tree can hold first.
+    fn range(&self, from: i64, to: i64) -> Vec<i64> {
+        let (min, max) = self.bounds();
+        let (from, to) = (from.max(min), to.min(max));
+        if from > to {
+            return vec![];
+        }
+        match self {
+            Tree::BS(tree) => tree
+                .range(from as i32..=to as i32)
+                .map(|&key| key as i64)
+                .collect(),
+            Tree::B(tree) => tree
+                .range(from as u32..=to as u32)
+                .map(|&key| key as i64)
+                .collect(),
+            Tree::BPlus(tree) => tree
+                .range(from as u32..=to as u32)
+                .map(|(&key, _)| key as i64)
+                .collect(),
+        }
+    }
+
+    fn show(&self) -> String {
+        match self {
+            Tree::BS(tree) => tree.to_string(),
+            Tree::B(tree) => tree.to_string(),
+            Tree::BPlus(tree) => tree.to_string(),
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        match self {
+            Tree::BS(tree) => tree.stats(),
+            Tree::B(tree) => tree.stats(),
+            Tree::BPlus(tree) => tree.stats(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Tree::BS(tree) => tree.validate(),
+            Tree::B(tree) => tree.validate(),
+            Tree::BPlus(tree) => tree.validate(),
+        }
+    }
+
+    fn write_to<W: Write>(&self, writer: W) -> Result<(), Error> {
+        match self {
+            Tree::BS(tree) => tree.write_to(writer),
+            Tree::B(tree) => tree.write_to(writer),
+            Tree::BPlus(tree) => tree.write_to(writer),
+        }
+    }
+
+    // The kind of tree is in the header, so try each of them in turn.
+    fn read_from(bytes: &[u8]) -> Result<Tree, Error> {
+        match BSTree::read_from(bytes) {
+            Err(Error::UnexpectedKind(_)) => (),
+            result => return result.map(Tree::BS),
+        }
+        match BTree::read_from(bytes) {
+            Err(Error::UnexpectedKind(_)) => (),
+            result => return result.map(Tree::B),
+        }
+        BPlusTree::read_from(bytes).map(Tree::BPlus)
+    }
+}
+
+fn to_i32(key: i64) -> Result<i32, String> {
+    i32::try_from(key).map_err(|_| format!("{key} doesn't fit in a bst key"))
+}
+
+fn to_u32(key: i64) -> Result<u32, String> {
+    u32::try_from(key).map_err(|_| format!("{key} doesn't fit in a u32 key"))
+}
+
+fn parse_key(arg: &str) -> Result<i64, String> {
+    arg.parse().map_err(|_| format!("invalid key {arg:?}"))
+}
+
+// Most keys a single insert or delete expands its ranges to.
+const MAX_KEYS: usize = 1_000_000;
+
+// Keys separated by spaces, where each one can also be a range `a..b` or
+// `a..=b`. Every key is checked against the tree before any is applied, so a
+// command changes the tree entirely or not at all: a key the tree can't hold
+// is an error, and ranges are clamped to the keys it can hold, like `range`.
+fn parse_keys(args: &[&str], tree: &Tree) -> Result<Vec<i64>, String> {
+    let (min, max) = tree.bounds();
+    let mut keys = vec![];
+    for arg in args {
+        let (from, to) = if let Some((from, to)) = arg.split_once("..=") {
+            (parse_key(from)?.max(min), parse_key(to)?.min(max))
+        } else if let Some((from, to)) = arg.split_once("..") {
+            (
+                parse_key(from)?.max(min),
+                parse_key(to)?.saturating_sub(1).min(max),
+            )
+        } else {
+            let key = parse_key(arg)?;
+            if key < min || key > max {
+                return Err(format!("{key} doesn't fit in a {} key", tree.name()));
+            }
+            (key, key)
+        };
+
+        if from <= to {
+            if (to - from) as usize >= MAX_KEYS - keys.len() {
+                return Err(format!("more than {MAX_KEYS} keys at once"));
+            }
+            keys.extend(from..=to);
+        }
+    }
+
+    if keys.is_empty() {
+        return Err("expected at least one key".to_string());
+    }
+    Ok(keys)
+}
+
+struct Session {
+    tree: Tree,
+    history: Vec<Vec<u8>>, // The tree before each change, for undo.
+}
+
+impl Session {
+    fn new() -> Self {
+        Session {
+            tree: Tree::BPlus(BPlusTree::with_max_degree(DEFAULT_MAX_DEGREE)),
+            history: vec![],
+        }
+    }
+
+    fn save_history(&mut self) -> Result<(), String> {
+        let mut bytes = vec![];
+        self.tree.write_to(&mut bytes).map_err(|e| e.to_string())?;
+        self.history.push(bytes);
+        Ok(())
+    }
+
+    // Run one command, returning what to print.
+    fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Ok(String::new()),
+        };
+
+        match (command, args) {
+            ("use", [kind, args @ ..]) => {
+                let tree = match (*kind, args) {
+                    ("bst", []) => Tree::BS(BSTree::new()),
+                    ("btree", []) => Tree::B(BTree::new()),
+                    ("bplus", []) => Tree::BPlus(BPlusTree::with_max_degree(DEFAULT_MAX_DEGREE)),
+                    ("bplus", [degree]) => match degree.parse() {
+                        Ok(degree) if degree >= 3 => {
+                            Tree::BPlus(BPlusTree::with_max_degree(degree))
+                        }
+                        _ => return Err(format!("invalid max degree {degree:?}, at least 3")),
+                    },
+                    _ => return Err("usage: use bst|btree|bplus [max degree]".to_string()),
+                };
+                self.save_history()?;
+                self.tree = tree;
+                Ok(format!("using an empty {}", self.tree.name()))
+            }
+            ("insert", args) => {
+                let keys = parse_keys(args, &self.tree)?;
+                self.save_history()?;
+                for &key in &keys {
+                    self.tree.insert(key)?;
+                }
+                Ok(format!("inserted {} keys", keys.len()))
+            }
+            ("delete" | "remove", args) => {
+                let keys = parse_keys(args, &self.tree)?;
+                self.save_history()?;
+                let mut deleted = 0;
+                for &key in &keys {
+                    deleted += self.tree.delete(key)? as usize;
+                }
+                Ok(format!("deleted {deleted} keys"))
+            }
+            ("get", [key]) => match self.tree.get(parse_key(key)?)? {
+                true => Ok(format!("found {key}")),
+                false => Ok(format!("{key} not found")),
+            },
+            ("range", [from, to]) => {
+                let (from, to) = (parse_key(from)?, parse_key(to)?);
+                let keys: Vec<String> = self
+                    .tree
+                    .range(from, to)
+                    .into_iter()
+                    .map(|key| key.to_string())
+                    .collect();
+                Ok(format!("[{}]", keys.join(", ")))
+            }
+            ("show", []) => match self.tree.show() {
+                tree if tree.is_empty() => Ok(format!("empty {}", self.tree.name())),
+                tree => Ok(tree.trim_end().to_string()),
+            },
+            ("validate", []) => match self.tree.validate() {
+                Ok(()) => Ok("ok".to_string()),
+                Err(error) => Err(format!("invalid tree: {error}")),
+            },
+            ("stats", []) => Ok(self.tree.stats().to_string().trim_end().to_string()),
+            ("undo", []) => match self.history.pop() {
+                Some(bytes) => {
+                    self.tree = Tree::read_from(&bytes).map_err(|e| e.to_string())?;
+                    Ok("undone".to_string())
+                }
+                None => Err("nothing to undo".to_string()),
+            },
+            ("save", [path]) => {
+                let file = File::create(path).map_err(|e| e.to_string())?;
+                let mut writer = BufWriter::new(file);
+                self.tree.write_to(&mut writer).map_err(|e| e.to_string())?;
+                writer.flush().map_err(|e| e.to_string())?;
+                Ok(format!("saved {} to {path}", self.tree.name()))
+            }
+            ("load", [path]) => {
+                let mut bytes = vec![];
+                File::open(path)
+                    .and_then(|mut file| io::Read::read_to_end(&mut file, &mut bytes))
+                    .map_err(|e| e.to_string())?;
+                let tree = Tree::read_from(&bytes).map_err(|e| e.to_string())?;
+
+                self.save_history()?;
+                self.tree = tree;
+                Ok(format!("loaded {} from {path}", self.tree.name()))
+            }
+            ("help", []) => Ok(HELP.to_string()),
+            _ => Err(format!(
+                "invalid command {line:?}, type help for every command"
+            )),
+        }
+    }
+}
+
+fn main() {
+    let mut session = Session::new();
+    let stdin = BufReader::new(io::stdin());
+    let mut stdout = io::stdout();
+
+    print!("> ");
+    let _ = stdout.flush();
+
+    for line in stdin.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => {
+                eprintln!("error: {error}");
+                break;
+            }
+        };
+
+        if matches!(line.trim(), "quit" | "exit") {
+            break;
+        }
+
+        match session.execute(&line) {
+            Ok(output) if output.is_empty() => (),
+            Ok(output) => println!("{output}"),
+            Err(error) => println!("error: {error}"),
+        }
+
+        print!("> ");
+        let _ = stdout.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Session;
+
+    fn run(session: &mut Session, line: &str) -> String {
+        session
+            .execute(line)
+            .unwrap_or_else(|error| format!("error: {error}"))
+    }
+
+    #[test]
+    fn explore_a_bplustree() {
+        let mut session = Session::new();
+
+        assert_eq!(run(&mut session, "use bplus 4"), "using an empty bplus");
+        assert_eq!(run(&mut session, "insert 1..20"), "inserted 19 keys");
+        assert_eq!(run(&mut session, "delete 7 100"), "deleted 1 keys");
+        assert_eq!(run(&mut session, "get 5"), "found 5");
+        assert_eq!(run(&mut session, "get 7"), "7 not found");
+        assert_eq!(run(&mut session, "range 3 9"), "[3, 4, 5, 6, 8, 9]");
+        assert_eq!(run(&mut session, "validate"), "ok");
+        assert!(run(&mut session, "stats").starts_with("height "));
+        assert!(run(&mut session, "show").starts_with("   "));
+    }
+
+    #[test]
+    fn undo_every_change() {
+        let mut session = Session::new();
+
+        run(&mut session, "use btree");
+        run(&mut session, "insert 1..=5");
+        run(&mut session, "delete 2");
+        assert_eq!(run(&mut session, "range 0 10"), "[1, 3, 4, 5]");
+
+        assert_eq!(run(&mut session, "undo"), "undone");
+        assert_eq!(run(&mut session, "range 0 10"), "[1, 2, 3, 4, 5]");
+        run(&mut session, "undo");
+        assert_eq!(run(&mut session, "show"), "empty btree");
+        run(&mut session, "undo");
+        assert_eq!(run(&mut session, "show"), "empty bplus");
+        assert_eq!(run(&mut session, "undo"), "error: nothing to undo");
+    }
+
+    #[test]
+    fn check_every_key_first() {
+        let mut session = Session::new();
+
+        run(&mut session, "use btree");
+        run(&mut session, "insert 1..=3");
+        assert_eq!(
+            run(&mut session, "insert 5 -1"),
+            "error: -1 doesn't fit in a btree key"
+        );
+        assert_eq!(
+            run(&mut session, "delete 1 4294967296"),
+            "error: 4294967296 doesn't fit in a btree key"
+        );
+        assert_eq!(run(&mut session, "range 0 10"), "[1, 2, 3]");
+
+        // Ranges are clamped to the keys of the tree.
+        assert_eq!(run(&mut session, "insert -5..=5"), "inserted 6 keys");
+        assert_eq!(run(&mut session, "range 0 10"), "[0, 1, 2, 3, 4, 5]");
+        assert_eq!(
+            run(&mut session, "delete 4294967290..9999999999"),
+            "deleted 0 keys"
+        );
+
+        // Rejected commands aren't in the history.
+        assert_eq!(run(&mut session, "undo"), "undone");
+        assert_eq!(run(&mut session, "undo"), "undone");
+        assert_eq!(run(&mut session, "range 0 10"), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("trees-repl-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut session = Session::new();
+
+        run(&mut session, "use bst");
+        run(&mut session, "insert -3 8 1");
+        assert_eq!(
+            run(&mut session, &format!("save {path}")),
+            format!("saved bst to {path}")
+        );
+
+        let mut session = Session::new();
+        assert_eq!(
+            run(&mut session, &format!("load {path}")),
+            format!("loaded bst from {path}")
+        );
+        assert_eq!(run(&mut session, "range -10 10"), "[-3, 1, 8]");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reject_invalid_commands() {
+        let mut session = Session::new();
+
+        assert!(run(&mut session, "insert").starts_with("error"));
+        assert!(run(&mut session, "insert -1").starts_with("error"));
+        assert!(run(&mut session, "insert x").starts_with("error"));
+        assert!(run(&mut session, "insert 0..9999999999").starts_with("error"));
+        assert!(run(&mut session, "use bplus 2").starts_with("error"));
+        assert!(run(&mut session, "frobnicate").starts_with("error"));
+        assert_eq!(run(&mut session, ""), "");
+    }
+}
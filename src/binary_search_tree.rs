@@ -1,3 +1,15 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::io::{Read, Write};
+use std::ops::RangeBounds;
+
+use crate::comparator::{Comparator, Natural};
+use crate::dot;
+use crate::range::Ends;
+use crate::render::{Block, Rendered};
+use crate::serialization::{self, Codec, Kind, NO_VALUE};
+use crate::stats::Stats;
+
 type Link = Option<Box<Node>>;
 
 #[derive(Clone)]
@@ -7,27 +19,148 @@ struct Node {
     right: Link,
 }
 
-pub struct BSTree {
+// The values are ordered by the comparator, see `src/comparator.rs`.
+pub struct BSTree<C = Natural> {
     root: Link,
-    size: u32,
+    comparator: C,
 }
 
+// Don't trust the number of nodes in the header to preallocate them all.
+const MAX_PREALLOCATED: usize = 4096;
+
 impl Node {
-    pub fn print(&self) {
-        if let Some(left) = self.left.as_ref() {
-            left.print();
+    // Node records in pre-order: a flag for each child that follows, then the
+    // value. Written with a stack, as the tree can be as deep as it has nodes.
+    fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            let flags = node.left.is_some() as u8 | (node.right.is_some() as u8) << 1;
+            serialization::write_u8(writer, flags)?;
+            node.val.encode(writer)?;
+
+            // The left subtree is written first, so it goes on top.
+            stack.extend(node.right.as_deref());
+            stack.extend(node.left.as_deref());
         }
+        Ok(())
+    }
 
-        print!("{} ", self.val);
+    // Read the records until no child is left to read, then build the tree
+    // from the last record back: a node comes right before its left subtree,
+    // then its right subtree, so both are on top of the stack of subtrees
+    // built so far when the node is reached:
+    //
+    //   records   5 (left, right)   2   8
+    //
+    //   8  ->  [8]
+    //   2  ->  [8, 2]
+    //   5  ->  [5]      2 on its left, 8 on its right
+    //
+    // The header has the number of nodes, which must be the number of
+    // records.
+    fn read_from<R: Read>(reader: &mut R, size: u32) -> Result<Box<Self>, serialization::Error> {
+        let mut records = Vec::with_capacity((size as usize).min(MAX_PREALLOCATED));
+        let mut to_read = 1;
+        while to_read > 0 {
+            if records.len() == size as usize {
+                return Err(serialization::Error::Corrupted("invalid number of nodes"));
+            }
+            let flags = serialization::read_u8(reader)?;
+            if flags > 0b11 {
+                return Err(serialization::Error::Corrupted("invalid node flags"));
+            }
+            records.push((flags, i32::decode(reader)?));
+            to_read += flags.count_ones() as usize;
+            to_read -= 1;
+        }
+        if records.len() != size as usize {
+            return Err(serialization::Error::Corrupted("invalid number of nodes"));
+        }
 
-        if let Some(right) = self.right.as_ref() {
-            right.print();
+        let mut subtrees: Vec<Box<Node>> = vec![];
+        for (flags, val) in records.into_iter().rev() {
+            let mut node = Box::new(Node {
+                val,
+                left: None,
+                right: None,
+            });
+            // Always there, the records were read until every child was.
+            if flags & 0b01 != 0 {
+                node.left = subtrees.pop();
+            }
+            if flags & 0b10 != 0 {
+                node.right = subtrees.pop();
+            }
+            subtrees.push(node);
         }
+        Ok(subtrees.pop().unwrap())
+    }
+
+    // Record: left port, value, right port. Returns the id of the node.
+    fn write_dot<W: fmt::Write>(
+        &self,
+        dot: &mut W,
+        next_id: &mut usize,
+    ) -> Result<usize, fmt::Error> {
+        let id = *next_id;
+        *next_id += 1;
+        writeln!(dot, "  node{id} [label=\"<l>|{}|<r>\"];", self.val)?;
+
+        for (port, child) in [("l", &self.left), ("r", &self.right)] {
+            if let Some(child) = child {
+                let child_id = child.write_dot(dot, next_id)?;
+                writeln!(dot, "  node{id}:{port} -> node{child_id};")?;
+            }
+        }
+        Ok(id)
+    }
+
+    // Check that every value is greater than `min` and at most `max`, equal
+    // values being on the left.
+    fn validate<C: Comparator<i32>>(
+        &self,
+        comparator: &C,
+        min: Option<i32>,
+        max: Option<i32>,
+    ) -> Result<(), String> {
+        if min.is_some_and(|min| comparator.compare(&self.val, &min).is_le())
+            || max.is_some_and(|max| comparator.compare(&self.val, &max).is_gt())
+        {
+            return Err(format!(
+                "{} is not in between {min:?} and {max:?}",
+                self.val
+            ));
+        }
+
+        if let Some(left) = &self.left {
+            left.validate(comparator, min, Some(self.val))?;
+        }
+        if let Some(right) = &self.right {
+            right.validate(comparator, Some(self.val), max)?;
+        }
+        Ok(())
+    }
+
+    // A missing child is drawn as a dot when the other child exists, to
+    // tell left and right apart.
+    fn block<F: Fn(&i32) -> String>(&self, format_value: &F) -> Block {
+        let childrens = match (&self.left, &self.right) {
+            (None, None) => vec![],
+            (left, right) => [left, right]
+                .map(|child| match child {
+                    Some(child) => child.block(format_value),
+                    None => Block::leaf("·".to_string()),
+                })
+                .into(),
+        };
+
+        Block::node(format_value(&self.val), childrens)
     }
 }
 
-fn remove(mut this: Box<Node>, val: i32) -> Option<Box<Node>> {
-    if this.val == val {
+fn remove<C: Comparator<i32>>(mut this: Box<Node>, val: i32, comparator: &C) -> Option<Box<Node>> {
+    let ordering = comparator.compare(&val, &this.val);
+    if ordering == Ordering::Equal {
         match (this.right.take(), this.left.take()) {
             (None, None) => None,
             (Some(right), Some(mut left)) => {
@@ -70,26 +203,184 @@ fn remove(mut this: Box<Node>, val: i32) -> Option<Box<Node>> {
             (Some(right), None) => Some(right),
             (None, Some(left)) => Some(left),
         }
-    } else if val > this.val {
+    } else if ordering == Ordering::Greater {
         if let Some(node) = this.right.take() {
-            this.right = remove(node, val);
+            this.right = remove(node, val, comparator);
         }
 
         Some(this)
     } else {
         if let Some(node) = this.left.take() {
-            this.left = remove(node, val);
+            this.left = remove(node, val, comparator);
         }
 
         Some(this)
     }
 }
 
+// Build a balanced tree from sorted values, using the middle value as the
+// root. Equal values are kept on the left, the same as `insert` does.
+fn build<C: Comparator<i32>>(values: &[i32], comparator: &C) -> Link {
+    if values.is_empty() {
+        return None;
+    }
+
+    let mut middle = values.len() / 2;
+    while middle + 1 < values.len()
+        && comparator
+            .compare(&values[middle + 1], &values[middle])
+            .is_eq()
+    {
+        middle += 1;
+    }
+
+    Some(Box::new(Node {
+        val: values[middle],
+        left: build(&values[..middle], comparator),
+        right: build(&values[middle + 1..], comparator),
+    }))
+}
+
+// In order iterator, going down the left links first.
+pub struct Iter<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iter<'a> {
+    fn push_left(&mut self, mut link: Option<&'a Node>) {
+        while let Some(node) = link {
+            self.stack.push(node);
+            link = node.left.as_deref();
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a i32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left(node.right.as_deref());
+        Some(&node.val)
+    }
+}
+
+// Values in a range, in order from both ends, see `src/range.rs`. The back
+// goes down the right links first.
+pub struct Range<'a, C> {
+    front: Iter<'a>,
+    back: Vec<&'a Node>,
+    comparator: &'a C,
+    ends: Ends<'a, i32>,
+}
+
+impl<'a, C: Comparator<i32>> Iterator for Range<'a, C> {
+    type Item = &'a i32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ends.is_done() {
+            return None;
+        }
+        let val = self.front.next();
+        self.ends.front(self.comparator, val).then(|| val.unwrap())
+    }
+}
+
+impl<'a, C: Comparator<i32>> DoubleEndedIterator for Range<'a, C> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.ends.is_done() {
+            return None;
+        }
+        let node = self.back.pop();
+        if let Some(node) = node {
+            push_right(&mut self.back, node.left.as_deref());
+        }
+        let val = node.map(|node| &node.val);
+        self.ends.back(self.comparator, val).then(|| val.unwrap())
+    }
+}
+
+fn push_right<'a>(stack: &mut Vec<&'a Node>, mut link: Option<&'a Node>) {
+    while let Some(node) = link {
+        stack.push(node);
+        link = node.right.as_deref();
+    }
+}
+
+impl<C: Comparator<i32>> fmt::Display for BSTree<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render_with(i32::to_string))
+    }
+}
+
+impl Default for BSTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Serialized trees are read back with the `Ord` order of the values, so only
+// those are written.
+// Drop the nodes one by one, dropping the root would drop its childrens
+// recursively, as deep as the tree is.
+impl<C> Drop for BSTree<C> {
+    fn drop(&mut self) {
+        let mut stack: Vec<Box<Node>> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+    }
+}
+
 impl BSTree {
     pub fn new() -> BSTree {
+        BSTree::with_comparator(Natural)
+    }
+
+    pub fn bulk_load(values: Vec<i32>) -> Self {
+        BSTree::bulk_load_with(values, Natural)
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), serialization::Error> {
+        serialization::write_header(&mut writer, Kind::BSTree, 0, i32::ID, NO_VALUE)?;
+        (self.node_count() as u32).encode(&mut writer)?;
+
+        match &self.root {
+            Some(node) => {
+                serialization::write_u8(&mut writer, 1)?;
+                node.write_to(&mut writer)?;
+            }
+            None => serialization::write_u8(&mut writer, 0)?,
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, serialization::Error> {
+        serialization::read_header(&mut reader, Kind::BSTree, i32::ID, NO_VALUE)?;
+        let size = u32::decode(&mut reader)?;
+
+        let root = match serialization::read_u8(&mut reader)? {
+            0 => None,
+            1 => Some(Node::read_from(&mut reader, size)?),
+            _ => return Err(serialization::Error::Corrupted("invalid root flag")),
+        };
+        if root.is_none() && size != 0 {
+            return Err(serialization::Error::Corrupted("invalid number of nodes"));
+        }
+        Ok(BSTree {
+            root,
+            comparator: Natural,
+        })
+    }
+}
+
+impl<C: Comparator<i32>> BSTree<C> {
+    // Tree with the values ordered by `comparator` instead of their `Ord`.
+    pub fn with_comparator(comparator: C) -> Self {
         BSTree {
             root: None,
-            size: 0,
+            comparator,
         }
     }
 
@@ -113,10 +404,9 @@ impl BSTree {
         // Find the right node to insert in,
         // either left or right.
         while let Some(n) = temp {
-            if val > n.val {
+            if self.comparator.compare(&val, &n.val).is_gt() {
                 if n.right.is_none() {
                     n.right = Some(Box::new(node));
-                    self.size += 1;
                     break;
                 } else {
                     temp = n.right.as_mut();
@@ -124,7 +414,6 @@ impl BSTree {
             } else {
                 if n.left.is_none() {
                     n.left = Some(Box::new(node));
-                    self.size += 1;
                     break;
                 } else {
                     temp = n.left.as_mut();
@@ -137,7 +426,7 @@ impl BSTree {
 
     pub fn remove(&mut self, val: i32) {
         if let Some(node) = self.root.take() {
-            self.root = remove(node, val);
+            self.root = remove(node, val, &self.comparator);
         }
     }
 
@@ -145,25 +434,134 @@ impl BSTree {
         let mut node = self.root.as_ref();
 
         while let Some(n) = node {
-            if &n.val == val {
-                return Some(&n.val);
+            match self.comparator.compare(val, &n.val) {
+                Ordering::Equal => return Some(&n.val),
+                Ordering::Less => node = n.left.as_ref(),
+                Ordering::Greater => node = n.right.as_ref(),
             }
+        }
+
+        None
+    }
 
-            if &n.val > val {
-                node = n.left.as_ref();
+    // Values in the range, which can also be iterated backward with `rev()`.
+    // Going down to the first and the last value in the range skips the
+    // subtrees outside of it: a node before the start only has values before
+    // the start on its left.
+    pub fn range<R: RangeBounds<i32>>(&self, range: R) -> Range<'_, C> {
+        let ends = Ends::new(&range);
+        let mut front = Iter { stack: vec![] };
+        let mut back = vec![];
+
+        let mut link = self.root.as_deref();
+        while let Some(node) = link {
+            if ends.before_start(&self.comparator, &node.val) {
+                link = node.right.as_deref();
             } else {
-                node = n.right.as_ref();
+                front.stack.push(node);
+                link = node.left.as_deref();
             }
         }
 
-        None
+        let mut link = self.root.as_deref();
+        while let Some(node) = link {
+            if ends.after_end(&self.comparator, &node.val) {
+                link = node.left.as_deref();
+            } else {
+                back.push(node);
+                link = node.right.as_deref();
+            }
+        }
+
+        Range {
+            front,
+            back,
+            comparator: &self.comparator,
+            ends,
+        }
     }
 
-    pub fn print(&self) {
-        if let Some(node) = &self.root {
-            node.print();
-            println!("");
+    pub fn iter(&self) -> Iter<'_> {
+        let mut iter = Iter { stack: vec![] };
+        iter.push_left(self.root.as_deref());
+        iter
+    }
+
+    // Build a balanced tree instead of inserting the values one by one, which
+    // would end up as a linked list when the values are already sorted.
+    pub fn bulk_load_with(mut values: Vec<i32>, comparator: C) -> Self {
+        values.sort_unstable_by(|a, b| comparator.compare(a, b));
+
+        BSTree {
+            root: build(&values, &comparator),
+            comparator,
+        }
+    }
+
+    // Number of levels, 0 for an empty tree. Walk the tree with a stack, as
+    // it can be as deep as it has nodes.
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut stack: Vec<(&Node, usize)> =
+            self.root.iter().map(|node| (node.as_ref(), 1)).collect();
+
+        while let Some((node, depth)) = stack.pop() {
+            height = height.max(depth);
+            for child in [&node.left, &node.right].into_iter().flatten() {
+                stack.push((child, depth + 1));
+            }
+        }
+        height
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.iter().count()
+    }
+
+    // Every node holds a single value, in its own allocation.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            height: self.height(),
+            ..Stats::default()
+        };
+
+        let mut stack: Vec<&Node> = self.root.iter().map(|node| node.as_ref()).collect();
+        while let Some(node) = stack.pop() {
+            let is_leaf = node.left.is_none() && node.right.is_none();
+            stats.add_node(1, 1, is_leaf);
+            stats.heap_bytes.nodes += std::mem::size_of::<Node>();
+            stack.extend(
+                [&node.left, &node.right]
+                    .into_iter()
+                    .flatten()
+                    .map(|node| node.as_ref()),
+            );
         }
+        stats
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.root {
+            Some(node) => node.validate(&self.comparator, None, None),
+            None => Ok(()),
+        }
+    }
+
+    // Draw the tree, formatting each value with `format_value`.
+    pub fn render_with<F: Fn(&i32) -> String>(&self, format_value: F) -> Rendered {
+        Rendered::from(self.root.as_ref().map(|node| node.block(&format_value)))
+    }
+
+    pub fn print(&self) {
+        print!("{self}");
+    }
+
+    // Graphviz graph of the tree, see `src/dot.rs`.
+    pub fn to_dot(&self) -> String {
+        dot::render("BSTree", |dot| match &self.root {
+            Some(node) => node.write_dot(dot, &mut 0).map(|_| ()),
+            None => Ok(()),
+        })
     }
 }
 
@@ -195,10 +593,228 @@ mod test {
         assert_eq!(tree.get(&7), None);
     }
 
+    #[test]
+    fn bulk_load_balanced_tree() {
+        fn height(link: &super::Link) -> usize {
+            link.as_ref()
+                .map_or(0, |node| 1 + height(&node.left).max(height(&node.right)))
+        }
+
+        let tree = BSTree::bulk_load((0..1000).collect());
+        assert_eq!(height(&tree.root), 10);
+        assert_eq!(tree.height(), 10);
+        assert_eq!(tree.node_count(), 1000);
+        assert!(tree.iter().copied().eq(0..1000));
+
+        let tree = BSTree::bulk_load(vec![3, 1, 3, 2, 3]);
+        assert_eq!(
+            tree.iter().copied().collect::<Vec<_>>(),
+            vec![1, 2, 3, 3, 3]
+        );
+        assert_eq!(tree.get(&3), Some(&3));
+    }
+
+    #[test]
+    fn stats() {
+        let mut tree = BSTree::new();
+        assert_eq!(tree.stats().nodes(), 0);
+
+        for i in [4, 2, 6, 5] {
+            tree.insert(i);
+        }
+        let stats = tree.stats();
+        assert_eq!(
+            (stats.height, stats.internal_nodes, stats.leaf_nodes),
+            (3, 2, 2)
+        );
+        assert_eq!((stats.keys, stats.min_keys, stats.max_keys), (4, 1, 1));
+        assert_eq!(stats.fill_factors[9], 4);
+        assert_eq!(
+            stats.heap_bytes.total(),
+            4 * std::mem::size_of::<super::Node>()
+        );
+    }
+
+    #[test]
+    fn height_of_a_list() {
+        let mut tree = BSTree::new();
+        assert_eq!(tree.height(), 0);
+        for i in 0..5_000 {
+            tree.insert(i);
+        }
+        assert_eq!(tree.height(), 5_000);
+    }
+
+    #[test]
+    fn validate() {
+        let mut tree = BSTree::new();
+        for i in [4, 2, 6, 2, 5] {
+            tree.insert(i);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+
+        tree.root.as_mut().unwrap().left.as_mut().unwrap().val = 7;
+        assert!(tree.validate().is_err());
+    }
+
+    #[test]
+    fn reverse_order() {
+        let mut tree = BSTree::with_comparator(|a: &i32, b: &i32| b.cmp(a));
+        for i in [4, 2, 6, 1, 5, 7] {
+            tree.insert(i);
+        }
+        tree.remove(6);
+
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.iter().copied().collect::<Vec<_>>(), [7, 5, 4, 2, 1]);
+        assert_eq!(tree.get(&5), Some(&5));
+        assert_eq!(tree.get(&6), None);
+    }
+
+    #[test]
+    fn display() {
+        let mut tree = BSTree::new();
+        for i in [4, 2, 6, 1, 5, 7] {
+            tree.insert(i);
+        }
+
+        assert_eq!(
+            tree.to_string(),
+            "      4
+  ┌───┴───┐
+  2       6
+┌─┴─┐   ┌─┴─┐
+1   ·   5   7
+"
+        );
+        assert_eq!(
+            tree.render_with(|value| format!("<{value}>"))
+                .to_string()
+                .lines()
+                .next(),
+            Some("       <4>")
+        );
+    }
+
+    #[test]
+    fn to_dot() {
+        let mut tree = BSTree::new();
+        for i in [2, 1, 3] {
+            tree.insert(i);
+        }
+
+        let dot = tree.to_dot();
+        assert!(dot.starts_with("digraph BSTree {\n"));
+        assert!(dot.contains("  node0 [label=\"<l>|2|<r>\"];\n"));
+        assert!(dot.contains("  node0:l -> node1;\n"));
+        assert!(dot.contains("  node0:r -> node2;\n"));
+        assert!(BSTree::new().to_dot().ends_with("height=0.1];\n}\n"));
+    }
+
+    #[test]
+    fn write_and_read_the_same_shape() {
+        let mut tree = BSTree::new();
+        for i in [5, 2, 8, 1, 4, 7, 9, 3, 6] {
+            tree.insert(i);
+        }
+        tree.remove(2);
+
+        let mut bytes = vec![];
+        tree.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes, include_bytes!("../fixtures/bstree_v1.bin"));
+
+        let tree = BSTree::read_from(bytes.as_slice()).unwrap();
+        let mut again = vec![];
+        tree.write_to(&mut again).unwrap();
+        assert_eq!(bytes, again);
+        assert_eq!(tree.get(&4), Some(&4));
+        assert_eq!(tree.get(&2), None);
+    }
+
+    #[test]
+    fn write_and_read_a_list() {
+        use crate::serialization::{self, Codec, Kind, NO_VALUE};
+
+        // Sorted values inserted one by one, each node the right child of
+        // the one before. Too deep to read or drop recursively.
+        let nodes = 1_000_000;
+        let mut bytes = vec![];
+        serialization::write_header(&mut bytes, Kind::BSTree, 0, i32::ID, NO_VALUE).unwrap();
+        (nodes as u32).encode(&mut bytes).unwrap();
+        bytes.push(1);
+        for i in 0..nodes {
+            bytes.push(if i + 1 < nodes { 0b10 } else { 0 });
+            i.encode(&mut bytes).unwrap();
+        }
+
+        let tree = BSTree::read_from(bytes.as_slice()).unwrap();
+        assert_eq!(tree.height(), nodes as usize);
+        assert_eq!(tree.get(&(nodes - 1)), Some(&(nodes - 1)));
+        let mut again = vec![];
+        tree.write_to(&mut again).unwrap();
+        assert_eq!(bytes, again);
+
+        // The number of nodes in the header must be the number of records.
+        for size in [0, 1, nodes as u32 - 1, nodes as u32 + 1] {
+            bytes[12..16].copy_from_slice(&size.to_le_bytes());
+            assert!(matches!(
+                BSTree::read_from(bytes.as_slice()),
+                Err(serialization::Error::Corrupted("invalid number of nodes"))
+            ));
+        }
+    }
+
     // tree.insert(8);
     // tree.insert(9);
     // tree.insert(4);
     // tree.insert(10);
     // tree.insert(3);
     // tree.insert(5);
+
+    #[test]
+    fn range_with_every_bound() {
+        use rand::rngs::StdRng;
+        use rand::seq::SliceRandom;
+        use rand::{Rng, SeedableRng};
+        use std::ops::Bound::{Excluded, Included, Unbounded};
+        use std::ops::RangeBounds;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut keys: Vec<i32> = (0..300).step_by(3).collect();
+        keys.shuffle(&mut rng);
+        let mut tree = BSTree::new();
+        for &key in &keys {
+            tree.insert(key);
+        }
+        keys.sort();
+
+        let bound = |rng: &mut StdRng| match rng.gen_range(0..3) {
+            0 => Included(rng.gen_range(-5..305)),
+            1 => Excluded(rng.gen_range(-5..305)),
+            _ => Unbounded,
+        };
+        for _ in 0..1000 {
+            let range = (bound(&mut rng), bound(&mut rng));
+            let expected: Vec<_> = keys.iter().filter(|key| range.contains(*key)).collect();
+            assert_eq!(tree.range(range).collect::<Vec<_>>(), expected, "{range:?}");
+            assert!(tree.range(range).rev().eq(expected.iter().rev().copied()));
+
+            // Both ends meet in the middle.
+            let mut iter = tree.range(range);
+            let (mut front, mut back) = (vec![], vec![]);
+            loop {
+                let (end, key) = match rng.gen() {
+                    true => (&mut front, iter.next()),
+                    false => (&mut back, iter.next_back()),
+                };
+                match key {
+                    Some(key) => end.push(key),
+                    None => break,
+                }
+            }
+            front.extend(back.into_iter().rev());
+            assert_eq!(front, expected, "{range:?}");
+            assert_eq!((iter.next(), iter.next_back()), (None, None));
+        }
+    }
 }
@@ -1,460 +1,1104 @@
-use std::collections::VecDeque;
-
-const DEBUG: bool = false;
-pub struct BPlusTree {
-    root: Option<Node>,
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Display};
+use std::io::{Read, Write};
+use std::ops::RangeBounds;
+
+use crate::arena::{Arena, NodeId};
+use crate::comparator::{Comparator, Natural};
+use crate::composite::Prefix;
+use crate::dot;
+use crate::range::Ends;
+use crate::render::{Block, Rendered};
+use crate::serialization::{self, Codec, Kind};
+use crate::stats::{Counters, HeapBytes, Stats};
+use crate::summary::Summary;
+
+// Used when the max degree isn't known, e.g. when deserializing with serde.
+pub const DEFAULT_MAX_DEGREE: usize = 4;
+
+// The nodes are stored in an arena, see `src/arena.rs`, and reference their
+// childrens by id. Each leaf also has the id of the next one, so iterating
+// only walks the leaves:
+//
+//            [3, 5]
+//           /   |   \
+//   [1, 2] -> [3, 4] -> [5, 6, 7]
+//
+// The keys are ordered by the comparator, their `Ord` order by default, see
+// `src/comparator.rs`. The internal nodes also keep a summary of the subtree
+// of each child, none by default, see `src/summary.rs`.
+pub struct BPlusTree<K = u32, V = K, C = Natural, S = ()> {
+    nodes: Arena<Node<K, V, S>>,
+    root: Option<NodeId>,
     max_degree: usize,
+    counters: Counters,
+    comparator: C,
 }
 
-struct Node {
-    keys: Vec<u32>,       // At least t - 1 keys, at most 2t - 1 keys
-    values: Vec<u32>,     // Only in leaf node.
-    childrens: Vec<Node>, // At least t children, at most 2t children
+struct Node<K, V, S = ()> {
+    keys: Vec<K>,           // At least t - 1 keys, at most 2t - 1 keys
+    values: Vec<V>,         // Only in leaf node.
+    childrens: Vec<NodeId>, // At least t children, at most 2t children
+    summaries: Vec<S>,      // Only in internal node, one per child.
+    next: Option<NodeId>,   // Only in leaf node, None for the last leaf.
     is_leaf: bool,
 }
 
-impl Node {
+impl<K, V, S> Node<K, V, S> {
     pub fn new(is_leaf: bool) -> Self {
         Node {
             keys: vec![],
             values: vec![],
             childrens: Vec::new(),
+            summaries: Vec::new(),
+            next: None,
             is_leaf,
         }
     }
+}
+
+// Every node but the root has at least this number of keys.
+fn min_keys(max_degree: usize) -> usize {
+    (max_degree / 2 - 1).max(1)
+}
 
-    pub fn insert_non_full(&mut self, key: u32, max_degree: usize) {
-        match self.keys.binary_search(&key) {
+impl<K: Clone, V, C: Comparator<K>, S: Summary<K, V>> BPlusTree<K, V, C, S> {
+    fn insert_non_full(&mut self, id: NodeId, key: K, value: V) {
+        let node = &mut self.nodes[id];
+        let index = match self.comparator.search(&node.keys, &key) {
             // Ignore if key is duplicated first
-            Ok(_index) => (),
-            Err(index) => {
-                if self.is_leaf {
-                    self.keys.insert(index, key);
-                    self.values.insert(index, key);
-                } else {
-                    self.childrens[index].insert_non_full(key, max_degree);
+            Ok(_) if node.is_leaf => return,
+            // The key of a parent is the first key of its right child.
+            Ok(index) => index + 1,
+            Err(index) => index,
+        };
 
-                    if self.childrens[index].keys.len() == max_degree {
-                        self.split_child(index, max_degree);
-                    }
-                }
+        if node.is_leaf {
+            node.keys.insert(index, key);
+            node.values.insert(index, value);
+        } else {
+            let child = node.childrens[index];
+            self.insert_non_full(child, key, value);
+            self.refresh(id, index);
+
+            if self.nodes[child].keys.len() == self.max_degree {
+                self.split_child(id, index);
             }
         }
     }
 
-    pub fn split_child(&mut self, index: usize, max_degree: usize) {
-        if let Some(child) = self.childrens.get_mut(index) {
-            let mut right_node = Node::new(child.is_leaf);
-            let breakpoint = max_degree / 2;
-            let min_number_of_keys = child.keys.len() - breakpoint;
+    // Split the child in two around the breakpoint. A leaf keeps every key,
+    // the first key of the right leaf is copied up, while an internal node
+    // moves its key at the breakpoint up. Only the ids of the childrens move
+    // to the right node, not the subtrees.
+    fn split_child(&mut self, id: NodeId, index: usize) {
+        self.counters.splits += 1;
+        let breakpoint = self.max_degree / 2;
+        let child_id = self.nodes[id].childrens[index];
+        let child = &mut self.nodes[child_id];
+        let mut right_node = Node::new(child.is_leaf);
+
+        let key = if child.is_leaf {
+            right_node.keys = child.keys.split_off(breakpoint);
+            right_node.values = child.values.split_off(breakpoint);
+            right_node.next = child.next;
+            right_node.keys[0].clone()
+        } else {
+            right_node.keys = child.keys.split_off(breakpoint + 1);
+            right_node.childrens = child.childrens.split_off(breakpoint + 1);
+            right_node.summaries = child.summaries.split_off(breakpoint + 1);
+            child.keys.pop().unwrap()
+        };
 
-            // TODO: We probably want to rewrite the following parts
-            // in a more concise a clear way.
-            if index > self.keys.len() {
-                if self.is_leaf {
-                    self.values.push(child.values[breakpoint]);
-                }
-                self.keys.push(child.keys[breakpoint]);
-            } else {
-                // TODO: Add explanation why this is needed
-                if self.is_leaf {
-                    self.values.insert(index, child.values[breakpoint]);
-                }
-                self.keys.insert(index, child.keys[breakpoint]);
-            }
+        let right_id = self.nodes.alloc(right_node);
+        let child = &mut self.nodes[child_id];
+        if child.is_leaf {
+            child.next = Some(right_id);
+        }
 
-            for i in 0..min_number_of_keys {
-                let key = child.keys.remove(breakpoint);
-
-                // If is leaf child, we split all the keys to the right node,
-                // including the key we move to parent.
-                //
-                // If is internal node, there's no need to the breakpoint key
-                // to the right node as it is available at the child level.
-                //
-                // Hence, we will skip the first key when it is not a leaf node.
-                if child.is_leaf || i != 0 {
-                    right_node.keys.push(key);
-                }
+        let node = &mut self.nodes[id];
+        node.keys.insert(index, key);
+        node.childrens.insert(index + 1, right_id);
+        node.summaries.insert(index + 1, S::identity());
+        self.refresh(id, index);
+        self.refresh(id, index + 1);
+    }
 
-                if child.is_leaf {
-                    let value = child.values.remove(breakpoint);
-                    right_node.values.push(value);
-                }
+    // New root above the old one, for it to split.
+    fn new_root(&mut self, root: NodeId) -> NodeId {
+        let mut new_root = Node::new(false);
+        new_root.childrens.push(root);
+        new_root.summaries.push(self.summarize(root));
+        self.nodes.alloc(new_root)
+    }
+
+    // Leaf which would hold the key.
+    fn find_leaf(&self, key: &K) -> Option<NodeId> {
+        let mut id = self.root?;
+        while !self.nodes[id].is_leaf {
+            let node = &self.nodes[id];
+            let index = match self.comparator.search(&node.keys, key) {
+                Ok(index) => index + 1,
+                Err(index) => index,
+            };
+            id = node.childrens[index];
+        }
+        Some(id)
+    }
+
+    // Remove the key from the subtree, then fix the child it was removed from
+    // if it has too few keys left, see `rebalance`.
+    fn remove_from(&mut self, id: NodeId, key: &K) -> Option<V> {
+        let node = &mut self.nodes[id];
+        if node.is_leaf {
+            let index = self.comparator.search(&node.keys, key).ok()?;
+            node.keys.remove(index);
+            return Some(node.values.remove(index));
+        }
+
+        let index = match self.comparator.search(&node.keys, key) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        };
+        let child = node.childrens[index];
+        let value = self.remove_from(child, key)?;
+        self.refresh(id, index);
+
+        if self.nodes[child].keys.len() < min_keys(self.max_degree) {
+            self.rebalance(id, index);
+        }
+
+        // The key may still separate two childrens, replace it with the
+        // first key on its right, its inorder successor.
+        if let Ok(index) = self.comparator.search(&self.nodes[id].keys, key) {
+            let successor = self.first_key(self.nodes[id].childrens[index + 1]).clone();
+            self.nodes[id].keys[index] = successor;
+        }
+        Some(value)
+    }
+
+    fn first_key(&self, mut id: NodeId) -> &K {
+        while let Some(&child) = self.nodes[id].childrens.first() {
+            id = child;
+        }
+        &self.nodes[id].keys[0]
+    }
+
+    // The child at `index` has too few keys, borrow one from a sibling that
+    // has more than the minimum, the left one first:
+    //
+    //       [5]                   [4]
+    //   [3, 4]  []     ->     [3]    [4]
+    //
+    // Otherwise merge it with a sibling, which the parent then has one key
+    // and one child less:
+    //
+    //     [3, 5]                 [5]
+    //   [1] [] [5, 6]    ->   [1]  [5, 6]
+    fn rebalance(&mut self, id: NodeId, index: usize) {
+        let min_keys = min_keys(self.max_degree);
+        let node = &self.nodes[id];
+        let has_extra_keys = |sibling: Option<&NodeId>| {
+            sibling.is_some_and(|&sibling| self.nodes[sibling].keys.len() > min_keys)
+        };
+        let left = index
+            .checked_sub(1)
+            .and_then(|left| node.childrens.get(left));
+        let right = node.childrens.get(index + 1);
+
+        if has_extra_keys(left) {
+            self.borrow_from_left(id, index);
+        } else if has_extra_keys(right) {
+            self.borrow_from_right(id, index);
+        } else if left.is_some() {
+            self.merge_childs(id, index - 1);
+        } else {
+            self.merge_childs(id, index);
+        }
+    }
+
+    // A leaf takes the last entry of its left sibling, which becomes the key
+    // in between them in the parent. An internal node takes the key in
+    // between them from the parent, along with the last child of its left
+    // sibling, and the last key of the sibling moves up to the parent.
+    fn borrow_from_left(&mut self, id: NodeId, index: usize) {
+        self.counters.borrows += 1;
+        let node = &self.nodes[id];
+        let (left_id, child_id) = (node.childrens[index - 1], node.childrens[index]);
+        let (left, child) = self.nodes.pair_mut(left_id, child_id);
+
+        if child.is_leaf {
+            child.keys.insert(0, left.keys.pop().unwrap());
+            child.values.insert(0, left.values.pop().unwrap());
+            self.nodes[id].keys[index - 1] = child.keys[0].clone();
+        } else {
+            let key = left.keys.pop().unwrap();
+            child.childrens.insert(0, left.childrens.pop().unwrap());
+            child.summaries.insert(0, left.summaries.pop().unwrap());
+            let parent_key = std::mem::replace(&mut self.nodes[id].keys[index - 1], key);
+            self.nodes[child_id].keys.insert(0, parent_key);
+        }
+        self.refresh(id, index - 1);
+        self.refresh(id, index);
+    }
+
+    // Same as `borrow_from_left`, with the first entry or key and child of
+    // the right sibling.
+    fn borrow_from_right(&mut self, id: NodeId, index: usize) {
+        self.counters.borrows += 1;
+        let node = &self.nodes[id];
+        let (child_id, right_id) = (node.childrens[index], node.childrens[index + 1]);
+        let (child, right) = self.nodes.pair_mut(child_id, right_id);
+
+        if child.is_leaf {
+            child.keys.push(right.keys.remove(0));
+            child.values.push(right.values.remove(0));
+            self.nodes[id].keys[index] = right.keys[0].clone();
+        } else {
+            let key = right.keys.remove(0);
+            child.childrens.push(right.childrens.remove(0));
+            child.summaries.push(right.summaries.remove(0));
+            let parent_key = std::mem::replace(&mut self.nodes[id].keys[index], key);
+            self.nodes[child_id].keys.push(parent_key);
+        }
+        self.refresh(id, index);
+        self.refresh(id, index + 1);
+    }
+
+    // Merge the child at `index + 1` into the one at `index`, and free it. The
+    // key in between them moves down to an internal node, and is dropped for
+    // leaves since it is the first key of the right leaf.
+    fn merge_childs(&mut self, id: NodeId, index: usize) {
+        self.counters.merges += 1;
+        let node = &mut self.nodes[id];
+        let key = node.keys.remove(index);
+        let left_id = node.childrens[index];
+        let right_id = node.childrens.remove(index + 1);
+        node.summaries.remove(index + 1);
+
+        let mut right = self.nodes.free(right_id);
+        let left = &mut self.nodes[left_id];
+        if left.is_leaf {
+            left.next = right.next;
+        } else {
+            left.keys.push(key);
+        }
+        left.keys.append(&mut right.keys);
+        left.values.append(&mut right.values);
+        left.childrens.append(&mut right.childrens);
+        left.summaries.append(&mut right.summaries);
+        self.refresh(id, index);
+    }
+
+    // Remove the keys in the range from the subtree. The childrens entirely
+    // in the range are freed whole, along with the keys in between them, and
+    // only the first and the last child holding keys in the range, when they
+    // also hold keys outside of it, are trimmed:
+    //
+    //          [3, 5, 7, 9]                      [3, 9]
+    //   [1, 2] [3, 4] [5, 6] [7, 8] [9]   ->   [1, 2] [3] [9]    range 4..=8
+    //
+    // A child is entirely in the range when the keys on both sides of it are,
+    // or for the first and the last child, when the whole subtree is after
+    // the start or before the end, as the parent knows. Then only the trimmed
+    // childrens are fixed, see `fix_child`.
+    fn delete_range_from(
+        &mut self,
+        id: NodeId,
+        ends: &Ends<K>,
+        after_start: bool,
+        before_end: bool,
+    ) -> usize {
+        let comparator = &self.comparator;
+        let node = &mut self.nodes[id];
+        let start = node
+            .keys
+            .partition_point(|key| ends.before_start(comparator, key));
+        let end = node
+            .keys
+            .partition_point(|key| !ends.after_end(comparator, key));
+        if start > end {
+            return 0;
+        }
+        if node.is_leaf {
+            node.keys.drain(start..end);
+            node.values.drain(start..end);
+            return end - start;
+        }
+
+        // The childrens from `start` to `end` hold keys in the range, and the
+        // ones from `first` to `last` are entirely in it.
+        let len = node.keys.len();
+        let after = |i: usize| i > start || (i == 0 && after_start);
+        let before = |i: usize| i < end || (i == len && before_end);
+        let first = if after(start) && before(start) {
+            start
+        } else {
+            start + 1
+        };
+        let last = if after(end) && before(end) {
+            end + 1
+        } else {
+            end
+        }
+        .max(first);
+
+        let inside: Vec<NodeId> = node.childrens.drain(first..last).collect();
+        node.summaries.drain(first..last);
+        // The key before each freed child, or after it for the first ones.
+        node.keys.drain(match first {
+            0 => 0..last,
+            _ => first - 1..last - 1,
+        });
+        let mut removed: usize = inside.into_iter().map(|id| self.free_subtree(id)).sum();
+
+        // The trimmed childrens, the right one is now after the left one.
+        let left = (first > start).then_some(start);
+        let right = (last == end && end > start).then_some(first);
+        if let Some(right) = right {
+            let child = self.nodes[id].childrens[right];
+            removed += self.delete_range_from(child, ends, after(end), before(end));
+            self.refresh(id, right);
+        }
+        if let Some(left) = left {
+            let child = self.nodes[id].childrens[left];
+            removed += self.delete_range_from(child, ends, after(start), before(start));
+            self.refresh(id, left);
+
+            // Link the left child to what is after the freed childrens, which
+            // is outside of the subtree when they were the last ones.
+            if first < last || right.is_some() {
+                let next = self.nodes[id]
+                    .childrens
+                    .get(first)
+                    .map(|&next| self.first_leaf(next));
+                let last_leaf = self.last_leaf(child);
+                self.nodes[last_leaf].next = next;
             }
+        }
 
-            // If node is leaf, means there's not children
-            if !child.is_leaf {
-                for _ in 0..min_number_of_keys {
-                    let value = child.childrens.remove(breakpoint + 1);
-                    right_node.childrens.push(value);
-                }
+        if let Some(right) = right {
+            self.fix_child(id, right);
+        }
+        if let Some(left) = left {
+            self.fix_child(id, left);
+        }
+        removed
+    }
+
+    fn free_subtree(&mut self, id: NodeId) -> usize {
+        let node = self.nodes.free(id);
+        let keys = if node.is_leaf { node.keys.len() } else { 0 };
+        keys + node
+            .childrens
+            .into_iter()
+            .map(|child| self.free_subtree(child))
+            .sum::<usize>()
+    }
+
+    fn first_leaf(&self, mut id: NodeId) -> NodeId {
+        while let Some(&child) = self.nodes[id].childrens.first() {
+            id = child;
+        }
+        id
+    }
 
-                // Since we now have childrens, we are not leaf node anymore.
-                right_node.is_leaf = false;
-                right_node.values.clear();
+    fn last_leaf(&self, mut id: NodeId) -> NodeId {
+        while let Some(&child) = self.nodes[id].childrens.last() {
+            id = child;
+        }
+        id
+    }
+
+    // Borrow or merge until the child at `index` has enough keys, unless it is
+    // the only child left. Unlike after `remove`, the child may have lost many
+    // keys, and may be an internal node left with a single child which has
+    // too few keys itself, and so on down. Those are fixed once they have
+    // siblings again, after each borrow or merge.
+    fn fix_child(&mut self, id: NodeId, mut index: usize) {
+        let min_keys = min_keys(self.max_degree);
+        while index < self.nodes[id].childrens.len() {
+            let child = self.nodes[id].childrens[index];
+            if self.nodes[id].childrens.len() == 1 || self.nodes[child].keys.len() >= min_keys {
+                return;
             }
 
-            // TODO: Add explanation why this is needed
-            if index + 1 > self.childrens.len() {
-                self.childrens.push(right_node);
-            } else {
-                self.childrens.insert(index + 1, right_node);
+            let merges = self.counters.merges;
+            self.rebalance(id, index);
+            // Merged into the left sibling, see `rebalance`.
+            if self.counters.merges > merges && index > 0 {
+                index -= 1;
             }
+
+            self.fix_childrens(self.nodes[id].childrens[index]);
         }
     }
 
-    pub fn search(&self, key: &u32) -> Option<&u32> {
-        match self.keys.binary_search(key) {
-            Ok(index) => {
-                if self.is_leaf {
-                    self.values.get(index)
-                } else {
-                    self.childrens[index + 1].search(key)
-                }
+    // Fix every child with too few keys, see `fix_child`.
+    fn fix_childrens(&mut self, id: NodeId) {
+        let min_keys = min_keys(self.max_degree);
+        let too_few_keys = |tree: &Self| {
+            let childrens = &tree.nodes[id].childrens;
+            let position = childrens
+                .iter()
+                .position(|&child| tree.nodes[child].keys.len() < min_keys);
+            position.filter(|_| childrens.len() > 1)
+        };
+        while let Some(index) = too_few_keys(self) {
+            self.fix_child(id, index);
+        }
+    }
+
+    // Merge the sorted entries into the subtree. Each child gets the entries
+    // in between its keys in the parent, and is split once afterward if it
+    // has too many keys, in as many nodes as needed, see
+    // `split_child_evenly`. Entries with a key already in the tree are
+    // ignored, like `insert`.
+    fn insert_batch_into(&mut self, id: NodeId, mut entries: Vec<(K, V)>) {
+        let comparator = &self.comparator;
+        let node = &mut self.nodes[id];
+        if node.is_leaf {
+            let old = std::mem::take(&mut node.keys).into_iter();
+            let mut old = old.zip(std::mem::take(&mut node.values)).peekable();
+            let mut entries = entries.into_iter().peekable();
+            loop {
+                let ordering = match (old.peek(), entries.peek()) {
+                    (Some(a), Some(b)) => comparator.compare(&a.0, &b.0),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => break,
+                };
+                let (key, value) = match ordering {
+                    Ordering::Less => old.next().unwrap(),
+                    Ordering::Equal => {
+                        entries.next();
+                        old.next().unwrap()
+                    }
+                    Ordering::Greater => entries.next().unwrap(),
+                };
+                node.keys.push(key);
+                node.values.push(value);
             }
-            Err(index) => {
-                if self.is_leaf {
-                    None
-                } else {
-                    self.childrens[index].search(key)
+            return;
+        }
+
+        // From the last child, so splitting a child doesn't move the ones
+        // left to do.
+        for index in (0..node.childrens.len()).rev() {
+            let start = match index {
+                0 => 0,
+                _ => {
+                    let key = &self.nodes[id].keys[index - 1];
+                    entries
+                        .partition_point(|(other, _)| self.comparator.compare(other, key).is_lt())
                 }
+            };
+            let entries = entries.split_off(start);
+            if entries.is_empty() {
+                continue;
+            }
+
+            let child = self.nodes[id].childrens[index];
+            self.insert_batch_into(child, entries);
+            self.refresh(id, index);
+            if self.nodes[child].keys.len() >= self.max_degree {
+                self.split_child_evenly(id, index);
             }
         }
     }
 
-    fn min_key(&self, max_degree: usize) -> usize {
-        let mut min_key = (max_degree / 2) - 1;
-
-        if min_key == 0 {
-            min_key = 1;
+    // Split the child in as few nodes as fit its keys, with the same number
+    // of keys each, give or take one, the same as `split_child` when the
+    // child has one key too many. The first key of each new leaf is copied
+    // up, while the key in between two new internal nodes moves up.
+    fn split_child_evenly(&mut self, id: NodeId, index: usize) {
+        let child_id = self.nodes[id].childrens[index];
+        let child = &mut self.nodes[child_id];
+        let mut new_nodes = vec![];
+
+        if child.is_leaf {
+            let keys = std::mem::take(&mut child.keys);
+            let entries = keys.into_iter().zip(std::mem::take(&mut child.values));
+            let mut groups = split_evenly(entries.collect(), self.max_degree - 1).into_iter();
+            (child.keys, child.values) = groups.next().unwrap().into_iter().unzip();
+            for entries in groups {
+                let mut leaf = Node::new(true);
+                (leaf.keys, leaf.values) = entries.into_iter().unzip();
+                new_nodes.push((leaf.keys[0].clone(), leaf));
+            }
+        } else {
+            let mut keys = std::mem::take(&mut child.keys).into_iter();
+            let childrens = std::mem::take(&mut child.childrens).into_iter();
+            let childrens = childrens.zip(std::mem::take(&mut child.summaries));
+            let mut groups = split_evenly(childrens.collect(), self.max_degree).into_iter();
+            (child.childrens, child.summaries) = groups.next().unwrap().into_iter().unzip();
+            child.keys = keys.by_ref().take(child.childrens.len() - 1).collect();
+            for childrens in groups {
+                let separator = keys.next().unwrap();
+                let mut node = Node::new(false);
+                node.keys = keys.by_ref().take(childrens.len() - 1).collect();
+                (node.childrens, node.summaries) = childrens.into_iter().unzip();
+                new_nodes.push((separator, node));
+            }
         }
 
-        min_key
+        self.counters.splits += new_nodes.len() as u64;
+        let (mut previous, next) = (child_id, self.nodes[child_id].next);
+        for (i, (separator, node)) in new_nodes.into_iter().enumerate() {
+            let is_leaf = node.is_leaf;
+            let new_id = self.nodes.alloc(node);
+            if is_leaf {
+                self.nodes[previous].next = Some(new_id);
+                self.nodes[new_id].next = next;
+            }
+            previous = new_id;
+
+            let summary = self.summarize(new_id);
+            let node = &mut self.nodes[id];
+            node.keys.insert(index + i, separator);
+            node.childrens.insert(index + i + 1, new_id);
+            node.summaries.insert(index + i + 1, summary);
+        }
+        self.refresh(id, index);
     }
 
-    pub fn remove(&mut self, key: &u32, max_degree: usize) -> Option<u32> {
-        // println!("--- remove {key} from {:?}", self);
-        let (index, result) = match self.keys.binary_search(key) {
-            Ok(index) => {
-                if self.is_leaf {
-                    let value = self.values.remove(index);
-                    self.keys.remove(index);
-                    (Some(index), Some(value))
+    // Remove the sorted keys from the subtree, each child the keys in between
+    // its keys in the parent, then fix the childrens left with too few keys.
+    fn remove_batch_from(&mut self, id: NodeId, keys: &[K]) -> usize {
+        let comparator = &self.comparator;
+        let node = &mut self.nodes[id];
+        if node.is_leaf {
+            let old = std::mem::take(&mut node.keys).into_iter();
+            let mut keys = keys.iter().peekable();
+            let mut removed = 0;
+            for (key, value) in old.zip(std::mem::take(&mut node.values)) {
+                while keys
+                    .next_if(|other| comparator.compare(other, &key).is_lt())
+                    .is_some()
+                {}
+                if keys
+                    .next_if(|other| comparator.compare(other, &key).is_eq())
+                    .is_some()
+                {
+                    removed += 1;
                 } else {
-                    (
-                        Some(index + 1),
-                        self.remove_from_internals(index, max_degree),
-                    )
+                    node.keys.push(key);
+                    node.values.push(value);
                 }
             }
-            Err(index) => {
-                if self.is_leaf {
-                    (None, None)
-                } else {
-                    (Some(index), self.childrens[index].remove(key, max_degree))
+            return removed;
+        }
+
+        let mut removed = 0;
+        let mut rest = keys;
+        for index in 0..node.childrens.len() {
+            let node = &self.nodes[id];
+            let end = match node.keys.get(index) {
+                Some(key) => {
+                    rest.partition_point(|other| self.comparator.compare(other, key).is_lt())
                 }
+                None => rest.len(),
+            };
+            let (keys, others) = rest.split_at(end);
+            rest = others;
+            if !keys.is_empty() {
+                removed += self.remove_batch_from(node.childrens[index], keys);
+                self.refresh(id, index);
             }
-        };
-
-        if let Some(index) = index {
-            // println!("--- keys: {:?}, index: {index}", self.keys);
-            self.rebalance(index, max_degree);
         }
-        result
+        self.fix_childrens(id);
+        removed
     }
 
-    pub fn remove_from_internals(&mut self, index: usize, max_degree: usize) -> Option<u32> {
-        if DEBUG {
-            println!("--- remove_from_internals");
+    // The root may be left with a single child, which may have a single
+    // child itself, or be an empty leaf.
+    fn collapse_root(&mut self) {
+        while let Some(root) = self.root.filter(|&root| self.nodes[root].keys.is_empty()) {
+            let node = self.nodes.free(root);
+            self.root = node.childrens.first().copied();
         }
-        let key = self.keys[index];
-        self.keys.remove(index);
+    }
 
-        if DEBUG {
-            println!(
-                "self: {:?}, child: {:?}, index: {index}",
-                self, self.childrens
-            );
+    // Summary of the entries of the subtree in the range. The subtree is
+    // entirely in the range when the parent knows it is after the start and
+    // before the end, see `delete_range_from`. Otherwise the summaries of the
+    // childrens entirely in the range are combined with the ones of the
+    // first and the last child in the range, which only hold some of it.
+    fn aggregate_from(&self, id: NodeId, ends: &Ends<K>, after_start: bool, before_end: bool) -> S {
+        if after_start && before_end {
+            return self.summarize(id);
         }
-        let min_key = self.min_key(max_degree);
-        let child_key = self.childrens[index + 1].keys.len();
-        let result = self.childrens[index + 1].remove(&key, max_degree);
 
-        if child_key == min_key {
-            if self.childrens[index + 1].is_leaf {
-                // println!("Case 2b: {:?}", self.childrens[index + 1]);
-                self.fill_with_immediate_sibling(index, max_degree);
-            }
-        } else {
-            // println!("Case 2a: {:?}", self.childrens[index + 1]);
-            self.fill_with_inorder_successor(index);
-        };
-
-        // This mean that the actual remove happen at self children
-        // children. Hence, we want to pick an inorder successor to
-        // replace the key we just removed.
-        if self.childrens.len() > index + 1 && !self.childrens[index + 1].is_leaf {
-            // println!("Case 2c: {:?}", self.childrens);
-
-            if child_key == min_key {
-                self.fill_with_inorder_successor(index);
-            }
+        let comparator = &self.comparator;
+        let node = &self.nodes[id];
+        let start = node
+            .keys
+            .partition_point(|key| ends.before_start(comparator, key));
+        let end = node
+            .keys
+            .partition_point(|key| !ends.after_end(comparator, key));
+        if start > end {
+            return S::identity();
+        }
+        if node.is_leaf {
+            let entries = node.keys[start..end].iter().zip(&node.values[start..end]);
+            return entries.fold(S::identity(), |summary, (key, value)| {
+                summary.combine(&S::entry(key, value))
+            });
         }
 
-        result
+        let len = node.keys.len();
+        let after = |i: usize| i > start || (i == 0 && after_start);
+        let before = |i: usize| i < end || (i == len && before_end);
+        (start..=end).fold(S::identity(), |summary, i| {
+            let child = match after(i) && before(i) {
+                true => node.summaries[i].clone(),
+                false => self.aggregate_from(node.childrens[i], ends, after(i), before(i)),
+            };
+            summary.combine(&child)
+        })
     }
 
-    pub fn fill_with_immediate_sibling(&mut self, index: usize, max_degree: usize) {
-        if DEBUG {
-            println!("--- fill_with_immediate_sibling");
+    // Recompute the summaries along the path to the leaf of the key, after
+    // changing the leaf in place.
+    fn refresh_path(&mut self, key: &K) {
+        let mut path = vec![];
+        let mut node = self.root;
+        while let Some(id) = node.filter(|&id| !self.nodes[id].is_leaf) {
+            let current = &self.nodes[id];
+            let index = match self.comparator.search(&current.keys, key) {
+                Ok(index) => index + 1,
+                Err(index) => index,
+            };
+            path.push((id, index));
+            node = Some(current.childrens[index]);
         }
-        let min_key = self.min_key(max_degree);
+        for (id, index) in path.into_iter().rev() {
+            self.refresh(id, index);
+        }
+    }
+}
 
-        if self.childrens[index].keys.len() > min_key {
-            let left_sibling = self.childrens.get_mut(index).unwrap();
-            let steal_key = left_sibling.keys.pop().unwrap();
-            let steal_value = left_sibling.values.pop().unwrap();
-            // println!("Steal {steal_key} from left sibling {:?}...", left_sibling);
-            self.keys.insert(index, steal_key);
-            self.childrens[index + 1].keys.insert(0, steal_key);
-            self.childrens[index + 1].values.insert(0, steal_value);
-        } else if self.childrens[index + 1].keys.len() > min_key {
-            println!("------------------- to handle");
+impl<K, V, C, S: Summary<K, V>> BPlusTree<K, V, C, S> {
+    // Summary of the subtree, from the summaries of the childrens of an
+    // internal node, or from the entries of a leaf.
+    fn summarize(&self, id: NodeId) -> S {
+        let node = &self.nodes[id];
+        if node.is_leaf {
+            let entries = node.keys.iter().zip(&node.values);
+            entries.fold(S::identity(), |summary, (key, value)| {
+                summary.combine(&S::entry(key, value))
+            })
         } else {
-            // println!("Case 3 internal");
-            self.childrens.remove(index + 1);
+            let summaries = node.summaries.iter();
+            summaries.fold(S::identity(), |summary, child| summary.combine(child))
         }
     }
 
-    pub fn fill_with_inorder_successor(&mut self, index: usize) {
-        if DEBUG {
-            // println!("--- fill_with_inorder_successor");
-        }
+    // Recompute the summary of the child at `index`, after it changed.
+    fn refresh(&mut self, id: NodeId, index: usize) {
+        let summary = self.summarize(self.nodes[id].childrens[index]);
+        self.nodes[id].summaries[index] = summary;
+    }
+}
 
-        let node = &self.childrens[index + 1];
-        let mut indexes = vec![];
-        for (i, n) in node.childrens.iter().enumerate() {
-            if n.keys.is_empty() {
-                indexes.push(i);
+// In order iterator, following the leaves from the first one.
+pub struct Iter<'a, K, V, S = ()> {
+    nodes: &'a Arena<Node<K, V, S>>,
+    leaf: Option<&'a Node<K, V, S>>,
+    index: usize, // Next entry of the leaf.
+}
+
+impl<'a, K, V, S> Iterator for Iter<'a, K, V, S> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(leaf) = self.leaf {
+            if self.index < leaf.keys.len() {
+                self.index += 1;
+                return Some((&leaf.keys[self.index - 1], &leaf.values[self.index - 1]));
             }
+
+            self.leaf = leaf.next.map(|next| &self.nodes[next]);
+            self.index = 0;
         }
 
-        let mut_node = &mut self.childrens[index + 1];
-        let mut removed_elem = 0;
-        for i in indexes {
-            mut_node.childrens.remove(i - removed_elem);
-            removed_elem += 1;
+        None
+    }
+}
+
+// Entries in a range, in order from both ends, see `src/range.rs`. The front
+// follows the links to the next leaves, while the back keeps its path from
+// the root, with the number of childrens or entries left to visit of each
+// node, since leaves don't link to the previous one.
+pub struct Range<'a, K, V, C, S = ()> {
+    front: Iter<'a, K, V, S>,
+    back: Vec<(&'a Node<K, V, S>, usize)>,
+    comparator: &'a C,
+    ends: Ends<'a, K>,
+}
+
+impl<'a, K, V, C: Comparator<K>, S> Iterator for Range<'a, K, V, C, S> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ends.is_done() {
+            return None;
         }
+        let entry = self.front.next();
+        let key = entry.map(|(key, _)| key);
+        self.ends
+            .front(self.comparator, key)
+            .then(|| entry.unwrap())
+    }
+}
 
-        let mut successor = &self.childrens[index + 1];
-        while !successor.childrens.is_empty() {
-            successor = &successor.childrens[0];
+impl<'a, K, V, C: Comparator<K>, S> DoubleEndedIterator for Range<'a, K, V, C, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.ends.is_done() {
+            return None;
         }
+        let entry = self.next_back_entry();
+        let key = entry.map(|(key, _)| key);
+        self.ends.back(self.comparator, key).then(|| entry.unwrap())
+    }
+}
 
-        self.keys.insert(index, successor.keys[0]);
+impl<'a, K, V, C, S> Range<'a, K, V, C, S> {
+    fn next_back_entry(&mut self) -> Option<(&'a K, &'a V)> {
+        while let Some((node, left)) = self.back.last_mut() {
+            let node: &'a Node<K, V, S> = node;
+            if *left == 0 {
+                self.back.pop();
+                continue;
+            }
+            *left -= 1;
 
-        // We need to see if our child internal node contain the key
-        // that we have just inserted. If yes, remove it.
-        if !self.childrens[index + 1].is_leaf {
-            if let Ok(key_index) = self.childrens[index + 1]
-                .keys
-                .binary_search(&successor.keys[0])
-            {
-                self.childrens[index + 1].keys.remove(key_index);
+            if node.is_leaf {
+                return Some((&node.keys[*left], &node.values[*left]));
             }
+            let child = &self.front.nodes[node.childrens[*left]];
+            let len = match child.is_leaf {
+                true => child.keys.len(),
+                false => child.childrens.len(),
+            };
+            self.back.push((child, len));
         }
+        None
     }
+}
 
-    pub fn find_indexes_involved(&self, mut index: usize) -> (usize, usize, usize) {
-        if DEBUG {
-            println!("keys: {:?}", self.keys);
-            println!("index: {index}, child_key: {}", self.childrens.len());
+// Position on an entry of the tree, which can move both ways and change the
+// entry it is on:
+//
+//   let mut cursor = tree.cursor();
+//   cursor.seek(&3);
+//   while cursor.key().is_some_and(|key| *key < 10) {
+//       cursor.delete_current();
+//   }
+//
+// Moving to the next entry follows the link to the next leaf, while moving
+// to the previous one from the first entry of a leaf goes down from the root
+// again, since leaves only link to the next one. The cursor is either on an
+// entry, or off the tree after moving past the first or the last entry.
+pub struct Cursor<'a, K, V, C = Natural, S = ()> {
+    tree: &'a mut BPlusTree<K, V, C, S>,
+    leaf: Option<NodeId>, // None when off the tree.
+    index: usize,
+}
+
+impl<K: Clone, V, C: Comparator<K>, S: Summary<K, V>> BPlusTree<K, V, C, S> {
+    // Cursor on the first entry, off the tree when it is empty.
+    pub fn cursor(&mut self) -> Cursor<'_, K, V, C, S> {
+        let mut cursor = Cursor {
+            tree: self,
+            leaf: None,
+            index: 0,
+        };
+        cursor.seek_first();
+        cursor
+    }
+}
+
+impl<K: Clone, V, C: Comparator<K>, S: Summary<K, V>> Cursor<'_, K, V, C, S> {
+    // Move to the first entry with a key at least the key, returning whether
+    // it is the key.
+    pub fn seek(&mut self, key: &K) -> bool {
+        self.leaf = self.tree.find_leaf(key);
+        let found = self
+            .leaf
+            .map(|id| self.tree.comparator.search(&self.tree.nodes[id].keys, key));
+        self.index = match found {
+            Some(Ok(index) | Err(index)) => index,
+            None => 0,
+        };
+        self.skip_end_of_leaf();
+        matches!(found, Some(Ok(_)))
+    }
+
+    pub fn seek_first(&mut self) {
+        self.leaf = self.tree.root;
+        while let Some(&first) = self
+            .leaf
+            .and_then(|id| self.tree.nodes[id].childrens.first())
+        {
+            self.leaf = Some(first);
         }
+        self.index = 0;
+        self.skip_end_of_leaf();
+    }
 
-        while index >= self.childrens.len() {
-            index -= 1;
+    pub fn seek_last(&mut self) {
+        self.leaf = self.tree.root;
+        while let Some(&last) = self
+            .leaf
+            .and_then(|id| self.tree.nodes[id].childrens.last())
+        {
+            self.leaf = Some(last);
         }
+        self.index = self.leaf.map_or(0, |id| self.tree.nodes[id].keys.len());
+        self.prev();
+    }
 
-        for (i, n) in self.childrens.iter().enumerate() {
-            if n.keys.is_empty() {
-                index = i;
-                break;
-            }
+    // Move to the next entry, returning false when there is none, which
+    // moves the cursor off the tree. Not an `Iterator`, a cursor also moves
+    // back and changes entries.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> bool {
+        if self.leaf.is_none() {
+            return false;
         }
+        self.index += 1;
+        self.skip_end_of_leaf();
+        self.leaf.is_some()
+    }
 
-        let mut left_index = index;
-        let mut right_index = index;
+    // Move to the previous entry, returning false when there is none, which
+    // moves the cursor off the tree.
+    pub fn prev(&mut self) -> bool {
+        let Some(id) = self.leaf else {
+            return false;
+        };
+        if self.index > 0 {
+            self.index -= 1;
+            return true;
+        }
 
-        if index != 0 {
-            left_index -= 1;
+        // Go down to the leaf again, remembering the last child on the left
+        // of the path. The previous leaf is the last one of its subtree.
+        let key = &self.tree.nodes[id].keys[0];
+        let (mut node, mut left) = (self.tree.root, None);
+        while let Some(current) = node.filter(|&current| current != id) {
+            let current = &self.tree.nodes[current];
+            let index = match self.tree.comparator.search(&current.keys, key) {
+                Ok(index) => index + 1,
+                Err(index) => index,
+            };
+            left = index
+                .checked_sub(1)
+                .map(|left| current.childrens[left])
+                .or(left);
+            node = Some(current.childrens[index]);
         }
+        while let Some(&last) = left.and_then(|id| self.tree.nodes[id].childrens.last()) {
+            left = Some(last);
+        }
+
+        self.leaf = left;
+        self.index = left.map_or(0, |id| self.tree.nodes[id].keys.len() - 1);
+        self.leaf.is_some()
+    }
+
+    pub fn key(&self) -> Option<&K> {
+        Some(&self.tree.nodes[self.leaf?].keys[self.index])
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        Some(&self.tree.nodes[self.leaf?].values[self.index])
+    }
+
+    // Replace the value of the entry, returning the previous one, None when
+    // off the tree.
+    pub fn update_current(&mut self, value: V) -> Option<V> {
+        let leaf = &mut self.tree.nodes[self.leaf?];
+        let previous = std::mem::replace(&mut leaf.values[self.index], value);
+        let key = leaf.keys[self.index].clone();
+        self.tree.refresh_path(&key);
+        Some(previous)
+    }
+
+    // Remove the entry and move to the next one, returning the removed entry.
+    // The entry is removed from the leaf in place when the leaf keeps enough
+    // keys and the key isn't the first one of the leaf, which a parent may
+    // use as separator. Otherwise it is removed from the tree, which may
+    // merge or borrow, and the cursor seeks the next entry again.
+    pub fn delete_current(&mut self) -> Option<(K, V)> {
+        let id = self.leaf?;
+        let min_keys = match self.tree.root == Some(id) {
+            true => 1,
+            false => min_keys(self.tree.max_degree),
+        };
+        let leaf = &mut self.tree.nodes[id];
 
-        if index != self.childrens.len() - 1 {
-            right_index += 1;
+        if self.index > 0 && leaf.keys.len() > min_keys {
+            let entry = (leaf.keys.remove(self.index), leaf.values.remove(self.index));
+            self.tree.refresh_path(&entry.0);
+            self.skip_end_of_leaf();
+            return Some(entry);
         }
 
-        (index, left_index, right_index)
+        let key = leaf.keys[self.index].clone();
+        let value = self.tree.remove(&key)?;
+        self.seek(&key);
+        Some((key, value))
     }
 
-    pub fn rebalance(&mut self, index: usize, max_degree: usize) {
-        if !self.is_leaf && !self.keys.is_empty() {
-            if DEBUG {
-                println!("--- rebalance");
-                println!("self: {:?}, child: {:?}", self, self.childrens);
+    // Past the last entry of a leaf, move to the first one of the next leaf,
+    // or off the tree after the last leaf.
+    fn skip_end_of_leaf(&mut self) {
+        while let Some(id) = self.leaf {
+            let leaf = &self.tree.nodes[id];
+            if self.index < leaf.keys.len() {
+                return;
             }
+            self.leaf = leaf.next;
+            self.index = 0;
+        }
+    }
+}
 
-            let (index, left_index, right_index) = self.find_indexes_involved(index);
+// Split items into the fewest groups of at most `capacity` items, with the
+// same number of items in each group, give or take one.
+fn split_evenly<T>(items: Vec<T>, capacity: usize) -> Vec<Vec<T>> {
+    let numbers_of_groups = items.len().div_ceil(capacity);
+    let mut items = items.into_iter();
+    let mut groups = Vec::with_capacity(numbers_of_groups);
 
-            if DEBUG {
-                println!("left_index: {left_index}, index: {index}, right_index: {right_index}");
-            }
+    for i in 0..numbers_of_groups {
+        let len = items.len() / (numbers_of_groups - i);
+        groups.push(items.by_ref().take(len).collect());
+    }
 
-            if self.childrens[index].keys.is_empty() {
-                if !self.childrens[index].is_leaf {
-                    self.rebalance_internal_node(index, right_index, left_index, max_degree);
-                } else {
-                    if DEBUG {
-                        println!("keys: {:?}", self.keys);
-                        println!(
-                            "right keys: {:?}, child_key: {:?}, left keys: {:?}",
-                            self.childrens[right_index].keys,
-                            self.childrens[index].keys,
-                            self.childrens[left_index].keys
-                        );
-                    }
+    groups
+}
 
-                    let min_key = self.min_key(max_degree);
-                    if self.childrens[left_index].keys.len() < min_key
-                        && self.childrens[right_index].keys.len() < min_key
-                    {
-                        println!("to handle");
-                    } else {
-                        // println!("Remove empty child");
-                        self.keys.remove(index);
-                        self.childrens.remove(index);
-                    }
-                }
-            }
-            if DEBUG {
-                println!("-----\n");
-            }
+impl<K: Codec, V: Codec> Node<K, V> {
+    // Node record: leaf flag, keys, values, then the children records.
+    fn write_to<W: Write>(&self, nodes: &Arena<Node<K, V>>, writer: &mut W) -> std::io::Result<()> {
+        serialization::write_u8(writer, self.is_leaf as u8)?;
+        serialization::write_len(writer, self.keys.len())?;
+        for key in &self.keys {
+            key.encode(writer)?;
         }
+
+        serialization::write_len(writer, self.values.len())?;
+        for value in &self.values {
+            value.encode(writer)?;
+        }
+
+        serialization::write_len(writer, self.childrens.len())?;
+        for &child in &self.childrens {
+            nodes[child].write_to(nodes, writer)?;
+        }
+        Ok(())
     }
 
-    pub fn rebalance_internal_node(
-        &mut self,
-        index: usize,
-        right_index: usize,
-        left_index: usize,
+    // The leaves are read from left to right, each one is linked to the
+    // `previous_leaf`.
+    fn read_from<R: Read>(
+        reader: &mut R,
         max_degree: usize,
-    ) {
-        let min_key = self.min_key(max_degree);
+        nodes: &mut Arena<Node<K, V>>,
+        previous_leaf: &mut Option<NodeId>,
+    ) -> Result<NodeId, serialization::Error> {
+        let is_leaf = match serialization::read_u8(reader)? {
+            0 => false,
+            1 => true,
+            _ => return Err(serialization::Error::Corrupted("invalid leaf flag")),
+        };
+        let mut node = Node::new(is_leaf);
 
-        if DEBUG {
-            println!(
-                "parent: {:?}, left child: {:?}, right child: {:?}",
-                self.keys, self.childrens[left_index].keys, self.childrens[right_index].keys,
-            );
+        let numbers_of_keys = serialization::read_len(reader)?;
+        if numbers_of_keys >= max_degree {
+            return Err(serialization::Error::Corrupted("too many keys"));
+        }
+        for _ in 0..numbers_of_keys {
+            node.keys.push(K::decode(reader)?);
         }
 
-        if self.childrens[index].keys.len() <= min_key {
-            if self.keys.len() <= min_key {
-                // println!("merge right and left siblings with parents");
-                let mut left = self.childrens.remove(left_index);
-                let mut right = self.childrens.remove(left_index);
-                left.childrens.append(&mut right.childrens);
-                left.keys.append(&mut self.keys);
-                left.keys.append(&mut right.keys);
-
-                self.childrens.push(left);
-            } else if index != left_index && self.childrens[left_index].keys.len() > min_key {
-                // Parent steal from left child. Right child steal key and child
-                // from left child.
-                let parent_key = self.keys.remove(left_index);
-                // println!("Right child steal {parent_key} from parent...");
-                let right = &mut self.childrens[index];
-                right.keys.push(parent_key);
-
-                let left = &mut self.childrens[left_index];
-                let steal_key = left.keys.pop().unwrap();
-                // println!("Parent steal {steal_key} from left_child...");
-                let steal_child = left.childrens.pop().unwrap();
-                self.keys.insert(left_index, steal_key);
-
-                let right = &mut self.childrens[index];
-                right.childrens.insert(0, steal_child);
-            } else if index != right_index && self.childrens[right_index].keys.len() > min_key {
-                // Parent steal from right child. Left child steal key and child
-                // from right child.
-                let parent_key = self.keys.remove(index);
-                // println!("Left child steal {parent_key} from parent...");
-                let left = &mut self.childrens[index];
-                left.keys.push(parent_key);
-
-                let right = &mut self.childrens[right_index];
-                let steal_key = right.keys.remove(0);
-                // println!("Parent steal {steal_key} from right child...");
-                let steal_child = right.childrens.remove(0);
-                self.keys.insert(index, steal_key);
-
-                let left = &mut self.childrens[index];
-                left.childrens.push(steal_child);
-            } else {
-                // println!("merge right and left siblings, remove key from parent");
-
-                let parent_key = if index > 0 {
-                    self.keys.remove(index - 1)
-                } else {
-                    self.keys.remove(index)
-                };
+        // A leaf has a value per key, an internal node none.
+        let numbers_of_values = serialization::read_len(reader)?;
+        if numbers_of_values != if is_leaf { numbers_of_keys } else { 0 } {
+            return Err(serialization::Error::Corrupted("invalid number of values"));
+        }
+        for _ in 0..numbers_of_values {
+            node.values.push(V::decode(reader)?);
+        }
 
-                let mut left = self.childrens.remove(left_index);
-                let mut right = self.childrens.remove(left_index);
+        let numbers_of_childrens = serialization::read_len(reader)?;
+        if numbers_of_childrens != if is_leaf { 0 } else { numbers_of_keys + 1 } {
+            return Err(serialization::Error::Corrupted(
+                "invalid number of childrens",
+            ));
+        }
+        for _ in 0..numbers_of_childrens {
+            let child = Node::read_from(reader, max_degree, nodes, previous_leaf)?;
+            node.childrens.push(child);
+            node.summaries.push(());
+        }
 
-                // println!("{:?}, {:?}", left, right);
+        let id = nodes.alloc(node);
+        if is_leaf {
+            if let Some(previous) = previous_leaf.replace(id) {
+                nodes[previous].next = Some(id);
+            }
+        }
+        Ok(id)
+    }
+}
 
-                left.keys.push(parent_key);
-                left.keys.append(&mut right.keys);
-                left.childrens.append(&mut right.childrens);
+impl<K: Debug, V: Debug, S> Node<K, V, S> {
+    // Internal nodes are records with a port in between the keys for each
+    // child, leaves a column per key with the value below it. Returns the id
+    // of the node, and push the id of the leaves from left to right.
+    fn write_dot<W: fmt::Write>(
+        &self,
+        nodes: &Arena<Node<K, V, S>>,
+        dot: &mut W,
+        next_id: &mut usize,
+        leaves: &mut Vec<usize>,
+    ) -> Result<usize, fmt::Error> {
+        let id = *next_id;
+        *next_id += 1;
+
+        let label = if self.is_leaf {
+            leaves.push(id);
+            let columns: Vec<_> = self
+                .keys
+                .iter()
+                .zip(&self.values)
+                .map(|(key, value)| {
+                    let key = dot::escape(&format!("{key:?}"));
+                    let value = dot::escape(&format!("{value:?}"));
+                    format!("{{{key}|{value}}}")
+                })
+                .collect();
+            columns.join("|")
+        } else {
+            let keys = self.keys.iter().map(|key| format!("{key:?}"));
+            dot::keys_label(keys, true)
+        };
+        writeln!(dot, "  node{id} [label=\"{label}\"];")?;
 
-                self.childrens.insert(left_index, left);
-            }
-        } else if self.childrens[left_index].keys.len() <= min_key {
-            // println!("Steal from parent to merge with right siblings...");
-            let parent_key = self.keys.remove(left_index);
-
-            let mut left = self.childrens.remove(left_index);
-            // - 1 because we remove left above.
-            let right = &mut self.childrens[right_index - 1];
-
-            // Combine childrens of right and left.
-            left.childrens.append(&mut right.childrens);
-            right.childrens = left.childrens;
-
-            // Steal key from parent.
-            right.keys.insert(0, parent_key);
-        } else if self.childrens[right_index].keys.len() <= min_key {
-            // Parent steal from left child. Right child steal key and child
-            // from left child.
-            let parent_key = self.keys.remove(right_index - 1);
-            // println!("Right child steal {parent_key} from parent...");
-            let right = &mut self.childrens[right_index];
-            right.keys.push(parent_key);
-
-            let left = &mut self.childrens[left_index];
-            let steal_key = left.keys.pop().unwrap();
-            // println!("Parent steal {steal_key} from left_child...");
-            let steal_child = left.childrens.pop().unwrap();
-            self.keys.insert(right_index - 1, steal_key);
-
-            let right = &mut self.childrens[right_index];
-            right.childrens.insert(0, steal_child);
+        for (i, &child) in self.childrens.iter().enumerate() {
+            let child_id = nodes[child].write_dot(nodes, dot, next_id, leaves)?;
+            writeln!(dot, "  node{id}:c{i} -> node{child_id};")?;
         }
+        Ok(id)
     }
 }
 
-impl std::fmt::Debug for Node {
+impl<K: std::fmt::Debug, V: std::fmt::Debug, S> std::fmt::Debug for Node<K, V, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
@@ -464,87 +1108,626 @@ impl std::fmt::Debug for Node {
     }
 }
 
-impl BPlusTree {
-    pub fn new(numbers: Vec<u32>, max_degree: usize) -> Self {
-        let mut tree = Self {
-            root: None,
-            max_degree,
-        };
+impl<K: Ord + Clone + 'static> BPlusTree<K, K> {
+    pub fn new(numbers: Vec<K>, max_degree: usize) -> Self {
+        let mut tree = Self::with_max_degree(max_degree);
 
         for i in numbers {
-            tree.insert(i);
+            tree.insert(i.clone(), i);
+        }
+
+        tree
+    }
+}
+
+impl<K: Ord + Clone + 'static, V> BPlusTree<K, V> {
+    pub fn with_max_degree(max_degree: usize) -> Self {
+        Self::with_comparator(max_degree, Natural)
+    }
+
+    pub fn bulk_load(entries: Vec<(K, V)>, max_degree: usize) -> Self {
+        Self::bulk_load_with(entries, max_degree, Natural)
+    }
+}
+
+impl<K: Ord + Clone + 'static, V, S: Summary<K, V>> BPlusTree<K, V, Natural, S> {
+    // Tree keeping the summary `S` of the subtree of each child, e.g.
+    //
+    //   let tree: BPlusTree<u64, u64, Natural, Sum<u64>> = BPlusTree::with_summary(8);
+    //
+    // see `src/summary.rs`.
+    pub fn with_summary(max_degree: usize) -> Self {
+        Self::with_summary_and_comparator(max_degree, Natural)
+    }
+
+    // Entries whose key starts with the prefix, e.g. every `(tenant_id,
+    // timestamp)` of a tenant, see `src/composite.rs`. Keys sharing a prefix
+    // are next to each other in the `Ord` order only, so the tree has to be
+    // in that order.
+    pub fn range_prefix<'a, P>(&'a self, prefix: &'a P) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: Prefix<P>,
+    {
+        self.seek(|key| key.cmp_prefix(prefix) == Ordering::Less)
+            .take_while(move |(key, _)| key.cmp_prefix(prefix) == Ordering::Equal)
+    }
+}
+
+impl<K: Clone, V, C: Comparator<K>> BPlusTree<K, V, C> {
+    // Tree with the keys ordered by `comparator` instead of their `Ord`.
+    pub fn with_comparator(max_degree: usize, comparator: C) -> Self {
+        Self::with_summary_and_comparator(max_degree, comparator)
+    }
+
+    // Only in a tree without summaries, which a value changed in place would
+    // leave stale. A cursor changes the value of a tree with summaries, see
+    // `Cursor::update_current`.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let id = self.find_leaf(key)?;
+        let leaf = &mut self.nodes[id];
+        let index = self.comparator.search(&leaf.keys, key).ok()?;
+        Some(&mut leaf.values[index])
+    }
+
+    // Build the tree bottom up instead of inserting the entries one by one:
+    //
+    //   [1, 2, 3, 4, 5, 6, 7], max degree 4
+    //
+    //          [3, 5]
+    //   [1, 2]  [3, 4]  [5, 6, 7]
+    //
+    // The entries are packed in as few leaves as possible, then the leaves in
+    // as few parents as possible, until only the root is left. Each level is
+    // split evenly, so no node end up with less than the minimum number of
+    // keys. Like `insert`, only the first value of a duplicated key is kept.
+    pub fn bulk_load_with(mut entries: Vec<(K, V)>, max_degree: usize, comparator: C) -> Self {
+        entries.sort_by(|a, b| comparator.compare(&a.0, &b.0));
+        entries.dedup_by(|next, previous| comparator.compare(&next.0, &previous.0).is_eq());
+
+        let mut tree = Self::with_comparator(max_degree, comparator);
+        let nodes = &mut tree.nodes;
+
+        // Each node along with the smallest key of its subtree, which is the
+        // key its parent use to separate it from its left sibling.
+        let mut level: Vec<(K, NodeId)> = split_evenly(entries, max_degree - 1)
+            .into_iter()
+            .map(|entries| {
+                let mut leaf = Node::new(true);
+                (leaf.keys, leaf.values) = entries.into_iter().unzip();
+                (leaf.keys[0].clone(), nodes.alloc(leaf))
+            })
+            .collect();
+
+        for pair in level.windows(2) {
+            nodes[pair[0].1].next = Some(pair[1].1);
         }
 
+        while level.len() > 1 {
+            level = split_evenly(level, max_degree)
+                .into_iter()
+                .map(|childrens| {
+                    let mut node = Node::new(false);
+                    let mut childrens = childrens.into_iter();
+                    let (min_key, first) = childrens.next().unwrap();
+
+                    node.childrens.push(first);
+                    for (key, child) in childrens {
+                        node.keys.push(key);
+                        node.childrens.push(child);
+                    }
+                    node.summaries = vec![(); node.childrens.len()];
+                    (min_key, nodes.alloc(node))
+                })
+                .collect();
+        }
+
+        tree.root = level.pop().map(|(_, root)| root);
         tree
     }
+}
 
-    pub fn insert(&mut self, key: u32) {
-        if let Some(node) = self.root.as_mut() {
-            node.insert_non_full(key, self.max_degree);
+impl<K: Clone, V, C: Comparator<K>, S: Summary<K, V>> BPlusTree<K, V, C, S> {
+    // Tree with the keys ordered by `comparator`, keeping the summary `S` of
+    // the subtree of each child, see `src/summary.rs`.
+    pub fn with_summary_and_comparator(max_degree: usize, comparator: C) -> Self {
+        Self {
+            nodes: Arena::new(),
+            root: None,
+            max_degree,
+            counters: Counters::default(),
+            comparator,
+        }
+    }
 
-            if node.keys.len() == self.max_degree {
-                let mut new_root = Node::new(false);
-                new_root.childrens.push(self.root.take().unwrap());
-                new_root.split_child(0, self.max_degree);
+    pub fn insert(&mut self, key: K, value: V) {
+        if let Some(root) = self.root {
+            self.insert_non_full(root, key, value);
+
+            if self.nodes[root].keys.len() == self.max_degree {
+                let new_root = self.new_root(root);
+                self.split_child(new_root, 0);
                 self.root = Some(new_root);
             }
         } else {
-            let mut node = Node::new(true);
-            node.insert_non_full(key, self.max_degree);
-            self.root = Some(node);
+            let root = self.nodes.alloc(Node::new(true));
+            self.root = Some(root);
+            self.insert_non_full(root, key, value);
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let root = self.root?;
+        let result = self.remove_from(root, key);
+
+        // The root lost its last key, either the tree is empty or the root
+        // has a single child left which takes its place.
+        if self.nodes[root].keys.is_empty() {
+            let node = self.nodes.free(root);
+            self.root = node.childrens.first().copied();
+        }
+
+        result
+    }
+
+    // Remove every entry in the range, returning how many. Rather than
+    // removing them one by one, the subtrees entirely in the range are freed
+    // whole, and only the nodes along the paths to the first and the last key
+    // of the range are trimmed and rebalanced.
+    pub fn delete_range<R: RangeBounds<K>>(&mut self, range: R) -> usize {
+        let Some(root) = self.root else {
+            return 0;
+        };
+        let ends = Ends::new(&range);
+        let (after_start, before_end) = (ends.unbounded_start(), ends.unbounded_end());
+        if after_start && before_end {
+            self.root = None;
+            return self.free_subtree(root);
         }
+        let removed = self.delete_range_from(root, &ends, after_start, before_end);
+        self.collapse_root();
+        removed
     }
 
-    pub fn remove(&mut self, key: &u32) -> Option<u32> {
-        if let Some(node) = self.root.as_mut() {
-            let result = node.remove(key, self.max_degree);
+    // Summary of the entries in the range, see `src/summary.rs`. Like
+    // `delete_range`, only the nodes along the paths to the first and the
+    // last key of the range are visited, the childrens in between are
+    // entirely in the range and their summary is in their parent.
+    pub fn aggregate<R: RangeBounds<K>>(&self, range: R) -> S {
+        let Some(root) = self.root else {
+            return S::identity();
+        };
+        let ends = Ends::new(&range);
+        let (after_start, before_end) = (ends.unbounded_start(), ends.unbounded_end());
+        self.aggregate_from(root, &ends, after_start, before_end)
+    }
+
+    // Insert many entries at once, sorted first then pushed down the tree
+    // together, so each node is visited and split at most once instead of
+    // once per entry. Like `insert`, a key already in the tree keeps its
+    // value, as does the first of a key given many times.
+    pub fn insert_batch<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        let mut entries: Vec<(K, V)> = entries.into_iter().collect();
+        entries.sort_by(|a, b| self.comparator.compare(&a.0, &b.0));
+        entries.dedup_by(|next, previous| self.comparator.compare(&next.0, &previous.0).is_eq());
+        if entries.is_empty() {
+            return;
+        }
+
+        let mut root = match self.root {
+            Some(root) => root,
+            None => self.nodes.alloc(Node::new(true)),
+        };
+        self.insert_batch_into(root, entries);
+
+        // The root may split in more nodes than fit in a single new root.
+        while self.nodes[root].keys.len() >= self.max_degree {
+            root = self.new_root(root);
+            self.split_child_evenly(root, 0);
+        }
+        self.root = Some(root);
+    }
+
+    // Remove many keys at once, returning how many were in the tree. The
+    // keys are sorted first then pushed down the tree together, so each node
+    // is visited and rebalanced once instead of once per key.
+    pub fn remove_batch<I: IntoIterator<Item = K>>(&mut self, keys: I) -> usize {
+        let mut keys: Vec<K> = keys.into_iter().collect();
+        keys.sort_by(|a, b| self.comparator.compare(a, b));
+        keys.dedup_by(|next, previous| self.comparator.compare(next, previous).is_eq());
+        let Some(root) = self.root else {
+            return 0;
+        };
+
+        let removed = self.remove_batch_from(root, &keys);
+        self.collapse_root();
+        removed
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let leaf = &self.nodes[self.find_leaf(key)?];
+        let index = self.comparator.search(&leaf.keys, key).ok()?;
+        Some(&leaf.values[index])
+    }
+}
 
-            if node.keys.is_empty() {
-                self.root = node.childrens.pop()
+// Only trees in the `Ord` order of their keys are serialized, the order they
+// are read back in.
+impl<K: Codec + Ord + Clone + 'static, V: Codec> BPlusTree<K, V> {
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), serialization::Error> {
+        let degree = self.max_degree as u32;
+        serialization::write_header(&mut writer, Kind::BPlusTree, degree, K::ID, V::ID)?;
+
+        match self.root {
+            Some(root) => {
+                serialization::write_u8(&mut writer, 1)?;
+                self.nodes[root].write_to(&self.nodes, &mut writer)?;
             }
+            None => serialization::write_u8(&mut writer, 0)?,
+        }
+        Ok(())
+    }
 
-            result
-        } else {
-            None
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, serialization::Error>
+    where
+        K: Debug,
+    {
+        let degree = serialization::read_header(&mut reader, Kind::BPlusTree, K::ID, V::ID)?;
+        if degree < 3 {
+            return Err(serialization::Error::UnexpectedDegree(degree));
         }
+
+        let mut tree = Self::with_max_degree(degree as usize);
+        tree.root = match serialization::read_u8(&mut reader)? {
+            0 => None,
+            1 => Some(Node::read_from(
+                &mut reader,
+                tree.max_degree,
+                &mut tree.nodes,
+                &mut None,
+            )?),
+            _ => return Err(serialization::Error::Corrupted("invalid root flag")),
+        };
+
+        // Same as `BTree::read_from`, each node has a valid shape but not
+        // the tree as a whole.
+        tree.validate()
+            .map_err(|_| serialization::Error::Corrupted("invalid tree"))?;
+        Ok(tree)
     }
+}
+
+impl<K: Debug, V: Debug, C, S> BPlusTree<K, V, C, S> {
+    // Graphviz graph of the tree, see `src/dot.rs`. The leaves are kept on
+    // the same rank, linked to their next sibling with a dashed edge.
+    pub fn to_dot(&self) -> String {
+        use std::fmt::Write as _;
 
-    pub fn get(&self, key: &u32) -> Option<&u32> {
-        self.root.as_ref().map_or(None, |node| node.search(key))
+        dot::render("BPlusTree", |dot| {
+            let mut leaves = vec![];
+            if let Some(root) = self.root {
+                self.nodes[root].write_dot(&self.nodes, dot, &mut 0, &mut leaves)?;
+            }
+
+            for pair in leaves.windows(2) {
+                writeln!(
+                    dot,
+                    "  node{} -> node{} [style=dashed, constraint=false];",
+                    pair[0], pair[1]
+                )?;
+            }
+            if leaves.len() > 1 {
+                let leaves: Vec<_> = leaves.iter().map(|id| format!("node{id}")).collect();
+                writeln!(dot, "  {{ rank=same; {} }}", leaves.join("; "))?;
+            }
+            Ok(())
+        })
     }
+}
 
-    pub fn print(&self) {
-        if let Some(node) = &self.root {
-            let mut queue = VecDeque::new();
-            queue.push_front(node);
-            let mut visited_child = 0;
-            let mut num_of_childs = 1;
-            let mut next_to_visit = 0;
-
-            while let Some(node) = queue.pop_back() {
-                print!(" {:?} ", node.keys);
-                // println!("{:?}: {:?}", node.keys, node.childrens);
-                visited_child += 1;
-
-                for c in &node.childrens {
-                    queue.push_front(c);
-                    next_to_visit += 1;
-                }
+impl<K: Debug, V, S: Summary<K, V> + PartialEq + Debug> Node<K, V, S> {
+    // Check the node and its subtree, where every key must be at least `min`
+    // and less than `max`. Returns the height of the subtree, and push the
+    // leaves from left to right.
+    fn validate<C: Comparator<K>>(
+        &self,
+        tree: &BPlusTree<K, V, C, S>,
+        is_root: bool,
+        min: Option<&K>,
+        max: Option<&K>,
+        leaves: &mut Vec<NodeId>,
+    ) -> Result<usize, String> {
+        let (keys, comparator, max_degree) = (&self.keys, &tree.comparator, tree.max_degree);
+
+        if keys.len() >= max_degree {
+            return Err(format!("{keys:?} has too many keys"));
+        }
+        if !is_root && keys.len() < min_keys(max_degree) {
+            return Err(format!("{keys:?} has too few keys"));
+        }
+        if keys
+            .windows(2)
+            .any(|pair| comparator.compare(&pair[0], &pair[1]).is_ge())
+        {
+            return Err(format!("{keys:?} is not sorted"));
+        }
+        let out_of_range = |key: &K| {
+            min.is_some_and(|min| comparator.compare(key, min).is_lt())
+                || max.is_some_and(|max| comparator.compare(key, max).is_ge())
+        };
+        if keys.iter().any(out_of_range) {
+            return Err(format!("{keys:?} is not in between {min:?} and {max:?}"));
+        }
 
-                if num_of_childs == visited_child {
-                    println!("");
-                    visited_child = 0;
-                    num_of_childs = next_to_visit;
-                    next_to_visit = 0;
-                }
+        if self.is_leaf {
+            if !self.childrens.is_empty() || keys.len() != self.values.len() {
+                return Err(format!("leaf {keys:?} has childrens or missing values"));
+            }
+            return Ok(0);
+        }
+
+        if keys.is_empty() || self.childrens.len() != keys.len() + 1 || !self.values.is_empty() {
+            return Err(format!("{keys:?} has {} childrens", self.childrens.len()));
+        }
+
+        let mut height = None;
+        for (i, &child) in self.childrens.iter().enumerate() {
+            let min = if i == 0 { min } else { Some(&keys[i - 1]) };
+            let max = keys.get(i).or(max);
+            let child_height = tree.nodes[child].validate(tree, false, min, max, leaves)?;
+            if child_height == 0 {
+                leaves.push(child);
+            }
+
+            if *height.get_or_insert(child_height) != child_height {
+                return Err(format!("childrens of {keys:?} are not at the same height"));
+            }
+
+            let summary = tree.summarize(child);
+            if self.summaries.get(i) != Some(&summary) {
+                return Err(format!(
+                    "summary of child {i} of {keys:?} is {:?} instead of {summary:?}",
+                    self.summaries.get(i)
+                ));
             }
         }
+        if self.summaries.len() != self.childrens.len() {
+            return Err(format!("{keys:?} has {} summaries", self.summaries.len()));
+        }
+        Ok(height.unwrap() + 1)
+    }
+}
+
+impl<K, V, S> Node<K, V, S> {
+    fn block<F: Fn(&K) -> String>(&self, nodes: &Arena<Node<K, V, S>>, format_key: &F) -> Block {
+        let keys: Vec<String> = self.keys.iter().map(format_key).collect();
+        let childrens = self
+            .childrens
+            .iter()
+            .map(|&child| nodes[child].block(nodes, format_key))
+            .collect();
+
+        Block::node(format!("[{}]", keys.join(", ")), childrens)
+    }
+
+    fn node_count(&self, nodes: &Arena<Node<K, V, S>>) -> usize {
+        1 + self
+            .childrens
+            .iter()
+            .map(|&child| nodes[child].node_count(nodes))
+            .sum::<usize>()
+    }
+
+    fn add_stats(&self, nodes: &Arena<Node<K, V, S>>, stats: &mut Stats, max_degree: usize) {
+        stats.add_node(self.keys.len(), max_degree - 1, self.is_leaf);
+        stats.heap_bytes.keys += HeapBytes::of(&self.keys);
+        stats.heap_bytes.values += HeapBytes::of(&self.values);
+        stats.heap_bytes.childrens += HeapBytes::of(&self.childrens);
+
+        for &child in &self.childrens {
+            nodes[child].add_stats(nodes, stats, max_degree);
+        }
+    }
+}
+
+impl<K, V, C, S> BPlusTree<K, V, C, S> {
+    pub fn iter(&self) -> Iter<'_, K, V, S> {
+        let mut leaf = self.root.map(|root| &self.nodes[root]);
+        while let Some(&first) = leaf.and_then(|node| node.childrens.first()) {
+            leaf = Some(&self.nodes[first]);
+        }
+
+        Iter {
+            nodes: &self.nodes,
+            leaf,
+            index: 0,
+        }
+    }
+
+    // Entries in the range, which can also be iterated backward with
+    // `rev()`. Going down to the first and the last entry in the range only
+    // visits the childrens which may hold keys in the range.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V, C, S>
+    where
+        K: Clone,
+        C: Comparator<K>,
+    {
+        let ends = Ends::new(&range);
+        let front = self.seek(|key| ends.before_start(&self.comparator, key));
+
+        // The back continues with the childrens before the one it goes down
+        // to.
+        let mut back = vec![];
+        let not_after_end = |key: &K| !ends.after_end(&self.comparator, key);
+        let mut node = self.root.map(|root| &self.nodes[root]);
+        while let Some(current) = node {
+            let index = current.keys.partition_point(not_after_end);
+            back.push((current, index));
+            node = current
+                .childrens
+                .get(index)
+                .map(|&child| &self.nodes[child]);
+        }
+
+        Range {
+            front,
+            back,
+            comparator: &self.comparator,
+            ends,
+        }
+    }
+
+    // Iterator from the first key which isn't `before`, where `before` is
+    // true for every key up to some key in the order of the tree, and false
+    // from there. Goes down to the leaf of that key, the right child of the
+    // last separator before it.
+    pub(crate) fn seek<F: Fn(&K) -> bool>(&self, before: F) -> Iter<'_, K, V, S> {
+        let mut leaf = self.root.map(|root| &self.nodes[root]);
+        while let Some(node) = leaf.filter(|node| !node.is_leaf) {
+            leaf = Some(&self.nodes[node.childrens[node.keys.partition_point(&before)]]);
+        }
+
+        Iter {
+            nodes: &self.nodes,
+            leaf,
+            index: leaf.map_or(0, |leaf| leaf.keys.partition_point(&before)),
+        }
+    }
+
+    // Number of levels, 0 for an empty tree. Every leaf is at the same depth,
+    // so only follow the first child.
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut node = self.root;
+        while let Some(id) = node {
+            height += 1;
+            node = self.nodes[id].childrens.first().copied();
+        }
+        height
+    }
+
+    // Every node of the arena is in the tree.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // The nodes are in the arena, which count in `heap_bytes.nodes` along
+    // with its freed slots.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            height: self.height(),
+            counters: self.counters,
+            ..Stats::default()
+        };
+        stats.heap_bytes.nodes = self.nodes.heap_bytes();
+        if let Some(root) = self.root {
+            self.nodes[root].add_stats(&self.nodes, &mut stats, self.max_degree);
+        }
+        stats
+    }
+
+    // Draw the tree, formatting each key with `format_key`.
+    pub fn render_with<F: Fn(&K) -> String>(&self, format_key: F) -> Rendered {
+        Rendered::from(
+            self.root
+                .map(|root| self.nodes[root].block(&self.nodes, &format_key)),
+        )
+    }
+}
+
+impl<K: Display, V, C, S> Display for BPlusTree<K, V, C, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render_with(K::to_string))
+    }
+}
+
+impl<K: Debug, V, C: Comparator<K>, S: Summary<K, V> + PartialEq + Debug> BPlusTree<K, V, C, S> {
+    // Check the number of keys of every node, that the keys are ordered and
+    // that every leaf is at the same height. Also check that the leaves are
+    // linked from left to right, that the summaries are up to date, and that
+    // every node of the arena is in the tree.
+    pub fn validate(&self) -> Result<(), String> {
+        let Some(root) = self.root else {
+            return match self.nodes.len() {
+                0 => Ok(()),
+                nodes => Err(format!("empty tree has {nodes} nodes")),
+            };
+        };
+
+        let mut leaves = vec![];
+        let node = &self.nodes[root];
+        if node.validate(self, true, None, None, &mut leaves)? == 0 {
+            leaves.push(root);
+        }
+
+        for (i, &leaf) in leaves.iter().enumerate() {
+            let next = leaves.get(i + 1).copied();
+            if self.nodes[leaf].next != next {
+                let keys = &self.nodes[leaf].keys;
+                return Err(format!("leaf {keys:?} is not linked to the next one"));
+            }
+        }
+
+        let reachable = node.node_count(&self.nodes);
+        if reachable != self.nodes.len() {
+            return Err(format!(
+                "{} nodes but only {reachable} in the tree",
+                self.nodes.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<K: Debug, V, C, S> BPlusTree<K, V, C, S> {
+    pub fn print(&self) {
+        print!("{}", self.render_with(|key| format!("{key:?}")));
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::BPlusTree;
+    use super::{min_keys, BPlusTree};
+    use crate::arena::NodeId;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    // Check the number of keys and childrens, returning the height.
+    fn check(tree: &BPlusTree<u32, u32>, id: NodeId, is_root: bool) -> usize {
+        let (node, max_degree) = (&tree.nodes[id], tree.max_degree);
+        assert!(node.keys.len() < max_degree);
+        assert!(is_root || node.keys.len() >= min_keys(max_degree));
+
+        if node.is_leaf {
+            assert_eq!(node.keys.len(), node.values.len());
+            return 0;
+        }
+
+        assert_eq!(node.childrens.len(), node.keys.len() + 1);
+        let height = check(tree, node.childrens[0], false);
+        for &child in &node.childrens {
+            assert_eq!(check(tree, child, false), height);
+        }
+        height + 1
+    }
+
+    #[test]
+    fn bulk_load_any_number_of_keys() {
+        for max_degree in 3..8 {
+            for n in 0..200 {
+                let entries = (0..n).rev().chain(0..n).map(|i| (i, i)).collect();
+                let tree = BPlusTree::bulk_load(entries, max_degree);
+                if let Some(root) = tree.root {
+                    check(&tree, root, true);
+                }
+
+                assert!(tree.iter().map(|(k, _)| *k).eq(0..n));
+                for i in 0..n {
+                    assert_eq!(tree.get(&i), Some(&i));
+                }
+            }
+        }
+    }
 
     #[test]
     fn get_on_empty_tree() {
@@ -556,9 +1739,9 @@ mod test {
     fn insert_on_root_node() {
         let mut tree = BPlusTree::new(vec![], 4);
 
-        tree.insert(1);
-        tree.insert(2);
-        tree.insert(3);
+        tree.insert(1, 1);
+        tree.insert(2, 2);
+        tree.insert(3, 3);
 
         assert_eq!(tree.get(&1), Some(&1));
         assert_eq!(tree.get(&2), Some(&2));
@@ -568,7 +1751,7 @@ mod test {
     #[test]
     fn insert_and_split_on_root_node() {
         let mut tree = BPlusTree::new(vec![7, 10, 15], 4);
-        tree.insert(8);
+        tree.insert(8, 8);
 
         assert_eq!(tree.get(&8), Some(&8));
         assert_eq!(tree.get(&18), None);
@@ -577,7 +1760,7 @@ mod test {
     #[test]
     fn insert_on_leaf_node() {
         let mut tree = BPlusTree::new(vec![7, 10, 15, 8], 4);
-        tree.insert(11);
+        tree.insert(11, 11);
         assert_eq!(tree.get(&11), Some(&11));
     }
 
@@ -585,7 +1768,7 @@ mod test {
     fn insert_and_split_on_leaf_node() {
         let mut tree = BPlusTree::new(vec![7, 10, 15, 8, 11], 4);
 
-        tree.insert(12);
+        tree.insert(12, 12);
         assert_eq!(tree.get(&12), Some(&12));
         assert_eq!(tree.get(&7), Some(&7));
         assert_eq!(tree.get(&8), Some(&8));
@@ -596,7 +1779,7 @@ mod test {
         let vec = vec![7, 10, 15, 8, 11, 12, 19, 25, 30];
         let mut tree = BPlusTree::new(vec.clone(), 4);
 
-        tree.insert(49);
+        tree.insert(49, 49);
         assert_eq!(tree.get(&49), Some(&49));
 
         for v in vec {
@@ -609,7 +1792,7 @@ mod test {
         let vec = vec![7, 10, 15, 8, 11, 12, 19, 25, 30, 49, 69, 90, 59];
         let mut tree = BPlusTree::new(vec.clone(), 4);
 
-        tree.insert(41);
+        tree.insert(41, 41);
         assert_eq!(tree.get(&41), Some(&41));
 
         for v in vec {
@@ -622,7 +1805,7 @@ mod test {
         let vec = vec![7, 10, 15, 8, 11, 12, 19, 25, 30, 49, 69, 90, 59, 41, 45];
         let mut tree = BPlusTree::new(vec.clone(), 4);
 
-        tree.insert(42);
+        tree.insert(42, 42);
         assert_eq!(tree.get(&42), Some(&42));
         for v in vec {
             assert_eq!(tree.get(&v), Some(&v));
@@ -637,7 +1820,7 @@ mod test {
         ];
         let mut tree = BPlusTree::new(vec.clone(), 4);
 
-        tree.insert(35);
+        tree.insert(35, 35);
         assert_eq!(tree.get(&35), Some(&35));
 
         for v in vec {
@@ -650,7 +1833,7 @@ mod test {
         let vec: Vec<u32> = (1..82).collect();
         let mut tree = BPlusTree::new(vec.clone(), 4);
 
-        tree.insert(82);
+        tree.insert(82, 82);
         assert_eq!(tree.get(&82), Some(&82));
 
         for v in vec {
@@ -658,6 +1841,162 @@ mod test {
         }
     }
 
+    #[test]
+    fn validate() {
+        let mut tree = BPlusTree::new((1..=20).collect(), 4);
+        assert_eq!(tree.validate(), Ok(()));
+
+        let child = tree.nodes[tree.root.unwrap()].childrens[0];
+        tree.nodes[child].keys.insert(0, 100);
+        assert!(tree.validate().is_err());
+    }
+
+    #[test]
+    fn height_and_node_count() {
+        let tree: BPlusTree = BPlusTree::with_max_degree(4);
+        assert_eq!(tree.height(), 0);
+        assert_eq!(tree.node_count(), 0);
+
+        // [3, 5] over [1, 2] [3, 4] [5, 6, 7]
+        let tree = BPlusTree::bulk_load((1..=7).map(|i| (i, i)).collect(), 4);
+        assert_eq!(tree.height(), 2);
+        assert_eq!(tree.node_count(), 4);
+    }
+
+    #[test]
+    fn stats() {
+        // [3, 5] over [1, 2] [3, 4] [5, 6, 7]
+        let stats = BPlusTree::bulk_load((1..=7).map(|i| (i, i)).collect(), 4).stats();
+        assert_eq!(
+            (stats.height, stats.internal_nodes, stats.leaf_nodes),
+            (2, 1, 3)
+        );
+        assert_eq!((stats.keys, stats.min_keys, stats.max_keys), (9, 2, 3));
+        assert_eq!(stats.fill_factors, [0, 0, 0, 0, 0, 0, 3, 0, 0, 1]);
+        assert!(stats.heap_bytes.values >= 7 * 4);
+        assert!(stats.heap_bytes.nodes >= 4 * std::mem::size_of::<super::Node<u32, u32>>());
+
+        let mut tree = BPlusTree::with_max_degree(3);
+        for i in 1..=4 {
+            tree.insert(i, i);
+        }
+        assert_eq!(tree.stats().counters.splits, 2);
+        tree.remove(&4);
+        tree.remove(&3);
+        assert!(tree.stats().counters.merges > 0);
+    }
+
+    #[test]
+    fn display() {
+        let tree = BPlusTree::new((1..=7).collect(), 4);
+
+        assert_eq!(
+            tree.to_string(),
+            "         [3, 5]
+   ┌────────┼─────────┐
+[1, 2]   [3, 4]   [5, 6, 7]
+"
+        );
+
+        let hex = tree.render_with(|key| format!("{key:#x}")).to_string();
+        assert!(hex.ends_with("[0x5, 0x6, 0x7]\n"));
+    }
+
+    #[test]
+    fn to_dot() {
+        let entries = (1..=5).map(|i| (i, format!("v{i}"))).collect();
+        let tree = BPlusTree::bulk_load(entries, 4);
+
+        assert_eq!(
+            tree.to_dot(),
+            r#"digraph BPlusTree {
+  node [shape=record, height=0.1];
+  node0 [label="<c0>|3|<c1>"];
+  node1 [label="{1|\"v1\"}|{2|\"v2\"}"];
+  node0:c0 -> node1;
+  node2 [label="{3|\"v3\"}|{4|\"v4\"}|{5|\"v5\"}"];
+  node0:c1 -> node2;
+  node1 -> node2 [style=dashed, constraint=false];
+  { rank=same; node1; node2 }
+}
+"#
+        );
+    }
+
+    #[test]
+    fn write_and_read_the_same_shape() {
+        let mut tree = BPlusTree::with_max_degree(4);
+        for i in 1..20u32 {
+            tree.insert(i, format!("v{i}"));
+        }
+        tree.remove(&1);
+        tree.remove(&2);
+        tree.remove(&3);
+
+        let mut bytes = vec![];
+        tree.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes, include_bytes!("../fixtures/bplustree_v1.bin"));
+
+        let tree = BPlusTree::<u32, String>::read_from(bytes.as_slice()).unwrap();
+        let mut again = vec![];
+        tree.write_to(&mut again).unwrap();
+        assert_eq!(bytes, again);
+        assert_eq!(tree.get(&4), Some(&"v4".to_string()));
+        assert_eq!(tree.get(&3), None);
+
+        // Can't be read back with a different value type.
+        assert!(BPlusTree::<u32, u32>::read_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn reject_corrupted_nodes() {
+        use crate::serialization::Error;
+
+        let read = |bytes: &[u8]| match BPlusTree::<u32, u32>::read_from(bytes) {
+            Err(Error::Corrupted(reason)) => reason,
+            Err(error) => panic!("{error}"),
+            Ok(_) => panic!("read a corrupted tree"),
+        };
+
+        // Header, root flag, then the leaf: flag at 13, 1 key, 1 value at 22,
+        // no childrens.
+        let mut bytes = vec![];
+        BPlusTree::new(vec![7u32], 4).write_to(&mut bytes).unwrap();
+        assert_eq!(bytes[13], 1);
+
+        let mut corrupted = bytes.clone();
+        corrupted[13] = 2;
+        assert_eq!(read(&corrupted), "invalid leaf flag");
+
+        // An internal node without childrens, with or without the value.
+        let mut corrupted = bytes.clone();
+        corrupted[13] = 0;
+        assert_eq!(read(&corrupted), "invalid number of values");
+        corrupted[22] = 0;
+        corrupted.drain(26..30);
+        assert_eq!(read(&corrupted), "invalid number of childrens");
+
+        // A leaf missing its value.
+        corrupted[13] = 1;
+        assert_eq!(read(&corrupted), "invalid number of values");
+
+        // The fixture's root, an internal node, read as a leaf.
+        let mut corrupted = include_bytes!("../fixtures/bplustree_v1.bin").to_vec();
+        corrupted[13] = 1;
+        assert!(matches!(
+            BPlusTree::<u32, String>::read_from(corrupted.as_slice()),
+            Err(Error::Corrupted("invalid number of values"))
+        ));
+
+        // Keys out of order in a leaf of a valid shape.
+        let mut bytes = vec![];
+        BPlusTree::new(vec![7u32, 8], 4)
+            .write_to(&mut bytes)
+            .unwrap();
+        bytes[18..22].copy_from_slice(&9u32.to_le_bytes());
+        assert_eq!(read(&bytes), "invalid tree");
+    }
+
     #[test]
     fn delete_key_on_root_node() {
         let mut tree = BPlusTree::new(vec![2, 7, 8], 4);
@@ -1027,6 +2366,429 @@ mod test {
         }
     }
 
+    #[test]
+    fn random_inserts_and_removes() {
+        for max_degree in 3..=8 {
+            for seed in 0..20 {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let mut tree = BPlusTree::with_max_degree(max_degree);
+                let mut map = BTreeMap::new();
+
+                for _ in 0..500 {
+                    let key: u32 = rng.gen_range(0..200);
+                    if rng.gen_bool(0.6) {
+                        tree.insert(key, key + 1);
+                        map.entry(key).or_insert(key + 1);
+                    } else {
+                        assert_eq!(tree.remove(&key), map.remove(&key), "remove {key}");
+                    }
+                    assert_eq!(tree.validate(), Ok(()), "degree {max_degree}, seed {seed}");
+                }
+                assert!(tree.iter().eq(map.iter()));
+            }
+        }
+    }
+
+    #[test]
+    fn case_insensitive_keys() {
+        let mut tree = BPlusTree::with_comparator(4, |a: &String, b: &String| {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        });
+        for (i, key) in ["b", "A", "c", "B", "d", "a", "E"].into_iter().enumerate() {
+            tree.insert(key.to_string(), i);
+        }
+
+        assert_eq!(tree.validate(), Ok(()));
+        let keys: Vec<&str> = tree.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["A", "b", "c", "d", "E"]);
+        assert_eq!(tree.get(&"C".to_string()), Some(&2));
+        assert_eq!(tree.remove(&"a".to_string()), Some(1));
+        assert_eq!(tree.get(&"A".to_string()), None);
+    }
+
+    #[test]
+    fn float_keys_with_total_order() {
+        let keys = [2.5, -0.0, f64::NAN, 0.0, f64::NEG_INFINITY, -1.0, 10.0];
+        let entries = keys.iter().map(|&key| (key, ())).collect();
+        let tree = BPlusTree::bulk_load_with(entries, 3, f64::total_cmp);
+
+        assert_eq!(tree.validate(), Ok(()));
+        let mut sorted = keys.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        assert!(tree
+            .iter()
+            .map(|(key, _)| key.to_bits())
+            .eq(sorted.iter().map(|key| key.to_bits())));
+        assert!(tree.get(&f64::NAN).is_some());
+        assert!(tree.get(&0.0).is_some() && tree.get(&-0.0).is_some());
+    }
+
+    #[test]
+    fn cursor_moves_both_ways() {
+        let mut tree = BPlusTree::bulk_load((0..100).map(|key| (key * 2, key)).collect(), 4);
+        let mut cursor = tree.cursor();
+        assert_eq!(cursor.key(), Some(&0));
+        assert!(!cursor.prev());
+        assert_eq!(cursor.key(), None);
+
+        assert!(cursor.seek(&50));
+        assert!(!cursor.seek(&51));
+        assert_eq!(cursor.value(), Some(&26));
+        assert!(!cursor.seek(&199));
+        assert_eq!(cursor.key(), None);
+
+        cursor.seek_last();
+        let mut keys = vec![];
+        while let Some(&key) = cursor.key() {
+            keys.push(key);
+            cursor.prev();
+        }
+        assert!(keys.into_iter().eq((0..100).rev().map(|key| key * 2)));
+
+        cursor.seek_first();
+        let mut keys = vec![];
+        while let Some(&key) = cursor.key() {
+            keys.push(key);
+            cursor.next();
+        }
+        assert!(keys.into_iter().eq((0..100).map(|key| key * 2)));
+        assert!(BPlusTree::<u32, u32>::with_max_degree(4)
+            .cursor()
+            .key()
+            .is_none());
+    }
+
+    #[test]
+    fn cursor_changes_entries() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for max_degree in [3, 4, 5, 8] {
+            let mut tree = BPlusTree::with_max_degree(max_degree);
+            let mut map = BTreeMap::new();
+            for _ in 0..300 {
+                let key = rng.gen_range(0..1000u32);
+                tree.insert(key, key);
+                map.insert(key, key);
+            }
+
+            let mut cursor = tree.cursor();
+            for _ in 0..1000 {
+                let key = rng.gen_range(0..1000);
+                let mut expected = map.range(key..).next();
+                assert_eq!(cursor.seek(&key), map.contains_key(&key));
+                assert_eq!(cursor.key(), expected.map(|(key, _)| key));
+
+                match rng.gen_range(0..4) {
+                    0 => {
+                        cursor.next();
+                        expected = expected.and_then(|(&key, _)| map.range(key + 1..).next());
+                    }
+                    1 if expected.is_some() => {
+                        cursor.prev();
+                        expected = map.range(..key).next_back();
+                    }
+                    2 if expected.is_some() => {
+                        let key = *expected.unwrap().0;
+                        assert_eq!(cursor.update_current(key + 1), map.insert(key, key + 1));
+                        expected = map.get_key_value(&key);
+                    }
+                    _ if expected.is_some() => {
+                        let key = *expected.unwrap().0;
+                        assert_eq!(cursor.delete_current(), map.remove_entry(&key));
+                        expected = map.range(key..).next();
+                    }
+                    _ => {}
+                }
+                assert_eq!(
+                    cursor.key().zip(cursor.value()),
+                    expected,
+                    "max degree {max_degree}"
+                );
+            }
+            assert_eq!(tree.validate(), Ok(()));
+            assert!(tree.iter().eq(map.iter()));
+        }
+    }
+
+    #[test]
+    fn cursor_deletes_every_entry() {
+        let mut tree = BPlusTree::bulk_load((0..200).map(|key| (key, key)).collect(), 4);
+        let mut cursor = tree.cursor();
+        cursor.seek(&50);
+        while cursor.key().is_some_and(|key| *key < 150) {
+            cursor.delete_current();
+        }
+        assert_eq!(cursor.key(), Some(&150));
+        cursor.seek_first();
+        while cursor.delete_current().is_some() {}
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.root, None);
+    }
+
+    // The summaries stay up to date through every change, which `validate`
+    // checks, and the summary of a range is the one of its entries.
+    #[test]
+    fn aggregate_over_ranges() {
+        use crate::comparator::Natural;
+        use crate::summary::{Count, Max, Min, Sum};
+        use std::ops::Bound::{Excluded, Included, Unbounded};
+        use std::ops::RangeBounds;
+
+        type Stats = (Count, Sum<u64>, Min<u64>, Max<u64>);
+        let mut rng = StdRng::seed_from_u64(0);
+        let bound = |rng: &mut StdRng| match rng.gen_range(0..3) {
+            0 => Included(rng.gen_range(0..1100)),
+            1 => Excluded(rng.gen_range(0..1100)),
+            _ => Unbounded,
+        };
+
+        for max_degree in 3..=8 {
+            let mut tree: BPlusTree<u32, u64, Natural, Stats> = BPlusTree::with_summary(max_degree);
+            let mut map = BTreeMap::new();
+            for _ in 0..300 {
+                let key = rng.gen_range(0..1000);
+                match rng.gen_range(0..6) {
+                    0 | 1 => {
+                        let value = rng.gen_range(0..1000);
+                        tree.insert(key, value);
+                        map.entry(key).or_insert(value);
+                    }
+                    2 => assert_eq!(tree.remove(&key), map.remove(&key)),
+                    3 => {
+                        let entries: Vec<(u32, u64)> = (0..rng.gen_range(0..50))
+                            .map(|_| (rng.gen_range(0..1000), rng.gen_range(0..1000)))
+                            .collect();
+                        for &(key, value) in &entries {
+                            map.entry(key).or_insert(value);
+                        }
+                        tree.insert_batch(entries);
+                    }
+                    4 => {
+                        let keys: Vec<u32> = (0..rng.gen_range(0..50))
+                            .map(|_| rng.gen_range(0..1000))
+                            .collect();
+                        for key in &keys {
+                            map.remove(key);
+                        }
+                        tree.remove_batch(keys);
+                    }
+                    _ => {
+                        let mut cursor = tree.cursor();
+                        cursor.seek(&key);
+                        if let Some(&key) = cursor.key() {
+                            match rng.gen() {
+                                true => {
+                                    cursor.update_current(7);
+                                    map.insert(key, 7);
+                                }
+                                false => {
+                                    cursor.delete_current();
+                                    map.remove(&key);
+                                }
+                            }
+                        }
+                    }
+                }
+                if rng.gen_range(0..20) == 0 {
+                    let range = (bound(&mut rng), bound(&mut rng));
+                    tree.delete_range(range);
+                    map.retain(|key, _| !range.contains(key));
+                }
+                assert_eq!(tree.validate(), Ok(()));
+
+                let range = (bound(&mut rng), bound(&mut rng));
+                let values: Vec<u64> = map
+                    .iter()
+                    .filter(|(key, _)| range.contains(key))
+                    .map(|(_, value)| *value)
+                    .collect();
+                let expected = (
+                    Count(values.len()),
+                    Sum(values.iter().sum()),
+                    Min(values.iter().min().copied()),
+                    Max(values.iter().max().copied()),
+                );
+                assert_eq!(tree.aggregate(range), expected, "{range:?}");
+            }
+        }
+    }
+
+    // Running sums of metrics bucketed by minute.
+    #[test]
+    fn sum_over_time_buckets() {
+        use crate::comparator::Natural;
+        use crate::summary::Sum;
+
+        let mut tree: BPlusTree<u32, u64, Natural, Sum<u64>> = BPlusTree::with_summary(8);
+        tree.insert_batch((0..10_000).map(|minute| (minute, u64::from(minute % 60))));
+        assert_eq!(
+            tree.aggregate(..),
+            Sum(10_000 / 60 * 1770 + (0..40).sum::<u64>())
+        );
+        assert_eq!(tree.aggregate(60..120), Sum(1770));
+        assert_eq!(tree.aggregate(65..=66), Sum(11));
+        assert_eq!(tree.aggregate(20_000..), Sum(0));
+
+        tree.delete_range(..9_960);
+        assert_eq!(tree.aggregate(..), Sum((0..40).sum()));
+    }
+
+    #[test]
+    fn delete_range() {
+        use std::ops::Bound::{Excluded, Included, Unbounded};
+        use std::ops::RangeBounds;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let bound = |rng: &mut StdRng| match rng.gen_range(0..3) {
+            0 => Included(rng.gen_range(0..1100)),
+            1 => Excluded(rng.gen_range(0..1100)),
+            _ => Unbounded,
+        };
+        for max_degree in 3..=8 {
+            for _ in 0..100 {
+                let len = rng.gen_range(0..1000);
+                let entries = (0..len).map(|key| (key, key)).collect();
+                let mut tree = match rng.gen() {
+                    true => BPlusTree::bulk_load(entries, max_degree),
+                    false => {
+                        let mut tree = BPlusTree::with_max_degree(max_degree);
+                        for (key, value) in entries {
+                            tree.insert(key, value);
+                        }
+                        tree
+                    }
+                };
+                let mut map: BTreeMap<u32, u32> = tree.iter().map(|(&k, &v)| (k, v)).collect();
+
+                for _ in 0..3 {
+                    let range = (bound(&mut rng), bound(&mut rng));
+                    let before = map.len();
+                    map.retain(|key, _| !range.contains(key));
+                    assert_eq!(tree.delete_range(range), before - map.len(), "{range:?}");
+                    assert_eq!(tree.validate(), Ok(()), "{range:?}");
+                    assert!(tree.iter().map(|(k, v)| (*k, *v)).eq(map.clone()));
+                }
+            }
+        }
+    }
+
+    // A large range only rebalances the nodes along its two ends.
+    #[test]
+    fn delete_range_frees_subtrees_whole() {
+        let entries = (0..100_000).map(|key| (key, key)).collect();
+        let mut tree = BPlusTree::bulk_load(entries, 8);
+        let height = tree.height();
+
+        assert_eq!(tree.delete_range(1000..99_000), 98_000);
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree
+            .iter()
+            .map(|(key, _)| *key)
+            .eq((0..1000).chain(99_000..100_000)));
+        let counters = tree.stats().counters;
+        assert!(
+            counters.merges + counters.borrows <= 2 * height as u64,
+            "{counters:?}"
+        );
+
+        assert_eq!(tree.delete_range(..), 2000);
+        assert_eq!((tree.root, tree.node_count()), (None, 0));
+    }
+
+    #[test]
+    fn insert_and_remove_batches() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for max_degree in 3..=8 {
+            let mut tree = BPlusTree::with_max_degree(max_degree);
+            let mut map = BTreeMap::new();
+            for _ in 0..100 {
+                let len = rng.gen_range(0..200);
+                let entries: Vec<(u32, u32)> = (0..len)
+                    .map(|_| (rng.gen_range(0..1000), rng.gen()))
+                    .collect();
+                for &(key, value) in &entries {
+                    map.entry(key).or_insert(value);
+                }
+                tree.insert_batch(entries);
+                assert_eq!(tree.validate(), Ok(()));
+
+                let keys: Vec<u32> = (0..rng.gen_range(0..200))
+                    .map(|_| rng.gen_range(0..1000))
+                    .collect();
+                let before = map.len();
+                for key in &keys {
+                    map.remove(key);
+                }
+                assert_eq!(tree.remove_batch(keys), before - map.len());
+                assert_eq!(tree.validate(), Ok(()));
+                assert!(tree.iter().map(|(k, v)| (*k, *v)).eq(map.clone()));
+            }
+            assert_eq!(tree.remove_batch(0..1000), map.len());
+            assert_eq!((tree.root, tree.node_count()), (None, 0));
+        }
+    }
+
+    // Each node splits at most once per batch, in as many nodes as needed.
+    #[test]
+    fn insert_batch_splits_nodes_once() {
+        let mut tree = BPlusTree::with_max_degree(8);
+        tree.insert_batch((0..10_000).map(|key| (key, key)));
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.iter().count(), 10_000);
+
+        // Every node but the first of each level is split off once, instead of
+        // splitting the last leaf again every 4 keys.
+        let splits = tree.stats().counters.splits;
+        assert_eq!(splits, (tree.node_count() - tree.height()) as u64);
+
+        tree.insert_batch((10_000..10_100).map(|key| (key, key)));
+        let splits = tree.stats().counters.splits - splits;
+        assert!(splits <= 100 / 4 + tree.height() as u64, "{splits}");
+        assert!(tree.iter().map(|(key, _)| *key).eq(0..10_100));
+    }
+
+    #[test]
+    fn range_prefix_of_composite_keys() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for max_degree in [3, 4, 8] {
+            let mut tree = BPlusTree::with_max_degree(max_degree);
+            let mut map = BTreeMap::new();
+            for _ in 0..500 {
+                let key = (
+                    rng.gen_range(0..20u32),
+                    rng.gen_range(0..5u8),
+                    rng.gen::<u64>(),
+                );
+                tree.insert(key, ());
+                map.insert(key, ());
+            }
+            // Removed keys leave their separators in the internal nodes.
+            for _ in 0..200 {
+                let key = *map.keys().nth(rng.gen_range(0..map.len())).unwrap();
+                tree.remove(&key);
+                map.remove(&key);
+            }
+
+            for tenant in 0..21 {
+                assert!(tree
+                    .range_prefix(&tenant)
+                    .map(|(key, _)| key)
+                    .eq(map.keys().filter(|key| key.0 == tenant)));
+                for kind in 0..6 {
+                    assert!(tree
+                        .range_prefix(&(tenant, kind))
+                        .map(|(key, _)| key)
+                        .eq(map.keys().filter(|key| (key.0, key.1) == (tenant, kind))));
+                }
+            }
+        }
+        assert_eq!(
+            BPlusTree::<(u32, u32), ()>::with_max_degree(4)
+                .range_prefix(&1)
+                .count(),
+            0
+        );
+    }
+
     // Use to generate random test case.
     //
     // If a test failed, we would add the test case manually.
@@ -1046,4 +2808,62 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn range_with_every_bound() {
+        use rand::rngs::StdRng;
+        use rand::seq::SliceRandom;
+        use rand::{Rng, SeedableRng};
+        use std::ops::Bound::{Excluded, Included, Unbounded};
+        use std::ops::RangeBounds;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut keys: Vec<u32> = (0..300).step_by(3).collect();
+        keys.shuffle(&mut rng);
+        let mut tree = BPlusTree::with_max_degree(4);
+        for &key in keys.iter().chain(&[1, 2, 4, 5]) {
+            tree.insert(key, key);
+        }
+        for key in [1, 2, 4, 5] {
+            tree.remove(&key);
+        }
+        keys.sort();
+
+        let bound = |rng: &mut StdRng| match rng.gen_range(0..3) {
+            0 => Included(rng.gen_range(0..305)),
+            1 => Excluded(rng.gen_range(0..305)),
+            _ => Unbounded,
+        };
+        for _ in 0..1000 {
+            let range = (bound(&mut rng), bound(&mut rng));
+            let expected: Vec<_> = keys.iter().filter(|key| range.contains(*key)).collect();
+            assert_eq!(
+                tree.range(range).map(|(key, _)| key).collect::<Vec<_>>(),
+                expected,
+                "{range:?}"
+            );
+            assert!(tree
+                .range(range)
+                .map(|(key, _)| key)
+                .rev()
+                .eq(expected.iter().rev().copied()));
+
+            // Both ends meet in the middle.
+            let mut iter = tree.range(range).map(|(key, _)| key);
+            let (mut front, mut back) = (vec![], vec![]);
+            loop {
+                let (end, key) = match rng.gen() {
+                    true => (&mut front, iter.next()),
+                    false => (&mut back, iter.next_back()),
+                };
+                match key {
+                    Some(key) => end.push(key),
+                    None => break,
+                }
+            }
+            front.extend(back.into_iter().rev());
+            assert_eq!(front, expected, "{range:?}");
+            assert_eq!((iter.next(), iter.next_back()), (None, None));
+        }
+    }
 }
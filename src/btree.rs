@@ -1,124 +1,138 @@
-use std::collections::VecDeque;
-
-pub struct BTree {
-    root: Option<Box<Node>>,
+use std::cmp::Ordering;
+use std::fmt;
+use std::io::{Read, Write};
+use std::ops::RangeBounds;
+
+use crate::arena::{Arena, NodeId};
+use crate::comparator::{Comparator, Natural};
+use crate::dot;
+use crate::inline_vec::InlineVec;
+use crate::range::Ends;
+use crate::recorder::{Recorder, Shape, Trace};
+use crate::render::{Block, Rendered};
+use crate::serialization::{self, Codec, Kind, NO_VALUE};
+use crate::stats::{Counters, Stats};
+
+// The nodes are stored in an arena, see `src/arena.rs`, and reference their
+// childrens by id. The keys and childrens of a node are inline arrays sized
+// from the minimum degree, see `src/inline_vec.rs`, so a node is a single
+// allocation and a split or a merge copies ranges of them at once. The keys
+// are ordered by the comparator, see `src/comparator.rs`.
+pub struct BTree<C = Natural> {
+    nodes: Arena<Node>,
+    root: Option<NodeId>,
+    counters: Counters,
+    comparator: C,
 }
 
 struct Node {
-    numbers_of_keys: usize,    // 2t ^ h - 1.
-    keys: Vec<u32>,            // At least t - 1 keys, at most 2t - 1 keys
-    childrens: Vec<Box<Node>>, // At least t children, at most 2t children
+    numbers_of_keys: usize,                           // 2t ^ h - 1.
+    keys: InlineVec<u32, MAX_DEGREE>,                 // At least t - 1 keys, at most 2t - 1 keys
+    childrens: InlineVec<NodeId, { MAX_DEGREE + 1 }>, // At least t children, at most 2t children
     is_leaf: bool,
 }
 
-const MINIMUM_DEGREE: usize = 2; // t
+const DEBUG: bool = false;
+pub const MINIMUM_DEGREE: usize = 2; // t
 const MAX_DEGREE: usize = 2 * MINIMUM_DEGREE - 1;
 
 impl Node {
     pub fn new(is_leaf: bool) -> Self {
         Node {
             numbers_of_keys: 0,
-            keys: vec![],
-            childrens: Vec::new(),
+            keys: InlineVec::new(),
+            childrens: InlineVec::new(),
             is_leaf,
         }
     }
 
-    pub fn search(&self, key: &u32) -> Option<&u32> {
-        let mut index = 0;
-        let mut node_key = self.keys[index];
-
-        while *key > node_key {
-            index += 1;
-
-            if index >= self.numbers_of_keys {
-                break;
+    pub fn search<'a, C: Comparator<u32>>(
+        &'a self,
+        nodes: &'a Arena<Node>,
+        comparator: &C,
+        key: &u32,
+    ) -> Option<&'a u32> {
+        match comparator.search(&self.keys, key) {
+            Ok(index) => self.keys.get(index),
+            Err(_) if self.is_leaf => None,
+            Err(index) => {
+                let next_node = &nodes[self.childrens[index]];
+                next_node.search(nodes, comparator, key)
             }
-
-            node_key = self.keys[index];
         }
+    }
+}
 
-        if index < self.numbers_of_keys && *key == node_key {
-            return self.keys.get(index);
-        } else if self.is_leaf {
-            return None;
-        } else {
-            let next_node = &self.childrens[index];
-            return next_node.search(key);
-        }
-    }
-
-    pub fn split_child(&mut self, index: usize) {
-        if let Some(child) = self.childrens.get_mut(index) {
-            let mut new_node = Self::new(child.is_leaf);
-            let new_node_number_of_keys = MINIMUM_DEGREE - 1;
-            new_node.numbers_of_keys = new_node_number_of_keys;
-
-            // Move keys[t..] to new node
-            // for j = 1 to t - 1
-            //   z.key(k) = y.key(j + t)
-            // y.n = t - 1
-            for j in 0..new_node_number_of_keys {
-                let key = child.keys.remove(MINIMUM_DEGREE);
-                new_node.keys.insert(j, key);
-                child.numbers_of_keys -= 1;
-            }
-
-            // Move childrens[t..] to new node if not leaf node
-            // if not y.leaf
-            //   for j = 1 to t
-            //     z.c(j) = y.c(j+t)
-            if !child.is_leaf {
-                for j in 0..MINIMUM_DEGREE {
-                    let nodes = child.childrens.remove(MINIMUM_DEGREE);
-                    new_node.childrens.insert(j, nodes);
-                }
-            }
+impl<C: Comparator<u32>> BTree<C> {
+    // Only the ids of the childrens move to the new node, not the subtrees.
+    fn split_child(&mut self, id: NodeId, index: usize) {
+        let Some(&child_id) = self.nodes[id].childrens.get(index) else {
+            return;
+        };
+        let child = &mut self.nodes[child_id];
+        let mut new_node = Node::new(child.is_leaf);
+        new_node.numbers_of_keys = MINIMUM_DEGREE - 1;
+
+        // Move keys[t..] to new node
+        // for j = 1 to t - 1
+        //   z.key(k) = y.key(j + t)
+        // y.n = t - 1
+        new_node.keys = child.keys.split_off(MINIMUM_DEGREE);
+
+        // Move childrens[t..] to new node if not leaf node
+        // if not y.leaf
+        //   for j = 1 to t
+        //     z.c(j) = y.c(j+t)
+        if !child.is_leaf {
+            new_node.childrens = child.childrens.split_off(MINIMUM_DEGREE);
+        }
 
-            // x.key(i) = y.key(t)
-            if let Some(key) = child.keys.pop() {
-                self.keys.insert(index, key);
-                child.numbers_of_keys -= 1;
-            }
+        // x.key(i) = y.key(t)
+        let key = child.keys.pop().unwrap();
+        child.numbers_of_keys = MINIMUM_DEGREE - 1;
 
-            // x.c(i+1) = z
-            self.childrens.insert(index + 1, Box::new(new_node));
+        // x.c(i+1) = z
+        let new_id = self.nodes.alloc(new_node);
+        let node = &mut self.nodes[id];
+        node.keys.insert(index, key);
+        node.childrens.insert(index + 1, new_id);
 
-            // x.n = x.n + 1
-            self.numbers_of_keys += 1;
-        };
+        // x.n = x.n + 1
+        node.numbers_of_keys += 1;
+        self.counters.splits += 1;
     }
 
-    pub fn insert_non_full(&mut self, key: u32) {
-        if self.is_leaf {
-            match self.keys.binary_search(&key) {
-                Ok(_) => (),
-                Err(pos) => {
-                    self.keys.insert(pos, key);
-                }
+    fn insert_non_full(&mut self, id: NodeId, key: u32, trace: &mut Trace) {
+        let node = &mut self.nodes[id];
+        if node.is_leaf {
+            // Ignore if key is duplicated
+            if let Err(pos) = self.comparator.search(&node.keys, &key) {
+                node.keys.insert(pos, key);
+                node.numbers_of_keys += 1;
+                trace.record("insert_into_leaf", || self.subtree(id));
             }
-
-            self.numbers_of_keys += 1;
         } else {
-            let mut index = self.numbers_of_keys - 1;
-
-            while index > 0 && key < self.keys[index] {
-                index -= 1;
-            }
-
-            if key > self.keys[index] {
-                index += 1;
-            }
-
-            if self.childrens[index].numbers_of_keys == MAX_DEGREE {
-                self.split_child(index);
-
-                if key > self.keys[index] {
-                    index += 1
+            let mut index = match self.comparator.search(&node.keys, &key) {
+                Ok(_) => return,
+                Err(index) => index,
+            };
+
+            let child = node.childrens[index];
+            if self.nodes[child].numbers_of_keys == MAX_DEGREE {
+                self.split_child(id, index);
+                trace.record("split_child", || self.subtree(id));
+
+                match self.comparator.compare(&key, &self.nodes[id].keys[index]) {
+                    Ordering::Less => (),
+                    // The key moved up from the child is the same key.
+                    Ordering::Equal => return,
+                    Ordering::Greater => index += 1,
                 }
             }
 
-            self.childrens[index].insert_non_full(key)
+            trace.enter(index);
+            self.insert_non_full(self.nodes[id].childrens[index], key, trace);
+            trace.leave();
         }
     }
 
@@ -146,39 +160,44 @@ impl Node {
     //
     // left = 17
     // right = 19
-    pub fn merge_childs(&mut self, index: usize) {
-        let key = self.keys.remove(index);
-        self.numbers_of_keys -= 1;
-
-        let left = self.childrens.remove(index);
-        let mut right = self.childrens.remove(index);
-
-        println!("Merging {:?}, {key}, {:?}...", left.keys, right.keys);
+    //
+    // The right child is freed, its childrens move to the left one.
+    fn merge_childs(&mut self, id: NodeId, index: usize) {
+        let node = &mut self.nodes[id];
+        let key = node.keys.remove(index);
+        node.numbers_of_keys -= 1;
+
+        let left_id = node.childrens[index];
+        let right_id = node.childrens.remove(index + 1);
+        let mut right = self.nodes.free(right_id);
+        let left = &mut self.nodes[left_id];
+
+        if DEBUG {
+            println!("Merging {:?}, {key}, {:?}...", left.keys, right.keys);
+        }
 
         // Merge the keys
-        let mut new_keys = left.keys;
-        new_keys.push(key);
-        new_keys.append(&mut right.keys);
-
-        let mut left_chidrens = left.childrens;
-        let mut right_childrens = right.childrens;
-        left_chidrens.append(&mut right_childrens);
-
-        let node = Node {
-            numbers_of_keys: left.numbers_of_keys + right.numbers_of_keys + 1,
-            is_leaf: left.is_leaf,
-            keys: new_keys,
-            childrens: left_chidrens,
-        };
-
-        self.childrens.insert(index, Box::new(node));
+        left.keys.push(key);
+        left.keys.append(&mut right.keys);
+        left.childrens.append(&mut right.childrens);
+        left.numbers_of_keys += right.numbers_of_keys + 1;
+        self.counters.merges += 1;
     }
 
-    pub fn remove_from_internals(&mut self, index: usize) -> Option<u32> {
-        let key = self.keys[index];
-
-        if self.childrens[index].numbers_of_keys >= MINIMUM_DEGREE {
-            println!("Swap with left child...");
+    fn remove_from_internals(
+        &mut self,
+        id: NodeId,
+        index: usize,
+        trace: &mut Trace,
+    ) -> Option<u32> {
+        let node = &self.nodes[id];
+        let key = node.keys[index];
+        let (left_id, right_id) = (node.childrens[index], node.childrens[index + 1]);
+
+        if self.nodes[left_id].numbers_of_keys >= MINIMUM_DEGREE {
+            if DEBUG {
+                println!("Swap with left child...");
+            }
             //     4  |  7
             //    /   |   \
             //   1|2  6  8|9
@@ -190,25 +209,33 @@ impl Node {
             //   1    6   8|9
 
             // Recursively find the biggest left children to be swap:
-            let mut most_left = &mut self.childrens[index];
+            let mut most_left = left_id;
 
-            while let Some(node) = most_left.childrens.last_mut() {
-                most_left = node;
+            while let Some(&child) = self.nodes[most_left].childrens.last() {
+                most_left = child;
             }
 
-            let k1 = most_left.keys[most_left.keys.len() - 1];
+            let k1 = *self.nodes[most_left].keys.last().unwrap();
 
             // Swap k1 with key:
-            println!("Replace {key} with {k1}: {:?}", self.keys);
-            let key = self.keys.remove(index);
-            self.keys.insert(index, k1);
+            if DEBUG {
+                println!("Replace {key} with {k1}: {:?}", self.nodes[id].keys);
+            }
+            self.nodes[id].keys[index] = k1;
+            trace.record("replace_with_predecessor", || self.subtree(id));
 
-            println!("Removing {k1} from {:?}...", self.childrens[index]);
-            self.childrens[index].remove(&k1);
+            if DEBUG {
+                println!("Removing {k1} from {:?}...", self.nodes[left_id]);
+            }
+            trace.enter(index);
+            self.remove_from(left_id, &k1, trace);
+            trace.leave();
 
             Some(key)
-        } else if self.childrens[index + 1].numbers_of_keys >= MINIMUM_DEGREE {
-            println!("Swap with right child...");
+        } else if self.nodes[right_id].numbers_of_keys >= MINIMUM_DEGREE {
+            if DEBUG {
+                println!("Swap with right child...");
+            }
             //     4  |  7
             //    /   |   \
             //   1   5|6  8|9
@@ -218,32 +245,44 @@ impl Node {
             //     5  |  7
             //    /   |   \
             //   1    6  8|9
-            let mut most_right = &mut self.childrens[index + 1];
+            let mut most_right = right_id;
 
-            while let Some(node) = most_right.childrens.first_mut() {
-                most_right = node;
+            while let Some(&child) = self.nodes[most_right].childrens.first() {
+                most_right = child;
             }
 
-            let k1 = most_right.keys[0];
+            let k1 = self.nodes[most_right].keys[0];
 
             // Swap k1 with key:
-            println!("Replace {key} with {k1}: {:?}", self.keys);
-            let key = self.keys.remove(index);
-            self.keys.insert(index, k1);
+            if DEBUG {
+                println!("Replace {key} with {k1}: {:?}", self.nodes[id].keys);
+            }
+            self.nodes[id].keys[index] = k1;
+            trace.record("replace_with_successor", || self.subtree(id));
 
-            println!("Removing {k1} from {:?}...", self.childrens[index + 1]);
-            self.childrens[index + 1].remove(&k1);
+            if DEBUG {
+                println!("Removing {k1} from {:?}...", self.nodes[right_id]);
+            }
+            trace.enter(index + 1);
+            self.remove_from(right_id, &k1, trace);
+            trace.leave();
 
             Some(key)
         } else {
-            self.merge_childs(index);
+            self.merge_childs(id, index);
+            trace.record("merge_childs", || self.subtree(id));
+
             // Recursively call remove
-            self.childrens[index].remove(&key)
+            trace.enter(index);
+            let result = self.remove_from(left_id, &key, trace);
+            trace.leave();
+            result
         }
     }
 
-    pub fn fill(&mut self, index: usize) {
-        let is_prev = index == (self.childrens.len() - 1);
+    fn fill(&mut self, id: NodeId, index: usize, trace: &mut Trace) {
+        let node = &self.nodes[id];
+        let is_prev = index == (node.childrens.len() - 1);
 
         // TODO: Add test case and fix this:
         // Currently, we only look at the prev sibling if we are the last
@@ -252,87 +291,719 @@ impl Node {
         // This is not entirely correct because the actual implementation
         // is to look at both immediate siblings to see if they have t
         // keys. So there will be a case where the fill doesn't happen at all.
-        let siblings = if index == self.childrens.len() - 1 {
-            self.childrens[index - 1].as_mut()
+        let child_id = node.childrens[index];
+        let siblings_id = if is_prev {
+            node.childrens[index - 1]
         } else {
-            self.childrens[index + 1].as_mut()
+            node.childrens[index + 1]
         };
 
-        if siblings.numbers_of_keys >= MINIMUM_DEGREE {
-            // Get the last key from our parent
-            //
-            // It doesn't matter as we will then decide
-            // to push to our children end or insert in front
-            // depending of whether is a left or right child.
-            let k1 = self.keys.pop().unwrap();
+        if self.nodes[siblings_id].numbers_of_keys >= MINIMUM_DEGREE {
+            // The key of our parent in between the child and its sibling
+            // moves down to the child, and the closest key of the sibling
+            // moves up in its place.
+            let separator = if is_prev { index - 1 } else { index };
+            let k1 = self.nodes[id].keys[separator];
+            let (child, siblings) = self.nodes.pair_mut(child_id, siblings_id);
 
-            if is_prev {
+            let k2 = if is_prev {
                 let k2 = siblings.keys.pop().unwrap();
                 siblings.numbers_of_keys -= 1;
                 if !siblings.is_leaf {
-                    let child = siblings.childrens.pop().unwrap();
-                    self.childrens[index].childrens.insert(0, child);
+                    let grandchild = siblings.childrens.pop().unwrap();
+                    child.childrens.insert(0, grandchild);
                 }
 
-                println!("Stealing {k2} last value from prev siblings and moving {k1} below as first value...");
-                self.childrens[index].keys.insert(0, k1);
-                self.childrens[index].numbers_of_keys += 1;
-                self.keys.push(k2);
+                if DEBUG {
+                    println!("Stealing {k2} last value from prev siblings and moving {k1} below as first value...");
+                }
+                child.keys.insert(0, k1);
+                k2
             } else {
                 let k2 = siblings.keys.remove(0);
                 siblings.numbers_of_keys -= 1;
                 if !siblings.is_leaf {
-                    let child = siblings.childrens.remove(0);
-                    self.childrens[index].childrens.push(child);
+                    let grandchild = siblings.childrens.remove(0);
+                    child.childrens.push(grandchild);
                 }
 
-                println!("Stealing {k2}, first value from next siblings and insert {k1} below as last value...");
-                self.childrens[index].keys.push(k1);
-                self.childrens[index].numbers_of_keys += 1;
-                self.keys.push(k2);
-            }
+                if DEBUG {
+                    println!("Stealing {k2}, first value from next siblings and insert {k1} below as last value...");
+                }
+                child.keys.push(k1);
+                k2
+            };
+            child.numbers_of_keys += 1;
+            self.nodes[id].keys[separator] = k2;
+            self.counters.borrows += 1;
+
+            let step = if is_prev {
+                "borrow_from_left"
+            } else {
+                "borrow_from_right"
+            };
+            trace.record(step, || self.subtree(id));
         } else {
-            if index == self.childrens.len() - 1 {
-                self.merge_childs(index - 1);
+            if is_prev {
+                self.merge_childs(id, index - 1);
             } else {
-                self.merge_childs(index);
+                self.merge_childs(id, index);
             }
+            trace.record("merge_childs", || self.subtree(id));
         }
     }
 
-    pub fn remove(&mut self, key: &u32) -> Option<u32> {
-        println!("--- Remove {key} from {:?}", self.keys);
-        match self.keys.binary_search(key) {
+    fn remove_from(&mut self, id: NodeId, key: &u32, trace: &mut Trace) -> Option<u32> {
+        let node = &mut self.nodes[id];
+        if DEBUG {
+            println!("--- Remove {key} from {:?}", node.keys);
+        }
+        match self.comparator.search(&node.keys, key) {
             Ok(index) => {
-                if self.is_leaf {
-                    let key = self.keys.remove(index);
-                    self.numbers_of_keys -= 1;
+                if node.is_leaf {
+                    let key = node.keys.remove(index);
+                    node.numbers_of_keys -= 1;
+                    trace.record("remove_from_leaf", || self.subtree(id));
                     Some(key)
                 } else {
-                    self.remove_from_internals(index)
+                    self.remove_from_internals(id, index, trace)
                 }
             }
             Err(index) => {
-                println!("Didn't found key in current node, child index: {index}...");
-                if self.is_leaf {
+                if DEBUG {
+                    println!("Didn't found key in current node, child index: {index}...");
+                }
+                if node.is_leaf {
                     None
                 } else {
-                    println!("my children: {:?}", self.childrens);
-                    if self.childrens[index].numbers_of_keys == MINIMUM_DEGREE - 1 {
-                        println!("Child has less than t keys, fill it up...");
-                        self.fill(index);
+                    if DEBUG {
+                        println!("my children: {:?}", node.childrens);
+                    }
+                    let child = node.childrens[index];
+                    if self.nodes[child].numbers_of_keys == MINIMUM_DEGREE - 1 {
+                        if DEBUG {
+                            println!("Child has less than t keys, fill it up...");
+                        }
+                        self.fill(id, index, trace);
                     }
 
-                    if index < self.childrens.len() {
-                        self.childrens[index].remove(key)
+                    // The child merged with its left sibling.
+                    let childrens = &self.nodes[id].childrens;
+                    let index = if index < childrens.len() {
+                        index
                     } else {
-                        // TODO: Add test case for this.
-                        self.childrens[index - 1].remove(key)
-                    }
+                        index - 1
+                    };
+                    let child = childrens[index];
+
+                    trace.enter(index);
+                    let result = self.remove_from(child, key, trace);
+                    trace.leave();
+                    result
                 }
             }
         }
     }
+
+    // Merge the sorted keys into the subtree, each child getting the keys in
+    // between its keys in the parent. A node can't hold more keys than it
+    // fits, so it is split by `fill_evenly` instead, and the new nodes are
+    // returned to the parent along with the key moving up before each of
+    // them.
+    fn insert_batch_into(&mut self, id: NodeId, mut keys: Vec<u32>) -> Vec<(u32, NodeId)> {
+        let node = &self.nodes[id];
+        let mut node_keys = node.keys.to_vec();
+        let mut childrens = node.childrens.to_vec();
+
+        if node.is_leaf {
+            let mut merged = Vec::with_capacity(node_keys.len() + keys.len());
+            let mut old = node_keys.into_iter().peekable();
+            let mut keys = keys.into_iter().peekable();
+            loop {
+                let ordering = match (old.peek(), keys.peek()) {
+                    (Some(a), Some(b)) => self.comparator.compare(a, b),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => break,
+                };
+                // Ignore if key is duplicated
+                if ordering.is_eq() {
+                    keys.next();
+                }
+                merged.extend(match ordering {
+                    Ordering::Greater => keys.next(),
+                    _ => old.next(),
+                });
+            }
+            return self.fill_evenly(id, merged, vec![]);
+        }
+
+        // From the last child, so the new childrens don't move the ones left
+        // to do.
+        for index in (0..childrens.len()).rev() {
+            let start = match index {
+                0 => 0,
+                _ => {
+                    let key = &node_keys[index - 1];
+                    keys.partition_point(|other| self.comparator.compare(other, key).is_le())
+                }
+            };
+            let batch = keys.split_off(start);
+            // Ignore the key already in the node.
+            if index > 0 {
+                let key = &node_keys[index - 1];
+                let end = keys.partition_point(|other| self.comparator.compare(other, key).is_lt());
+                keys.truncate(end);
+            }
+            if batch.is_empty() {
+                continue;
+            }
+
+            let new_nodes = self.insert_batch_into(childrens[index], batch);
+            for (i, (key, new_id)) in new_nodes.into_iter().enumerate() {
+                node_keys.insert(index + i, key);
+                childrens.insert(index + i + 1, new_id);
+            }
+        }
+        self.fill_evenly(id, node_keys, childrens)
+    }
+
+    // Put the keys and childrens back in the node, split in as few nodes as
+    // fit them with the same number of keys each, give or take one, the same
+    // as `build`. Returns the new nodes after the node, each with the key
+    // moving up to the parent before it.
+    fn fill_evenly(
+        &mut self,
+        id: NodeId,
+        keys: Vec<u32>,
+        childrens: Vec<NodeId>,
+    ) -> Vec<(u32, NodeId)> {
+        let numbers_of_nodes = (keys.len() + 1).div_ceil(MAX_DEGREE + 1);
+        let keys_in_nodes = keys.len() - (numbers_of_nodes - 1);
+        let is_leaf = self.nodes[id].is_leaf;
+        let (mut keys, mut childrens) = (&keys[..], &childrens[..]);
+        let mut separator = None;
+        let mut new_nodes = vec![];
+
+        for i in 0..numbers_of_nodes {
+            let len =
+                keys_in_nodes / numbers_of_nodes + (i < keys_in_nodes % numbers_of_nodes) as usize;
+            let mut node = Node::new(is_leaf);
+            node.keys = InlineVec::from_slice(&keys[..len]);
+            node.numbers_of_keys = len;
+            keys = &keys[len..];
+            if !is_leaf {
+                node.childrens = InlineVec::from_slice(&childrens[..len + 1]);
+                childrens = &childrens[len + 1..];
+            }
+
+            match separator {
+                None => self.nodes[id] = node,
+                Some(key) => new_nodes.push((key, self.nodes.alloc(node))),
+            }
+            if let Some((&key, rest)) = keys.split_first() {
+                separator = Some(key);
+                keys = rest;
+            }
+        }
+
+        self.counters.splits += new_nodes.len() as u64;
+        new_nodes
+    }
+
+    // Remove the sorted keys from the subtree, returning how many were in it.
+    // Each child gets the keys in between its keys in the parent. A key of
+    // the node itself is replaced with the closest key of its childrens which
+    // isn't removed, and that key is removed from the child instead:
+    //
+    //   remove 1, 4         4 | 7               5 | 7
+    //                      /  |  \      =>     /  |  \
+    //                    1   5|6  8|9        1   5|6  8|9
+    //
+    // then 1 is removed from the first child, and 5 from the second one.
+    // Then the childrens left with too few keys are filled. The node itself
+    // may be left without keys, and a single child, for its parent to fill.
+    fn remove_batch_from(&mut self, id: NodeId, keys: &[u32], trace: &mut Trace) -> usize {
+        let comparator = &self.comparator;
+        let node = &mut self.nodes[id];
+        if node.is_leaf {
+            let old = std::mem::take(&mut node.keys);
+            let mut keys = keys.iter().peekable();
+            for key in &old {
+                while keys
+                    .next_if(|other| comparator.compare(other, key).is_lt())
+                    .is_some()
+                {}
+                if keys
+                    .next_if(|other| comparator.compare(other, key).is_eq())
+                    .is_none()
+                {
+                    node.keys.push(*key);
+                }
+            }
+            node.numbers_of_keys = node.keys.len();
+            return old.len() - node.keys.len();
+        }
+
+        let mut parts = vec![];
+        let mut matched = vec![];
+        let mut rest = keys;
+        for index in 0..node.childrens.len() {
+            let key = node.keys.get(index);
+            let end = match key {
+                Some(key) => rest.partition_point(|other| comparator.compare(other, key).is_lt()),
+                None => rest.len(),
+            };
+            parts.push(rest[..end].to_vec());
+            rest = &rest[end..];
+            if let (Some(key), Some(other)) = (key, rest.first()) {
+                if comparator.compare(other, key).is_eq() {
+                    matched.push(index);
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        let mut removed = 0;
+        for &index in matched.iter().rev() {
+            match self.replacement(id, index, keys, &parts) {
+                Some((child, key)) => {
+                    self.nodes[id].keys[index] = key;
+                    let part = &mut parts[child];
+                    let Err(at) = self.comparator.search(part, &key) else {
+                        unreachable!();
+                    };
+                    part.insert(at, key);
+                }
+                None => {
+                    // Both childrens are removed whole, so the key goes with
+                    // the right one.
+                    let node = &mut self.nodes[id];
+                    node.keys.remove(index);
+                    node.numbers_of_keys -= 1;
+                    let right = node.childrens.remove(index + 1);
+                    parts.remove(index + 1);
+                    removed += 1 + self.free_subtree(right);
+                }
+            }
+        }
+
+        let childrens = self.nodes[id].childrens.to_vec();
+        for (child, part) in childrens.into_iter().zip(parts) {
+            if !part.is_empty() {
+                removed += self.remove_batch_from(child, &part, trace);
+            }
+        }
+        self.fix_childrens(id, trace);
+        removed
+    }
+
+    // The key replacing the removed key of the node at `index`: the last key
+    // of the left child, else the first key of the right child, skipping the
+    // keys removed or already moved up from them. Returns it with its child.
+    fn replacement(
+        &self,
+        id: NodeId,
+        index: usize,
+        keys: &[u32],
+        parts: &[Vec<u32>],
+    ) -> Option<(usize, u32)> {
+        let node = &self.nodes[id];
+        let find = |child: usize, backward: bool| {
+            let stays = |key: &u32| {
+                self.comparator.search(keys, key).is_err()
+                    && self.comparator.search(&parts[child], key).is_err()
+            };
+            let subtree = &self.nodes[node.childrens[child]];
+            subtree
+                .find_key(&self.nodes, backward, &stays)
+                .map(|key| (child, key))
+        };
+        find(index, true).or_else(|| find(index + 1, false))
+    }
+
+    // Fill every child with too few keys, see `fill`, then the childrens of
+    // the filled child, since it may have taken a child with too few keys.
+    fn fix_childrens(&mut self, id: NodeId, trace: &mut Trace) {
+        let too_few_keys = |tree: &Self| {
+            let childrens = &tree.nodes[id].childrens;
+            let position = childrens
+                .iter()
+                .position(|&child| tree.nodes[child].numbers_of_keys < MINIMUM_DEGREE - 1);
+            position.filter(|_| childrens.len() > 1)
+        };
+        while let Some(index) = too_few_keys(self) {
+            self.fill(id, index, trace);
+
+            // The child merged with its left sibling if it was the last one.
+            let childrens = &self.nodes[id].childrens;
+            let child = childrens[index.min(childrens.len() - 1)];
+            self.fix_childrens(child, trace);
+        }
+    }
+
+    // Free the subtree, returning its number of keys.
+    fn free_subtree(&mut self, id: NodeId) -> usize {
+        let node = self.nodes.free(id);
+        let keys_in_childrens: usize = node
+            .childrens
+            .iter()
+            .map(|&child| self.free_subtree(child))
+            .sum();
+        node.numbers_of_keys + keys_in_childrens
+    }
+
+    fn subtree(&self, id: NodeId) -> Shape {
+        self.nodes[id].shape(&self.nodes)
+    }
+}
+
+impl Node {
+    // The first key of the subtree for which `keep` is true, going in order
+    // or backward.
+    fn find_key<F: Fn(&u32) -> bool>(
+        &self,
+        nodes: &Arena<Node>,
+        backward: bool,
+        keep: &F,
+    ) -> Option<u32> {
+        let steps = steps(self);
+        (0..steps).find_map(|step| {
+            let step = if backward { steps - 1 - step } else { step };
+            if self.is_leaf {
+                Some(self.keys[step]).filter(keep)
+            } else if step % 2 == 1 {
+                Some(self.keys[step / 2]).filter(keep)
+            } else {
+                nodes[self.childrens[step / 2]].find_key(nodes, backward, keep)
+            }
+        })
+    }
+}
+
+impl Node {
+    // Node record: leaf flag, keys, then the children records.
+    fn write_to<W: Write>(&self, nodes: &Arena<Node>, writer: &mut W) -> std::io::Result<()> {
+        serialization::write_u8(writer, self.is_leaf as u8)?;
+        serialization::write_len(writer, self.keys.len())?;
+        for key in &self.keys {
+            key.encode(writer)?;
+        }
+
+        serialization::write_len(writer, self.childrens.len())?;
+        for &child in &self.childrens {
+            nodes[child].write_to(nodes, writer)?;
+        }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(
+        reader: &mut R,
+        nodes: &mut Arena<Node>,
+    ) -> Result<NodeId, serialization::Error> {
+        let mut node = Node::new(match serialization::read_u8(reader)? {
+            0 => false,
+            1 => true,
+            _ => return Err(serialization::Error::Corrupted("invalid leaf flag")),
+        });
+
+        let numbers_of_keys = serialization::read_len(reader)?;
+        if numbers_of_keys > MAX_DEGREE {
+            return Err(serialization::Error::Corrupted("too many keys"));
+        }
+        for _ in 0..numbers_of_keys {
+            node.keys.push(u32::decode(reader)?);
+        }
+        node.numbers_of_keys = numbers_of_keys;
+
+        // An internal node has at least a key, and a child on each side of
+        // every key.
+        let numbers_of_childrens = serialization::read_len(reader)?;
+        let expected = if node.is_leaf { 0 } else { numbers_of_keys + 1 };
+        if numbers_of_childrens != expected || (!node.is_leaf && numbers_of_keys == 0) {
+            return Err(serialization::Error::Corrupted(
+                "invalid number of childrens",
+            ));
+        }
+        for _ in 0..numbers_of_childrens {
+            node.childrens.push(Node::read_from(reader, nodes)?);
+        }
+        Ok(nodes.alloc(node))
+    }
+}
+
+// Build a subtree of the given height from sorted keys, splitting the keys
+// evenly between as few childrens as possible, with the key in between two
+// childrens moving up:
+//
+//   [1, 2, 3, 4, 5, 6, 7], height 1
+//
+//             [4]
+//   [1, 2, 3]     [5, 6, 7]
+fn build(keys: &[u32], height: u32, nodes: &mut Arena<Node>) -> NodeId {
+    if height == 0 {
+        let mut leaf = Node::new(true);
+        leaf.keys = InlineVec::from_slice(keys);
+        leaf.numbers_of_keys = keys.len();
+        return nodes.alloc(leaf);
+    }
+
+    let mut numbers_of_childrens = MINIMUM_DEGREE;
+    while keys.len() - (numbers_of_childrens - 1) > numbers_of_childrens * capacity(height - 1) {
+        numbers_of_childrens += 1;
+    }
+
+    let mut node = Node::new(false);
+    let keys_in_childrens = keys.len() - (numbers_of_childrens - 1);
+    let mut start = 0;
+
+    for i in 0..numbers_of_childrens {
+        let len = keys_in_childrens / numbers_of_childrens
+            + (i < keys_in_childrens % numbers_of_childrens) as usize;
+        node.childrens
+            .push(build(&keys[start..start + len], height - 1, nodes));
+        start += len;
+
+        if i < numbers_of_childrens - 1 {
+            node.keys.push(keys[start]);
+            start += 1;
+        }
+    }
+
+    node.numbers_of_keys = node.keys.len();
+    nodes.alloc(node)
+}
+
+// Maximum number of keys in a subtree of the given height.
+fn capacity(height: u32) -> usize {
+    (MAX_DEGREE + 1).pow(height + 1) - 1
+}
+
+// In order iterator, interleaving the childrens with the keys.
+pub struct Iter<'a> {
+    nodes: &'a Arena<Node>,
+    stack: Vec<(&'a Node, usize)>, // Node and the next child or key to visit.
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, index)) = self.stack.last_mut() {
+            let node: &'a Node = node;
+            let i = *index;
+            *index += 1;
+
+            if node.is_leaf {
+                if i < node.keys.len() {
+                    return Some(&node.keys[i]);
+                }
+            } else if i < 2 * node.keys.len() + 1 {
+                // Even steps visit a child, odd steps the key after it.
+                if i % 2 == 0 {
+                    self.stack.push((&self.nodes[node.childrens[i / 2]], 0));
+                } else {
+                    return Some(&node.keys[i / 2]);
+                }
+                continue;
+            }
+
+            self.stack.pop();
+        }
+
+        None
+    }
+}
+
+// Keys in a range, in order from both ends, see `src/range.rs`. The front
+// is an in order iterator, the back goes through the same steps from the
+// last one, with the number of steps left to visit of each node.
+pub struct Range<'a, C> {
+    front: Iter<'a>,
+    back: Vec<(&'a Node, usize)>,
+    comparator: &'a C,
+    ends: Ends<'a, u32>,
+}
+
+impl<'a, C: Comparator<u32>> Iterator for Range<'a, C> {
+    type Item = &'a u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ends.is_done() {
+            return None;
+        }
+        let key = self.front.next();
+        self.ends.front(self.comparator, key).then(|| key.unwrap())
+    }
+}
+
+impl<'a, C: Comparator<u32>> DoubleEndedIterator for Range<'a, C> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.ends.is_done() {
+            return None;
+        }
+        let key = self.next_back_key();
+        self.ends.back(self.comparator, key).then(|| key.unwrap())
+    }
+}
+
+impl<'a, C> Range<'a, C> {
+    fn next_back_key(&mut self) -> Option<&'a u32> {
+        while let Some((node, left)) = self.back.last_mut() {
+            let node: &'a Node = node;
+            if *left == 0 {
+                self.back.pop();
+                continue;
+            }
+            *left -= 1;
+            let i = *left;
+
+            if node.is_leaf {
+                return Some(&node.keys[i]);
+            }
+            // Odd steps visit a key, even steps the child before it.
+            if i % 2 == 1 {
+                return Some(&node.keys[i / 2]);
+            }
+            let child = &self.front.nodes[node.childrens[i / 2]];
+            self.back.push((child, steps(child)));
+        }
+        None
+    }
+}
+
+// Children and keys of a node, the steps to visit all of it.
+fn steps(node: &Node) -> usize {
+    match node.is_leaf {
+        true => node.keys.len(),
+        false => 2 * node.keys.len() + 1,
+    }
+}
+
+impl Node {
+    // Record with a port in between the keys for each child. Returns the id
+    // of the node.
+    fn write_dot<W: fmt::Write>(
+        &self,
+        nodes: &Arena<Node>,
+        dot: &mut W,
+        next_id: &mut usize,
+    ) -> Result<usize, fmt::Error> {
+        let id = *next_id;
+        *next_id += 1;
+        let label = dot::keys_label(&self.keys, !self.childrens.is_empty());
+        writeln!(dot, "  node{id} [label=\"{label}\"];")?;
+
+        for (i, &child) in self.childrens.iter().enumerate() {
+            let child_id = nodes[child].write_dot(nodes, dot, next_id)?;
+            writeln!(dot, "  node{id}:c{i} -> node{child_id};")?;
+        }
+        Ok(id)
+    }
+}
+
+impl Node {
+    // Check the node and its subtree, where every key must be in between
+    // `min` and `max`. Returns the height of the subtree.
+    fn validate<C: Comparator<u32>>(
+        &self,
+        nodes: &Arena<Node>,
+        comparator: &C,
+        is_root: bool,
+        min: Option<u32>,
+        max: Option<u32>,
+    ) -> Result<usize, String> {
+        let keys = &self.keys;
+
+        if self.numbers_of_keys != keys.len() {
+            return Err(format!(
+                "{keys:?} has numbers_of_keys {}",
+                self.numbers_of_keys
+            ));
+        }
+        if keys.len() > MAX_DEGREE || keys.is_empty() {
+            return Err(format!("{keys:?} has too many or no keys"));
+        }
+        if !is_root && keys.len() < MINIMUM_DEGREE - 1 {
+            return Err(format!(
+                "{keys:?} has less than {} keys",
+                MINIMUM_DEGREE - 1
+            ));
+        }
+        if keys
+            .windows(2)
+            .any(|pair| comparator.compare(&pair[0], &pair[1]).is_ge())
+        {
+            return Err(format!("{keys:?} is not sorted"));
+        }
+        if min.is_some_and(|min| comparator.compare(&keys[0], &min).is_le())
+            || max.is_some_and(|max| comparator.compare(&keys[keys.len() - 1], &max).is_ge())
+        {
+            return Err(format!("{keys:?} is not in between {min:?} and {max:?}"));
+        }
+
+        if self.is_leaf {
+            return match self.childrens.is_empty() {
+                true => Ok(0),
+                false => Err(format!("leaf {keys:?} has childrens")),
+            };
+        }
+
+        if self.childrens.len() != keys.len() + 1 {
+            return Err(format!("{keys:?} has {} childrens", self.childrens.len()));
+        }
+
+        let mut height = None;
+        for (i, &child) in self.childrens.iter().enumerate() {
+            let min = if i == 0 { min } else { Some(keys[i - 1]) };
+            let max = keys.get(i).copied().or(max);
+            let child_height = nodes[child].validate(nodes, comparator, false, min, max)?;
+
+            if *height.get_or_insert(child_height) != child_height {
+                return Err(format!("childrens of {keys:?} are not at the same height"));
+            }
+        }
+        Ok(height.unwrap() + 1)
+    }
+
+    fn shape(&self, nodes: &Arena<Node>) -> Shape {
+        Shape {
+            keys: self.keys.to_vec(),
+            childrens: self
+                .childrens
+                .iter()
+                .map(|&child| nodes[child].shape(nodes))
+                .collect(),
+        }
+    }
+
+    fn block<F: Fn(&u32) -> String>(&self, nodes: &Arena<Node>, format_key: &F) -> Block {
+        let keys: Vec<String> = self.keys.iter().map(format_key).collect();
+        let childrens = self
+            .childrens
+            .iter()
+            .map(|&child| nodes[child].block(nodes, format_key))
+            .collect();
+
+        Block::node(format!("[{}]", keys.join(", ")), childrens)
+    }
+
+    fn add_stats(&self, nodes: &Arena<Node>, stats: &mut Stats) {
+        // The keys and childrens are inline, they only count in the nodes.
+        stats.add_node(self.keys.len(), MAX_DEGREE, self.is_leaf);
+
+        for &child in &self.childrens {
+            nodes[child].add_stats(nodes, stats);
+        }
+    }
+
+    fn node_count(&self, nodes: &Arena<Node>) -> usize {
+        1 + self
+            .childrens
+            .iter()
+            .map(|&child| nodes[child].node_count(nodes))
+            .sum::<usize>()
+    }
 }
 
 impl std::fmt::Debug for Node {
@@ -345,83 +1016,392 @@ impl std::fmt::Debug for Node {
     }
 }
 
+impl<C: Comparator<u32>> fmt::Display for BTree<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render_with(u32::to_string))
+    }
+}
+
+impl Default for BTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Serialized trees are read back with the `Ord` order of the keys, so only
+// those are written.
 impl BTree {
     pub fn new() -> BTree {
-        BTree { root: None }
+        BTree::with_comparator(Natural)
+    }
+
+    pub fn bulk_load(keys: Vec<u32>) -> Self {
+        BTree::bulk_load_with(keys, Natural)
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), serialization::Error> {
+        let degree = MINIMUM_DEGREE as u32;
+        serialization::write_header(&mut writer, Kind::BTree, degree, u32::ID, NO_VALUE)?;
+
+        match self.root {
+            Some(root) => {
+                serialization::write_u8(&mut writer, 1)?;
+                self.nodes[root].write_to(&self.nodes, &mut writer)?;
+            }
+            None => serialization::write_u8(&mut writer, 0)?,
+        }
+        Ok(())
+    }
+
+    // The minimum degree is a constant, so only tree written with the same
+    // minimum degree can be read back.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, serialization::Error> {
+        let degree = serialization::read_header(&mut reader, Kind::BTree, u32::ID, NO_VALUE)?;
+        if degree as usize != MINIMUM_DEGREE {
+            return Err(serialization::Error::UnexpectedDegree(degree));
+        }
+
+        let mut tree = BTree::new();
+        tree.root = match serialization::read_u8(&mut reader)? {
+            0 => None,
+            1 => Some(Node::read_from(&mut reader, &mut tree.nodes)?),
+            _ => return Err(serialization::Error::Corrupted("invalid root flag")),
+        };
+
+        // Each node has a valid shape, but the keys may be out of order, the
+        // nodes too empty or the leaves at different depths.
+        tree.validate()
+            .map_err(|_| serialization::Error::Corrupted("invalid tree"))?;
+        Ok(tree)
+    }
+}
+
+impl<C: Comparator<u32>> BTree<C> {
+    // Tree with the keys ordered by `comparator` instead of their `Ord`.
+    pub fn with_comparator(comparator: C) -> Self {
+        BTree {
+            nodes: Arena::new(),
+            root: None,
+            counters: Counters::default(),
+            comparator,
+        }
     }
 
     pub fn insert(&mut self, key: u32) {
-        if let Some(node) = &mut self.root {
-            if node.numbers_of_keys == MAX_DEGREE {
+        self.insert_traced(key, &mut Trace::off());
+    }
+
+    // Same as `insert`, recording every internal step.
+    pub fn insert_recorded(&mut self, key: u32, recorder: &mut Recorder) {
+        let mut trace = Trace::on(recorder, format!("insert {key}"), self.shape());
+        self.insert_traced(key, &mut trace);
+    }
+
+    fn insert_traced(&mut self, key: u32, trace: &mut Trace) {
+        if let Some(root) = self.root {
+            if self.nodes[root].numbers_of_keys == MAX_DEGREE {
                 let mut new_root = Node::new(false);
-                new_root.childrens.push(self.root.take().unwrap());
-                new_root.split_child(0);
-                new_root.insert_non_full(key);
-                self.root = Some(Box::new(new_root));
+                new_root.childrens.push(root);
+                let new_root = self.nodes.alloc(new_root);
+                self.root = Some(new_root);
+                self.split_child(new_root, 0);
+                trace.record("split_root", || self.subtree(new_root));
+
+                self.insert_non_full(new_root, key, trace);
             } else {
-                node.insert_non_full(key);
+                self.insert_non_full(root, key, trace);
             }
         } else {
-            let mut node = Node::new(true);
-            node.insert_non_full(key);
-            self.root = Some(Box::new(node));
+            let root = self.nodes.alloc(Node::new(true));
+            self.root = Some(root);
+            self.insert_non_full(root, key, trace);
         }
     }
 
     pub fn remove(&mut self, key: &u32) -> Option<u32> {
-        if let Some(node) = self.root.as_mut() {
-            let result = node.remove(key);
+        self.remove_traced(key, &mut Trace::off())
+    }
 
-            if node.keys.is_empty() {
-                self.root = Some(node.childrens.remove(0));
-            }
+    // Same as `remove`, recording every internal step.
+    pub fn remove_recorded(&mut self, key: &u32, recorder: &mut Recorder) -> Option<u32> {
+        let mut trace = Trace::on(recorder, format!("remove {key}"), self.shape());
+        self.remove_traced(key, &mut trace)
+    }
 
-            result
-        } else {
-            None
+    fn remove_traced(&mut self, key: &u32, trace: &mut Trace) -> Option<u32> {
+        let root = self.root?;
+        let result = self.remove_from(root, key, trace);
+
+        if self.nodes[root].keys.is_empty() {
+            let node = self.nodes.free(root);
+            // A leaf has no childrens, the tree is then empty.
+            self.root = node.childrens.first().copied();
+            trace.record_root("collapse_root", self.root.map(|root| self.subtree(root)));
+        }
+
+        result
+    }
+
+    // Insert many keys at once, sorted first then pushed down the tree
+    // together, so each node is visited and split at most once instead of
+    // once per key, in as many nodes as needed.
+    pub fn insert_batch<I: IntoIterator<Item = u32>>(&mut self, keys: I) {
+        let mut keys: Vec<u32> = keys.into_iter().collect();
+        keys.sort_unstable_by(|a, b| self.comparator.compare(a, b));
+        keys.dedup_by(|next, previous| self.comparator.compare(next, previous).is_eq());
+        if keys.is_empty() {
+            return;
+        }
+
+        let mut root = match self.root {
+            Some(root) => root,
+            None => self.nodes.alloc(Node::new(true)),
+        };
+        let mut new_nodes = self.insert_batch_into(root, keys);
+
+        // The root may split in more nodes than fit in a single new root.
+        while !new_nodes.is_empty() {
+            let (keys, mut childrens): (Vec<_>, Vec<_>) = new_nodes.into_iter().unzip();
+            childrens.insert(0, root);
+            root = self.nodes.alloc(Node::new(false));
+            new_nodes = self.fill_evenly(root, keys, childrens);
+        }
+        self.root = Some(root);
+    }
+
+    // Remove many keys at once, returning how many were in the tree. The keys
+    // are sorted first then pushed down the tree together, so each node is
+    // visited and filled once instead of once per key.
+    pub fn remove_batch<I: IntoIterator<Item = u32>>(&mut self, keys: I) -> usize {
+        let mut keys: Vec<u32> = keys.into_iter().collect();
+        keys.sort_unstable_by(|a, b| self.comparator.compare(a, b));
+        keys.dedup_by(|next, previous| self.comparator.compare(next, previous).is_eq());
+        let Some(root) = self.root else {
+            return 0;
+        };
+
+        let mut trace = Trace::off();
+        let removed = self.remove_batch_from(root, &keys, &mut trace);
+
+        // The root may be left without keys and a single child, which may
+        // have no keys either, or be an empty leaf.
+        while let Some(root) = self.root.filter(|&root| self.nodes[root].keys.is_empty()) {
+            let node = self.nodes.free(root);
+            self.root = node.childrens.first().copied();
+        }
+        removed
+    }
+
+    // Check the number of keys of every node, that the keys are ordered and
+    // that every leaf is at the same height. Also check that every node of
+    // the arena is in the tree.
+    pub fn validate(&self) -> Result<(), String> {
+        let Some(root) = self.root else {
+            return match self.nodes.len() {
+                0 => Ok(()),
+                nodes => Err(format!("empty tree has {nodes} nodes")),
+            };
+        };
+
+        self.nodes[root].validate(&self.nodes, &self.comparator, true, None, None)?;
+        let reachable = self.nodes[root].node_count(&self.nodes);
+        if reachable != self.nodes.len() {
+            return Err(format!(
+                "{} nodes but only {reachable} in the tree",
+                self.nodes.len()
+            ));
+        }
+        Ok(())
+    }
+
+    // Number of levels, 0 for an empty tree. Every leaf is at the same depth,
+    // so only follow the first child.
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut node = self.root;
+        while let Some(id) = node {
+            height += 1;
+            node = self.nodes[id].childrens.first().copied();
+        }
+        height
+    }
+
+    // Every node of the arena is in the tree.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // The nodes are in the arena, which count in `heap_bytes.nodes` along
+    // with its freed slots.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            height: self.height(),
+            counters: self.counters,
+            ..Stats::default()
+        };
+        stats.heap_bytes.nodes = self.nodes.heap_bytes();
+        if let Some(root) = self.root {
+            self.nodes[root].add_stats(&self.nodes, &mut stats);
         }
+        stats
+    }
+
+    fn shape(&self) -> Shape {
+        self.root.map_or(
+            Shape {
+                keys: vec![],
+                childrens: vec![],
+            },
+            |root| self.subtree(root),
+        )
     }
 
     pub fn get(&self, key: &u32) -> Option<&u32> {
-        if let Some(node) = &self.root {
-            node.search(key)
+        if let Some(root) = self.root {
+            self.nodes[root].search(&self.nodes, &self.comparator, key)
         } else {
             None
         }
     }
 
-    pub fn print(&self) {
-        if let Some(node) = &self.root {
-            let mut queue = VecDeque::new();
-            queue.push_front(node);
-            let mut visited_child = 0;
-            let mut num_of_childs = 1;
-            let mut next_to_visit = 0;
-
-            while let Some(node) = queue.pop_back() {
-                print!(" {:?} ", node.keys);
-                // println!("{:?}: {:?}", node.keys, node.childrens);
-                visited_child += 1;
-
-                for c in &node.childrens {
-                    queue.push_front(c);
-                    next_to_visit += 1;
-                }
+    // Keys in the range, which can also be iterated backward with `rev()`.
+    // Going down to the first and the last key in the range only visits the
+    // childrens which may hold keys in the range.
+    pub fn range<R: RangeBounds<u32>>(&self, range: R) -> Range<'_, C> {
+        let ends = Ends::new(&range);
+        let mut front = vec![];
+        let mut back = vec![];
+
+        // The front continues with the key after the child it goes down to,
+        // and the back with the key before it.
+        let mut node = self.root.map(|root| &self.nodes[root]);
+        while let Some(current) = node {
+            let index = current
+                .keys
+                .partition_point(|key| ends.before_start(&self.comparator, key));
+            if current.is_leaf {
+                front.push((current, index));
+                break;
+            }
+            front.push((current, 2 * index + 1));
+            node = Some(&self.nodes[current.childrens[index]]);
+        }
 
-                if num_of_childs == visited_child {
-                    println!("");
-                    visited_child = 0;
-                    num_of_childs = next_to_visit;
-                    next_to_visit = 0;
-                }
+        let mut node = self.root.map(|root| &self.nodes[root]);
+        while let Some(current) = node {
+            let index = current
+                .keys
+                .partition_point(|key| !ends.after_end(&self.comparator, key));
+            if current.is_leaf {
+                back.push((current, index));
+                break;
             }
+            back.push((current, 2 * index));
+            node = Some(&self.nodes[current.childrens[index]]);
+        }
+
+        Range {
+            front: Iter {
+                nodes: &self.nodes,
+                stack: front,
+            },
+            back,
+            comparator: &self.comparator,
+            ends,
         }
     }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            nodes: &self.nodes,
+            stack: self
+                .root
+                .iter()
+                .map(|&root| (&self.nodes[root], 0))
+                .collect(),
+        }
+    }
+
+    // Build the tree from the keys directly instead of inserting them one by
+    // one, using the lowest height that fit all the keys.
+    pub fn bulk_load_with(mut keys: Vec<u32>, comparator: C) -> Self {
+        keys.sort_unstable_by(|a, b| comparator.compare(a, b));
+        keys.dedup_by(|next, previous| comparator.compare(next, previous).is_eq());
+
+        let mut tree = BTree::with_comparator(comparator);
+        if keys.is_empty() {
+            return tree;
+        }
+
+        let mut height = 0;
+        while keys.len() > capacity(height) {
+            height += 1;
+        }
+
+        tree.root = Some(build(&keys, height, &mut tree.nodes));
+        tree
+    }
+
+    // Draw the tree, formatting each key with `format_key`.
+    pub fn render_with<F: Fn(&u32) -> String>(&self, format_key: F) -> Rendered {
+        Rendered::from(
+            self.root
+                .map(|root| self.nodes[root].block(&self.nodes, &format_key)),
+        )
+    }
+
+    pub fn print(&self) {
+        print!("{self}");
+    }
+
+    // Graphviz graph of the tree, see `src/dot.rs`.
+    pub fn to_dot(&self) -> String {
+        dot::render("BTree", |dot| match self.root {
+            Some(root) => self.nodes[root]
+                .write_dot(&self.nodes, dot, &mut 0)
+                .map(|_| ()),
+            None => Ok(()),
+        })
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::BTree;
+    use super::{BTree, Node, MAX_DEGREE, MINIMUM_DEGREE};
+    use crate::arena::NodeId;
+    use crate::recorder::Recorder;
+
+    // Check the number of keys and childrens, returning the height.
+    fn check(tree: &BTree, id: NodeId, is_root: bool) -> usize {
+        let node = &tree.nodes[id];
+        assert_eq!(node.numbers_of_keys, node.keys.len());
+        assert!(node.keys.len() <= MAX_DEGREE);
+        assert!(is_root || node.keys.len() >= MINIMUM_DEGREE - 1);
+
+        if node.is_leaf {
+            return 0;
+        }
+
+        assert_eq!(node.childrens.len(), node.keys.len() + 1);
+        let height = check(tree, node.childrens[0], false);
+        for &child in &node.childrens {
+            assert_eq!(check(tree, child, false), height);
+        }
+        height + 1
+    }
+
+    #[test]
+    fn bulk_load_any_number_of_keys() {
+        for n in 0..300 {
+            let tree = BTree::bulk_load((0..n).rev().chain(0..n).collect());
+            if let Some(root) = tree.root {
+                check(&tree, root, true);
+            }
+
+            assert!(tree.iter().copied().eq(0..n));
+            assert_eq!(tree.get(&n), None);
+        }
+    }
 
     #[test]
     fn basics() {
@@ -486,6 +1466,335 @@ mod test {
         tree.print();
     }
 
+    #[test]
+    fn validate() {
+        let mut tree = BTree::bulk_load((1..=20).collect());
+        assert_eq!(tree.validate(), Ok(()));
+
+        let child = tree.nodes[tree.root.unwrap()].childrens[0];
+        tree.nodes[child].keys[0] = 100;
+        assert!(tree.validate().is_err());
+    }
+
+    #[test]
+    fn height_and_node_count() {
+        assert_eq!(BTree::new().height(), 0);
+        assert_eq!(BTree::new().node_count(), 0);
+
+        // [4, 7] over [1, 2, 3] [5, 6] [8, 9]
+        let tree = BTree::bulk_load((1..=9).collect());
+        assert_eq!(tree.height(), 2);
+        assert_eq!(tree.node_count(), 4);
+    }
+
+    #[test]
+    fn stats() {
+        // [4, 7] over [1, 2, 3] [5, 6] [8, 9]
+        let stats = BTree::bulk_load((1..=9).collect()).stats();
+        assert_eq!(
+            (stats.height, stats.internal_nodes, stats.leaf_nodes),
+            (2, 1, 3)
+        );
+        assert_eq!((stats.keys, stats.min_keys, stats.max_keys), (9, 2, 3));
+        assert_eq!(stats.fill_factors, [0, 0, 0, 0, 0, 0, 3, 0, 0, 1]);
+        assert!(stats.heap_bytes.nodes >= 4 * std::mem::size_of::<Node>());
+        assert_eq!((stats.heap_bytes.keys, stats.heap_bytes.childrens), (0, 0));
+
+        let mut tree = BTree::bulk_load(vec![1, 2, 3]);
+        tree.insert(4);
+        tree.remove(&4);
+        tree.remove(&2);
+        let stats = tree.stats();
+        assert_eq!((stats.counters.splits, stats.counters.merges), (1, 1));
+        assert_eq!((stats.height, stats.leaf_nodes, stats.keys), (1, 1, 2));
+    }
+
+    #[test]
+    fn ignore_duplicated_keys() {
+        let mut tree = BTree::new();
+        for i in (0..50).chain(0..50).chain((0..50).rev()) {
+            tree.insert(i);
+        }
+
+        check(&tree, tree.root.unwrap(), true);
+        assert!(tree.iter().copied().eq(0..50));
+    }
+
+    #[test]
+    fn random_inserts_and_removes() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        use std::collections::BTreeSet;
+
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut tree = BTree::new();
+            let mut set = BTreeSet::new();
+
+            for _ in 0..500 {
+                let key = rng.gen_range(0..200);
+                if rng.gen_bool(0.6) {
+                    tree.insert(key);
+                    set.insert(key);
+                } else {
+                    let expected = set.remove(&key).then_some(key);
+                    assert_eq!(tree.remove(&key), expected, "seed {seed}, remove {key}");
+                }
+                assert_eq!(tree.validate(), Ok(()), "seed {seed}");
+            }
+            assert!(tree.iter().eq(set.iter()));
+        }
+    }
+
+    #[test]
+    fn insert_and_remove_batches() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        use std::collections::BTreeSet;
+
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut tree = BTree::new();
+            let mut set = BTreeSet::<u32>::new();
+
+            for _ in 0..50 {
+                let keys: Vec<u32> = (0..rng.gen_range(0..100))
+                    .map(|_| rng.gen_range(0..500))
+                    .collect();
+                set.extend(&keys);
+                tree.insert_batch(keys);
+                assert_eq!(tree.validate(), Ok(()), "seed {seed}");
+
+                // Whole subtrees are removed along with a range of keys.
+                let keys: Vec<u32> = match rng.gen() {
+                    true => (0..rng.gen_range(0..100))
+                        .map(|_| rng.gen_range(0..500))
+                        .collect(),
+                    false => {
+                        let start = rng.gen_range(0..500);
+                        (start..start + rng.gen_range(0..200)).collect()
+                    }
+                };
+                let before = set.len();
+                for key in &keys {
+                    set.remove(key);
+                }
+                assert_eq!(tree.remove_batch(keys), before - set.len(), "seed {seed}");
+                assert_eq!(tree.validate(), Ok(()), "seed {seed}");
+                if let Some(root) = tree.root {
+                    check(&tree, root, true);
+                }
+                assert!(tree.iter().eq(set.iter()), "seed {seed}");
+            }
+            assert_eq!(tree.remove_batch(0..500), set.len());
+            assert_eq!((tree.root, tree.node_count()), (None, 0));
+        }
+    }
+
+    // Each node splits at most once per batch, in as many nodes as needed.
+    #[test]
+    fn insert_batch_splits_nodes_once() {
+        let mut tree = BTree::new();
+        tree.insert_batch(0..10_000);
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree.iter().copied().eq(0..10_000));
+
+        // Every node but the first of each level is split off once.
+        let splits = tree.stats().counters.splits;
+        assert_eq!(splits, (tree.node_count() - tree.height()) as u64);
+    }
+
+    #[test]
+    fn reverse_order() {
+        let mut tree = BTree::with_comparator(|a: &u32, b: &u32| b.cmp(a));
+        for key in 0..100 {
+            tree.insert(key);
+        }
+        for key in (0..100).step_by(3) {
+            assert_eq!(tree.remove(&key), Some(key));
+        }
+
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree
+            .iter()
+            .copied()
+            .eq((0..100).rev().filter(|key| key % 3 != 0)));
+        assert_eq!(tree.get(&50), Some(&50));
+        assert_eq!(tree.get(&51), None);
+
+        let tree = BTree::bulk_load_with((0..100).collect(), |a: &u32, b: &u32| b.cmp(a));
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree.iter().copied().eq((0..100).rev()));
+    }
+
+    #[test]
+    fn record_split_root() {
+        let mut tree = BTree::bulk_load(vec![1, 2, 3]);
+        let mut recorder = Recorder::new();
+        tree.insert_recorded(4, &mut recorder);
+
+        assert_eq!(
+            recorder.steps(),
+            vec!["start", "split_root", "insert_into_leaf"]
+        );
+        let frames = recorder.frames();
+        assert_eq!(frames[1].tree.keys, vec![2]);
+        assert_eq!(frames[2].path, vec![1]);
+        assert_eq!(frames[2].tree, tree.shape());
+    }
+
+    #[test]
+    fn record_merge_and_collapse_root() {
+        //     2              1|2|3
+        //   /   \    ->
+        //  1     3
+        let mut tree = BTree::bulk_load(vec![1, 2, 3]);
+        tree.insert(4);
+        tree.remove(&4);
+
+        let mut recorder = Recorder::new();
+        assert_eq!(tree.remove_recorded(&2, &mut recorder), Some(2));
+        assert_eq!(
+            recorder.steps(),
+            vec!["start", "merge_childs", "remove_from_leaf", "collapse_root"]
+        );
+        assert_eq!(recorder.frames()[3].tree, tree.shape());
+    }
+
+    #[test]
+    fn recorded_frames_follow_the_tree() {
+        let mut tree = BTree::new();
+        let mut untraced = BTree::new();
+        let mut recorder = Recorder::new();
+
+        // Every change must be recorded for the last frame to be the tree.
+        for i in [2, 7, 8, 9, 4, 6, 1, 5, 3, 10, 11, 14, 16, 17, 18, 19, 20] {
+            tree.insert_recorded(i, &mut recorder);
+            untraced.insert(i);
+            assert_eq!(recorder.frames().last().unwrap().tree, tree.shape());
+        }
+        for i in [7, 16, 1, 18, 4] {
+            tree.remove_recorded(&i, &mut recorder);
+            untraced.remove(&i);
+            assert_eq!(recorder.frames().last().unwrap().tree, tree.shape());
+        }
+
+        let steps = recorder.steps();
+        for step in ["split_child", "merge_childs", "borrow_from_left"] {
+            assert!(steps.contains(&step), "{step} not in {steps:?}");
+        }
+
+        // Counted the same with or without a recorder.
+        let counters = tree.stats().counters;
+        assert_eq!(counters, untraced.stats().counters);
+        assert!(counters.splits > 0 && counters.merges > 0 && counters.borrows > 0);
+    }
+
+    #[test]
+    fn display() {
+        let tree = BTree::bulk_load((1..=9).collect());
+
+        assert_eq!(
+            tree.to_string(),
+            "            [4, 7]
+    ┌──────────┼────────┐
+[1, 2, 3]   [5, 6]   [8, 9]
+"
+        );
+        assert_eq!(BTree::new().to_string(), "");
+    }
+
+    #[test]
+    fn to_dot() {
+        let tree = BTree::bulk_load((1..=9).collect());
+
+        assert_eq!(
+            tree.to_dot(),
+            "digraph BTree {
+  node [shape=record, height=0.1];
+  node0 [label=\"<c0>|4|<c1>|7|<c2>\"];
+  node1 [label=\"1|2|3\"];
+  node0:c0 -> node1;
+  node2 [label=\"5|6\"];
+  node0:c1 -> node2;
+  node3 [label=\"8|9\"];
+  node0:c2 -> node3;
+}
+"
+        );
+    }
+
+    #[test]
+    fn write_and_read_the_same_shape() {
+        let mut tree = BTree::new();
+        for i in 1..30 {
+            tree.insert(i);
+        }
+        tree.remove(&7);
+        tree.remove(&16);
+
+        let mut bytes = vec![];
+        tree.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes, include_bytes!("../fixtures/btree_v1.bin"));
+
+        let tree = BTree::read_from(bytes.as_slice()).unwrap();
+        let mut again = vec![];
+        tree.write_to(&mut again).unwrap();
+        assert_eq!(bytes, again);
+        assert_eq!(tree.get(&8), Some(&8));
+        assert_eq!(tree.get(&16), None);
+    }
+
+    #[test]
+    fn reject_corrupted_nodes() {
+        use crate::serialization::{self, Codec, Error, Kind, NO_VALUE};
+
+        // A tree of nodes given as (is_leaf, keys, number of childrens), in
+        // the order they are written.
+        let read = |nodes: &[(bool, &[u32], usize)]| {
+            let mut bytes = vec![];
+            let degree = MINIMUM_DEGREE as u32;
+            serialization::write_header(&mut bytes, Kind::BTree, degree, u32::ID, NO_VALUE)
+                .unwrap();
+            bytes.push(1);
+            for &(is_leaf, keys, childrens) in nodes {
+                bytes.push(is_leaf as u8);
+                serialization::write_len(&mut bytes, keys.len()).unwrap();
+                for key in keys {
+                    key.encode(&mut bytes).unwrap();
+                }
+                serialization::write_len(&mut bytes, childrens).unwrap();
+            }
+            match BTree::read_from(bytes.as_slice()) {
+                Err(Error::Corrupted(reason)) => reason,
+                Err(error) => panic!("{error}"),
+                Ok(_) => panic!("read a corrupted tree"),
+            }
+        };
+
+        assert_eq!(read(&[(true, &[1, 2], 1)]), "invalid number of childrens");
+        // An internal node without keys, or with as many childrens as keys.
+        assert_eq!(
+            read(&[(false, &[], 1), (true, &[1], 0)]),
+            "invalid number of childrens"
+        );
+        assert_eq!(
+            read(&[(false, &[2], 1), (true, &[1], 0)]),
+            "invalid number of childrens"
+        );
+
+        // Nodes of a valid shape, in a tree breaking its invariants.
+        assert_eq!(read(&[(true, &[2, 1], 0)]), "invalid tree");
+        assert_eq!(
+            read(&[(false, &[2], 2), (true, &[3], 0), (true, &[4], 0)]),
+            "invalid tree"
+        );
+        assert_eq!(
+            read(&[(false, &[2], 2), (true, &[], 0), (true, &[4], 0)]),
+            "invalid tree"
+        );
+    }
+
     #[test]
     fn merge_child_before_swapping_left_child_bigget_value() {
         let mut tree = BTree::new();
@@ -742,4 +2051,63 @@ mod test {
         // Actual case c
         assert_eq!(tree.remove(&4), Some(4));
     }
+
+    #[test]
+    fn range_with_every_bound() {
+        use rand::rngs::StdRng;
+        use rand::seq::SliceRandom;
+        use rand::{Rng, SeedableRng};
+        use std::ops::Bound::{Excluded, Included, Unbounded};
+        use std::ops::RangeBounds;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut keys: Vec<u32> = (0..300).step_by(3).collect();
+        keys.shuffle(&mut rng);
+        let mut tree = BTree::new();
+        for &key in &keys {
+            tree.insert(key);
+        }
+        keys.sort();
+
+        let bound = |rng: &mut StdRng| match rng.gen_range(0..3) {
+            0 => Included(rng.gen_range(0..305)),
+            1 => Excluded(rng.gen_range(0..305)),
+            _ => Unbounded,
+        };
+        for _ in 0..1000 {
+            let range = (bound(&mut rng), bound(&mut rng));
+            let expected: Vec<_> = keys.iter().filter(|key| range.contains(*key)).collect();
+            assert_eq!(tree.range(range).collect::<Vec<_>>(), expected, "{range:?}");
+            assert!(tree.range(range).rev().eq(expected.iter().rev().copied()));
+
+            // Both ends meet in the middle.
+            let mut iter = tree.range(range);
+            let (mut front, mut back) = (vec![], vec![]);
+            loop {
+                let (end, key) = match rng.gen() {
+                    true => (&mut front, iter.next()),
+                    false => (&mut back, iter.next_back()),
+                };
+                match key {
+                    Some(key) => end.push(key),
+                    None => break,
+                }
+            }
+            front.extend(back.into_iter().rev());
+            assert_eq!(front, expected, "{range:?}");
+            assert_eq!((iter.next(), iter.next_back()), (None, None));
+        }
+
+        // In the order of the comparator, from the start to the end.
+        let tree = BTree::bulk_load_with((0..100).collect(), |a: &u32, b: &u32| b.cmp(a));
+        assert!(tree
+            .range((Included(20), Included(10)))
+            .copied()
+            .eq((10..=20).rev()));
+        assert!(tree
+            .range((Included(20), Excluded(10)))
+            .rev()
+            .copied()
+            .eq(11..=20));
+    }
 }
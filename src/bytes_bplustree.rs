@@ -0,0 +1,762 @@
+use std::fmt::{self, Display};
+
+use crate::arena::{Arena, NodeId};
+use crate::render::{Block, Rendered};
+use crate::stats::{Counters, HeapBytes, Stats};
+
+pub const DEFAULT_NODE_BYTES: usize = 4096;
+
+// Bytes counted for each entry on top of its key, for the length of the key
+// and for its value or child.
+const ENTRY_BYTES: usize = 8;
+
+// A B+ Tree of byte string keys, e.g. URL paths, where a node is limited in
+// bytes instead of in number of keys, like a page on disk. Long keys take
+// less room in two ways.
+//
+// A leaf stores the prefix shared by all its keys once, and only the suffix
+// of each key:
+//
+//   "/users/" ["1/posts", "2", "2/posts"]
+//
+// An internal node stores the shortest separator in between two childrens
+// instead of the first key of the right child, chosen in `split_child`:
+//
+//   ["/users/1/posts"] | ["/users/2/posts"]   separator "/users/2"
+//
+// Every key on the left is less than the separator, and every key on the
+// right at least the separator. So the more a node's keys share, the more of
+// them fit in a node, and the higher the fan-out.
+//
+// A node with more bytes than `node_bytes` is split in two halves of about
+// the same bytes. A node with less than a quarter of `node_bytes` is merged
+// with a sibling, then split again if they don't fit in a single node, which
+// moves some keys from the sibling. Keys are at most a quarter of a node, so
+// a node always has room for a few of them.
+pub struct BytesBPlusTree<V> {
+    nodes: Arena<Node<V>>,
+    root: Option<NodeId>,
+    node_bytes: usize,
+    counters: Counters,
+}
+
+struct Node<V> {
+    prefix: Vec<u8>,        // Only in leaf node, shared by every key.
+    keys: Vec<Vec<u8>>,     // Suffixes of the keys in a leaf, separators otherwise.
+    values: Vec<V>,         // Only in leaf node.
+    childrens: Vec<NodeId>, // One more than the separators.
+    next: Option<NodeId>,   // Only in leaf node, None for the last leaf.
+    is_leaf: bool,
+}
+
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+// Shortest key greater than `left` and at most `right`, the start of
+// `right` up to the first byte that differs from `left`.
+fn shortest_separator(left: &[u8], right: &[u8]) -> Vec<u8> {
+    right[..common_prefix_len(left, right) + 1].to_vec()
+}
+
+// Index splitting keys in two halves of about the same bytes, with at least
+// one key on each side.
+fn split_point(keys: &[Vec<u8>]) -> usize {
+    let total: usize = keys.iter().map(|key| key.len() + ENTRY_BYTES).sum();
+    let mut bytes = 0;
+    let at = keys
+        .iter()
+        .take_while(|key| {
+            bytes += key.len() + ENTRY_BYTES;
+            bytes <= total / 2
+        })
+        .count();
+    at.clamp(1, keys.len() - 1)
+}
+
+impl<V> Node<V> {
+    fn new(is_leaf: bool) -> Self {
+        Node {
+            prefix: vec![],
+            keys: vec![],
+            values: vec![],
+            childrens: vec![],
+            next: None,
+            is_leaf,
+        }
+    }
+
+    // Bytes of the node, compared with `node_bytes`.
+    fn size(&self) -> usize {
+        let keys: usize = self.keys.iter().map(|key| key.len() + ENTRY_BYTES).sum();
+        let last_child = if self.is_leaf { 0 } else { ENTRY_BYTES };
+        self.prefix.len() + keys + last_child
+    }
+
+    fn key(&self, index: usize) -> Vec<u8> {
+        [&self.prefix[..], &self.keys[index]].concat()
+    }
+
+    // Search the key in a leaf, or a separator in an internal node. A key
+    // that doesn't start with the prefix of the leaf is either before or
+    // after all its keys.
+    fn search(&self, key: &[u8]) -> Result<usize, usize> {
+        let len = self.prefix.len();
+        match key.get(..len) {
+            Some(start) if start == self.prefix => self
+                .keys
+                .binary_search_by(|suffix| suffix.as_slice().cmp(&key[len..])),
+            _ if key < self.prefix.as_slice() => Err(0),
+            _ => Err(self.keys.len()),
+        }
+    }
+
+    fn child_index(&self, key: &[u8]) -> usize {
+        match self.search(key) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        }
+    }
+
+    // Insert in a leaf, shortening its prefix if the key doesn't start with
+    // it. The first key of an empty leaf is its prefix.
+    fn insert_entry(&mut self, key: &[u8], value: V) {
+        let index = match self.search(key) {
+            // Ignore if key is duplicated
+            Ok(_) => return,
+            Err(index) => index,
+        };
+
+        if self.keys.is_empty() {
+            self.prefix = key.to_vec();
+        }
+        let common = common_prefix_len(&self.prefix, key);
+        if common < self.prefix.len() {
+            let cut = self.prefix.split_off(common);
+            for suffix in &mut self.keys {
+                suffix.splice(0..0, cut.iter().copied());
+            }
+        }
+
+        self.keys.insert(index, key[common..].to_vec());
+        self.values.insert(index, value);
+    }
+
+    // Whole keys of a leaf, leaving it without keys nor prefix.
+    fn take_keys(&mut self) -> Vec<Vec<u8>> {
+        let prefix = std::mem::take(&mut self.prefix);
+        std::mem::take(&mut self.keys)
+            .into_iter()
+            .map(|suffix| [&prefix[..], &suffix].concat())
+            .collect()
+    }
+
+    // Store sorted keys in a leaf with the longest prefix, the one shared by
+    // the first and the last key.
+    fn set_keys(&mut self, keys: Vec<Vec<u8>>) {
+        self.prefix = match (keys.first(), keys.last()) {
+            (Some(first), Some(last)) => first[..common_prefix_len(first, last)].to_vec(),
+            _ => vec![],
+        };
+        let len = self.prefix.len();
+        self.keys = keys.into_iter().map(|key| key[len..].to_vec()).collect();
+    }
+}
+
+impl<V> BytesBPlusTree<V> {
+    pub fn new() -> Self {
+        Self::with_node_bytes(DEFAULT_NODE_BYTES)
+    }
+
+    pub fn with_node_bytes(node_bytes: usize) -> Self {
+        assert!(node_bytes >= 64, "nodes must have at least 64 bytes");
+        BytesBPlusTree {
+            nodes: Arena::new(),
+            root: None,
+            node_bytes,
+            counters: Counters::default(),
+        }
+    }
+
+    // Longest key that can be inserted, a quarter of a node.
+    pub fn max_key_len(&self) -> usize {
+        self.node_bytes / 4 - ENTRY_BYTES
+    }
+
+    // Panics if the key is longer than `max_key_len`.
+    pub fn insert(&mut self, key: &[u8], value: V) {
+        assert!(
+            key.len() <= self.max_key_len(),
+            "key of {} bytes is longer than {} bytes",
+            key.len(),
+            self.max_key_len()
+        );
+
+        let root = match self.root {
+            Some(root) => root,
+            None => *self.root.insert(self.nodes.alloc(Node::new(true))),
+        };
+        self.insert_into(root, key, value);
+        self.fit_root();
+    }
+
+    fn insert_into(&mut self, id: NodeId, key: &[u8], value: V) {
+        let node = &mut self.nodes[id];
+        if node.is_leaf {
+            node.insert_entry(key, value);
+            return;
+        }
+
+        let index = node.child_index(key);
+        let child = node.childrens[index];
+        self.insert_into(child, key, value);
+        self.counters.splits += self.fit_child(id, index);
+    }
+
+    // Split the child until every part of it fits in a node, returning the
+    // number of splits.
+    fn fit_child(&mut self, id: NodeId, index: usize) -> u64 {
+        let child = self.nodes[id].childrens[index];
+        if self.nodes[child].size() <= self.node_bytes {
+            return 0;
+        }
+
+        self.split_child(id, index);
+        1 + self.fit_child(id, index + 1) + self.fit_child(id, index)
+    }
+
+    // Add a root above the root until it fits in a node.
+    fn fit_root(&mut self) {
+        let Some(root) = self.root else { return };
+        if self.nodes[root].size() > self.node_bytes {
+            let mut new_root = Node::new(false);
+            new_root.childrens.push(root);
+            let new_root = self.nodes.alloc(new_root);
+            self.root = Some(new_root);
+            self.counters.splits += self.fit_child(new_root, 0);
+            self.fit_root();
+        }
+    }
+
+    // Split the child in two halves of about the same bytes. A leaf keeps
+    // every key and the shortest separator in between the halves goes up,
+    // while an internal node moves its separator in the middle up. Each half
+    // of a leaf gets the longest prefix of its keys.
+    fn split_child(&mut self, id: NodeId, index: usize) {
+        let child_id = self.nodes[id].childrens[index];
+        let child = &mut self.nodes[child_id];
+        let mut right_node = Node::new(child.is_leaf);
+
+        let separator = if child.is_leaf {
+            let mut keys = child.take_keys();
+            let at = split_point(&keys);
+            let right_keys = keys.split_off(at);
+            let separator = shortest_separator(&keys[at - 1], &right_keys[0]);
+
+            child.set_keys(keys);
+            right_node.set_keys(right_keys);
+            right_node.values = child.values.split_off(at);
+            right_node.next = child.next;
+            separator
+        } else {
+            // The separator at `at` moves up, leaving at least one on the
+            // right.
+            let at = split_point(&child.keys).min(child.keys.len() - 2);
+            right_node.keys = child.keys.split_off(at + 1);
+            right_node.childrens = child.childrens.split_off(at + 1);
+            child.keys.pop().unwrap()
+        };
+
+        let right_id = self.nodes.alloc(right_node);
+        let child = &mut self.nodes[child_id];
+        if child.is_leaf {
+            child.next = Some(right_id);
+        }
+
+        let node = &mut self.nodes[id];
+        node.keys.insert(index, separator);
+        node.childrens.insert(index + 1, right_id);
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let root = self.root?;
+        let value = self.remove_from(root, key)?;
+
+        // The root lost its last key, either the tree is empty or the root
+        // has a single child left which takes its place. Otherwise the
+        // separators of the root may have changed, and it may not fit.
+        if self.nodes[root].keys.is_empty() {
+            let node = self.nodes.free(root);
+            self.root = node.childrens.first().copied();
+        } else {
+            self.fit_root();
+        }
+        Some(value)
+    }
+
+    // Remove the key from the subtree, then fix the child it was removed from
+    // if it has too few bytes left, or too many when its separators changed.
+    fn remove_from(&mut self, id: NodeId, key: &[u8]) -> Option<V> {
+        let node = &mut self.nodes[id];
+        if node.is_leaf {
+            let index = node.search(key).ok()?;
+            node.keys.remove(index);
+            return Some(node.values.remove(index));
+        }
+
+        let index = node.child_index(key);
+        let child = node.childrens[index];
+        let value = self.remove_from(child, key)?;
+
+        let size = self.nodes[child].size();
+        if size > self.node_bytes {
+            self.counters.splits += self.fit_child(id, index);
+        } else if size < self.node_bytes / 4 {
+            self.rebalance(id, index);
+        }
+        Some(value)
+    }
+
+    // Merge the child at `index` with its left sibling, or its right one for
+    // the first child. If they don't fit in a single node, split them again,
+    // which is the same as borrowing keys from the sibling.
+    fn rebalance(&mut self, id: NodeId, index: usize) {
+        let index = index.saturating_sub(1);
+        self.merge_childs(id, index);
+
+        match self.fit_child(id, index) {
+            0 => self.counters.merges += 1,
+            _ => self.counters.borrows += 1,
+        }
+    }
+
+    // Merge the child at `index + 1` into the one at `index`, and free it. The
+    // separator in between them moves down to an internal node, and is
+    // dropped for leaves.
+    fn merge_childs(&mut self, id: NodeId, index: usize) {
+        let node = &mut self.nodes[id];
+        let separator = node.keys.remove(index);
+        let left_id = node.childrens[index];
+        let right_id = node.childrens.remove(index + 1);
+
+        let mut right = self.nodes.free(right_id);
+        let left = &mut self.nodes[left_id];
+        if left.is_leaf {
+            let mut keys = left.take_keys();
+            keys.extend(right.take_keys());
+            left.set_keys(keys);
+            left.values.append(&mut right.values);
+            left.next = right.next;
+        } else {
+            left.keys.push(separator);
+            left.keys.append(&mut right.keys);
+            left.childrens.append(&mut right.childrens);
+        }
+    }
+
+    // Leaf which would hold the key.
+    fn find_leaf(&self, key: &[u8]) -> Option<NodeId> {
+        let mut id = self.root?;
+        while !self.nodes[id].is_leaf {
+            let node = &self.nodes[id];
+            id = node.childrens[node.child_index(key)];
+        }
+        Some(id)
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        let leaf = &self.nodes[self.find_leaf(key)?];
+        let index = leaf.search(key).ok()?;
+        Some(&leaf.values[index])
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        let id = self.find_leaf(key)?;
+        let leaf = &mut self.nodes[id];
+        let index = leaf.search(key).ok()?;
+        Some(&mut leaf.values[index])
+    }
+
+    // Entries whose key starts with the prefix. With composite keys encoded
+    // by `composite::encode`, the encoded prefix of a key is a prefix of its
+    // bytes, e.g. every `(tenant_id, timestamp)` of a tenant are the keys
+    // starting with `encode(&tenant_id)`.
+    pub fn range_prefix<'a>(&'a self, prefix: &'a [u8]) -> impl Iterator<Item = (Vec<u8>, &'a V)> {
+        // The keys starting with the prefix are at least the prefix, from
+        // where it would be inserted, which may be the end of the leaf.
+        let leaf = self.find_leaf(prefix).map(|id| &self.nodes[id]);
+        Iter {
+            nodes: &self.nodes,
+            leaf,
+            index: leaf.map_or(0, |leaf| match leaf.search(prefix) {
+                Ok(index) | Err(index) => index,
+            }),
+        }
+        .take_while(move |(key, _)| key.starts_with(prefix))
+    }
+
+    // The keys are put back together from the prefix and the suffix.
+    pub fn iter(&self) -> Iter<'_, V> {
+        let mut leaf = self.root.map(|root| &self.nodes[root]);
+        while let Some(&first) = leaf.and_then(|node| node.childrens.first()) {
+            leaf = Some(&self.nodes[first]);
+        }
+
+        Iter {
+            nodes: &self.nodes,
+            leaf,
+            index: 0,
+        }
+    }
+
+    // Number of levels, 0 for an empty tree.
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut node = self.root;
+        while let Some(id) = node {
+            height += 1;
+            node = self.nodes[id].childrens.first().copied();
+        }
+        height
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // Nodes are as full as their bytes over `node_bytes`. The keys count the
+    // prefixes, the suffixes and the separators.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            height: self.height(),
+            counters: self.counters,
+            ..Stats::default()
+        };
+        stats.heap_bytes.nodes = self.nodes.heap_bytes();
+
+        let mut stack: Vec<NodeId> = self.root.into_iter().collect();
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id];
+            stats.add_sized_node(node.keys.len(), node.size(), self.node_bytes, node.is_leaf);
+            stats.heap_bytes.keys += HeapBytes::of(&node.prefix)
+                + HeapBytes::of(&node.keys)
+                + node.keys.iter().map(HeapBytes::of).sum::<usize>();
+            stats.heap_bytes.values += HeapBytes::of(&node.values);
+            stats.heap_bytes.childrens += HeapBytes::of(&node.childrens);
+            stack.extend(&node.childrens);
+        }
+        stats
+    }
+
+    // Check that every node fits, that the keys are ordered and in between
+    // the separators around them, and that every leaf is at the same height.
+    // Also check that the leaves are linked from left to right, and that
+    // every node of the arena is in the tree. Nodes can be less than a
+    // quarter full, as splitting a leaf may give its halves longer prefixes,
+    // but only the root can be empty.
+    pub fn validate(&self) -> Result<(), String> {
+        let Some(root) = self.root else {
+            return match self.nodes.len() {
+                0 => Ok(()),
+                nodes => Err(format!("empty tree has {nodes} nodes")),
+            };
+        };
+
+        let mut leaves = vec![];
+        let mut reachable = 0;
+        self.validate_node(root, true, None, None, &mut leaves, &mut reachable)?;
+
+        for (i, &leaf) in leaves.iter().enumerate() {
+            if self.nodes[leaf].next != leaves.get(i + 1).copied() {
+                return Err(format!(
+                    "leaf {} is not linked to the next one",
+                    self.label(leaf)
+                ));
+            }
+        }
+        if reachable != self.nodes.len() {
+            return Err(format!(
+                "{} nodes but only {reachable} in the tree",
+                self.nodes.len()
+            ));
+        }
+        Ok(())
+    }
+
+    // Returns the height of the subtree, and push the leaves from left to
+    // right.
+    fn validate_node(
+        &self,
+        id: NodeId,
+        is_root: bool,
+        min: Option<&[u8]>,
+        max: Option<&[u8]>,
+        leaves: &mut Vec<NodeId>,
+        reachable: &mut usize,
+    ) -> Result<usize, String> {
+        *reachable += 1;
+        let node = &self.nodes[id];
+        let label = self.label(id);
+
+        if node.size() > self.node_bytes {
+            return Err(format!("{label} has {} bytes", node.size()));
+        }
+        if !is_root && node.keys.is_empty() {
+            return Err(format!("{label} has no keys"));
+        }
+        if node.keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(format!("{label} is not sorted"));
+        }
+        let out_of_range =
+            |key: &[u8]| min.is_some_and(|min| key < min) || max.is_some_and(|max| key >= max);
+        if (0..node.keys.len()).any(|i| out_of_range(&[&node.prefix[..], &node.keys[i]].concat())) {
+            return Err(format!("{label} is not in between {min:?} and {max:?}"));
+        }
+
+        if node.is_leaf {
+            if !node.childrens.is_empty() || node.keys.len() != node.values.len() {
+                return Err(format!("leaf {label} has childrens or missing values"));
+            }
+            leaves.push(id);
+            return Ok(0);
+        }
+
+        if !node.prefix.is_empty() || node.childrens.len() != node.keys.len() + 1 {
+            return Err(format!("{label} has {} childrens", node.childrens.len()));
+        }
+
+        let mut height = None;
+        for (i, &child) in node.childrens.iter().enumerate() {
+            let min = if i == 0 {
+                min
+            } else {
+                Some(&node.keys[i - 1][..])
+            };
+            let max = node.keys.get(i).map(|key| &key[..]).or(max);
+            let child_height = self.validate_node(child, false, min, max, leaves, reachable)?;
+
+            if *height.get_or_insert(child_height) != child_height {
+                return Err(format!("childrens of {label} are not at the same height"));
+            }
+        }
+        Ok(height.unwrap() + 1)
+    }
+
+    // A leaf shows its prefix before its suffixes, e.g. `/users/ [1, 2]`.
+    fn label(&self, id: NodeId) -> String {
+        let node = &self.nodes[id];
+        let keys: Vec<_> = node
+            .keys
+            .iter()
+            .map(|key| String::from_utf8_lossy(key))
+            .collect();
+        let keys = format!("[{}]", keys.join(", "));
+        match node.prefix.is_empty() {
+            true => keys,
+            false => format!("{} {keys}", String::from_utf8_lossy(&node.prefix)),
+        }
+    }
+
+    fn block(&self, id: NodeId) -> Block {
+        let childrens = self.nodes[id]
+            .childrens
+            .iter()
+            .map(|&child| self.block(child))
+            .collect();
+        Block::node(self.label(id), childrens)
+    }
+
+    // Draw the tree, with the keys as UTF-8.
+    pub fn render(&self) -> Rendered {
+        Rendered::from(self.root.map(|root| self.block(root)))
+    }
+
+    pub fn print(&self) {
+        print!("{self}");
+    }
+}
+
+impl<V> Default for BytesBPlusTree<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Display for BytesBPlusTree<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+// In order iterator, following the leaves from the first one.
+pub struct Iter<'a, V> {
+    nodes: &'a Arena<Node<V>>,
+    leaf: Option<&'a Node<V>>,
+    index: usize, // Next entry of the leaf.
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (Vec<u8>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(leaf) = self.leaf {
+            if self.index < leaf.keys.len() {
+                self.index += 1;
+                return Some((leaf.key(self.index - 1), &leaf.values[self.index - 1]));
+            }
+
+            self.leaf = leaf.next.map(|next| &self.nodes[next]);
+            self.index = 0;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{shortest_separator, BytesBPlusTree};
+    use crate::composite::{decode, encode};
+    use crate::stats::Counters;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    fn url(i: u32) -> Vec<u8> {
+        format!("https://example.com/api/v1/users/{i:06}/profile").into_bytes()
+    }
+
+    #[test]
+    fn shortest_separators() {
+        assert_eq!(
+            shortest_separator(b"/users/1/posts", b"/users/2/posts"),
+            b"/users/2"
+        );
+        assert_eq!(shortest_separator(b"ab", b"abc"), b"abc");
+        assert_eq!(shortest_separator(b"", b"b"), b"b");
+    }
+
+    #[test]
+    fn leaves_share_a_prefix() {
+        let mut tree = BytesBPlusTree::with_node_bytes(128);
+        for key in ["/users/2", "/users/1/posts", "/users/2/posts"] {
+            tree.insert(key.as_bytes(), ());
+        }
+        assert_eq!(tree.to_string(), "/users/ [1/posts, 2, 2/posts]\n");
+
+        // Once split, each leaf has the prefix of its own keys, and the
+        // separators are only as long as needed to tell the leaves apart.
+        for i in 0..200 {
+            tree.insert(format!("/users/{i:03}/posts").as_bytes(), ());
+        }
+        assert_eq!(tree.validate(), Ok(()));
+
+        let mut stack = vec![tree.root.unwrap()];
+        while let Some(id) = stack.pop() {
+            let node = &tree.nodes[id];
+            if node.is_leaf {
+                assert!(node.prefix.starts_with(b"/users/"), "{}", tree.label(id));
+            } else {
+                assert!(node.keys.iter().all(|key| key.len() <= "/users/000".len()));
+            }
+            stack.extend(&node.childrens);
+        }
+    }
+
+    #[test]
+    fn random_inserts_and_removes() {
+        let mut counters = Counters::default();
+        for node_bytes in [64, 128, 512] {
+            for seed in 0..20 {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let mut tree = BytesBPlusTree::with_node_bytes(node_bytes);
+                let mut map = BTreeMap::new();
+
+                for _ in 0..500 {
+                    // Keys of various lengths, many sharing a prefix.
+                    let len = rng.gen_range(0..=tree.max_key_len().min(12));
+                    let key: Vec<u8> = (0..len).map(|_| rng.gen_range(b'a'..b'e')).collect();
+                    if rng.gen_bool(0.6) {
+                        tree.insert(&key, key.len());
+                        map.entry(key).or_insert(len);
+                    } else {
+                        assert_eq!(tree.remove(&key), map.remove(&key), "remove {key:?}");
+                    }
+                    assert_eq!(tree.validate(), Ok(()), "{node_bytes} bytes, seed {seed}");
+                }
+                assert!(tree
+                    .iter()
+                    .eq(map.iter().map(|(key, value)| (key.clone(), value))));
+                for key in map.keys() {
+                    assert_eq!(tree.get(key), map.get(key));
+                }
+                let stats = tree.stats();
+                counters.merges += stats.counters.merges;
+                counters.borrows += stats.counters.borrows;
+            }
+        }
+        assert!(counters.merges > 0 && counters.borrows > 0, "{counters:?}");
+    }
+
+    #[test]
+    fn long_keys_fit_more_per_node() {
+        let mut tree = BytesBPlusTree::new();
+        for i in 0..10_000 {
+            tree.insert(&url(i), i);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.get(&url(1234)), Some(&1234));
+        *tree.get_mut(&url(1234)).unwrap() += 1;
+        assert_eq!(tree.get(&url(1234)), Some(&1235));
+
+        // 44 bytes keys in 4096 bytes nodes: at most 78 keys per node
+        // uncompressed, while the leaves hold more than that only half full
+        // after inserting the keys in order.
+        let stats = tree.stats();
+        assert!(stats.leaf_nodes < 10_000 / 78, "{stats}");
+        for i in (0..10_000).step_by(2) {
+            assert_eq!(tree.remove(&url(i)), Some(i + (i == 1234) as u32));
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree.stats().counters.merges > 0);
+        assert!(tree
+            .iter()
+            .map(|(key, _)| key)
+            .eq((1..10_000).step_by(2).map(url)));
+    }
+
+    #[test]
+    fn range_prefix_of_encoded_composite_keys() {
+        let mut tree = BytesBPlusTree::with_node_bytes(128);
+        let tenants = ["", "a", "a\0", "ab", "b"];
+        for (i, tenant) in tenants.iter().enumerate() {
+            for timestamp in [-5i64, 0, 1, 1 << 40] {
+                tree.insert(&encode(&(tenant.to_string(), timestamp)), i);
+            }
+        }
+        assert_eq!(tree.validate(), Ok(()));
+
+        for (i, tenant) in tenants.iter().enumerate() {
+            let prefix = encode(&tenant.to_string());
+            let entries: Vec<_> = tree.range_prefix(&prefix).collect();
+            assert!(entries.iter().all(|&(_, &value)| value == i));
+            let timestamps: Vec<(String, i64)> = entries
+                .iter()
+                .map(|(key, _)| decode(key).unwrap())
+                .collect();
+            assert_eq!(
+                timestamps.iter().map(|key| key.1).collect::<Vec<_>>(),
+                [-5, 0, 1, 1 << 40]
+            );
+        }
+        assert_eq!(tree.range_prefix(&encode(&"c".to_string())).count(), 0);
+        assert_eq!(tree.range_prefix(b"").count(), 20);
+    }
+
+    #[test]
+    #[should_panic]
+    fn key_longer_than_a_quarter_of_a_node() {
+        let mut tree = BytesBPlusTree::with_node_bytes(64);
+        tree.insert(&[0; 9], ());
+    }
+}
@@ -0,0 +1,44 @@
+use std::cmp::Ordering;
+
+use crate::simd;
+
+// How the keys of a tree are ordered, so keys can be ordered differently than
+// their `Ord` without a newtype wrapper, e.g. strings case-insensitively or
+// floats with `f64::total_cmp`:
+//
+//   let tree = BPlusTree::with_comparator(8, f64::total_cmp);
+//
+// Any `Fn(&K, &K) -> Ordering` closure is a comparator. It is stored in the
+// tree and used for every comparison of keys, so it must be a total order and
+// must not change while the tree holds keys.
+pub trait Comparator<K> {
+    fn compare(&self, a: &K, b: &K) -> Ordering;
+
+    // Search a key in sorted keys, the same as `binary_search`: `Ok` with the
+    // index of the key, or `Err` with the index to insert it at.
+    fn search(&self, keys: &[K], key: &K) -> Result<usize, usize> {
+        keys.binary_search_by(|other| self.compare(other, key))
+    }
+}
+
+// The `Ord` order of the keys, the comparator of a tree unless another one is
+// given. Wide nodes of `u32` and `i32` keys are searched with SIMD, see
+// `src/simd.rs`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Natural;
+
+impl<K: Ord + 'static> Comparator<K> for Natural {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        a.cmp(b)
+    }
+
+    fn search(&self, keys: &[K], key: &K) -> Result<usize, usize> {
+        simd::search(keys, key)
+    }
+}
+
+impl<K, F: Fn(&K, &K) -> Ordering> Comparator<K> for F {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        self(a, b)
+    }
+}
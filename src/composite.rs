@@ -0,0 +1,233 @@
+use std::cmp::Ordering;
+
+// Composite keys, e.g. `(tenant_id, timestamp)` for a multi-column index.
+// Tuples are ordered by their first element, then by the next one, so every
+// key sharing a prefix is next to each other in the tree, and
+// `BPlusTree::range_prefix` iterates them:
+//
+//   (1, 10) (1, 20) (2, 5) (2, 7) (2, 9) (3, 1)
+//           range_prefix(&2) = ^^^^^^^^^^^^^^
+//
+// A key compares to its prefixes with `cmp_prefix`. A tuple has a prefix for
+// its first element, and a 3-tuple also for its first two elements.
+pub trait Prefix<P> {
+    // How the start of the key compares to the prefix, `Equal` when the key
+    // starts with it.
+    fn cmp_prefix(&self, prefix: &P) -> Ordering;
+}
+
+impl<A: Ord, B> Prefix<A> for (A, B) {
+    fn cmp_prefix(&self, prefix: &A) -> Ordering {
+        self.0.cmp(prefix)
+    }
+}
+
+impl<A: Ord, B, C> Prefix<A> for (A, B, C) {
+    fn cmp_prefix(&self, prefix: &A) -> Ordering {
+        self.0.cmp(prefix)
+    }
+}
+
+impl<A: Ord, B: Ord, C> Prefix<(A, B)> for (A, B, C) {
+    fn cmp_prefix(&self, prefix: &(A, B)) -> Ordering {
+        (&self.0, &self.1).cmp(&(&prefix.0, &prefix.1))
+    }
+}
+
+// Encoding of keys to bytes which compare in the same order as the keys, for
+// composite keys in a tree of byte keys, see `src/bytes_bplustree.rs`:
+//
+//   unsigned integers   big endian
+//   signed integers     big endian with the sign bit flipped, so negative
+//                       numbers come first
+//   strings             the bytes with 0x00 escaped as 0x00 0xFF, then
+//                       0x00 0x01, so a string is before any longer string
+//                       starting with it
+//   tuples              their elements one after the other
+//
+// Every element knows where it ends, so the encoding of a tuple starts with
+// the encoding of its prefixes, and `BytesBPlusTree::range_prefix` with the
+// encoded prefix iterates the keys starting with it.
+pub trait OrderedBytes: Sized {
+    fn write_ordered(&self, bytes: &mut Vec<u8>);
+
+    // Read a key back from the start of the bytes, and move them past it.
+    fn read_ordered(bytes: &mut &[u8]) -> Option<Self>;
+}
+
+pub fn encode<K: OrderedBytes>(key: &K) -> Vec<u8> {
+    let mut bytes = vec![];
+    key.write_ordered(&mut bytes);
+    bytes
+}
+
+// `None` when the bytes aren't the encoding of a key, or have bytes left after it.
+pub fn decode<K: OrderedBytes>(mut bytes: &[u8]) -> Option<K> {
+    let key = K::read_ordered(&mut bytes)?;
+    bytes.is_empty().then_some(key)
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    let taken = bytes.get(..len)?;
+    *bytes = &bytes[len..];
+    Some(taken)
+}
+
+macro_rules! unsigned_ordered_bytes {
+    ($($type:ty),*) => {$(
+        impl OrderedBytes for $type {
+            fn write_ordered(&self, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(&self.to_be_bytes());
+            }
+
+            fn read_ordered(bytes: &mut &[u8]) -> Option<Self> {
+                let taken = take(bytes, size_of::<$type>())?;
+                Some(<$type>::from_be_bytes(taken.try_into().unwrap()))
+            }
+        }
+    )*};
+}
+
+macro_rules! signed_ordered_bytes {
+    ($($type:ty => $unsigned:ty),*) => {$(
+        impl OrderedBytes for $type {
+            fn write_ordered(&self, bytes: &mut Vec<u8>) {
+                ((*self as $unsigned) ^ (1 << (<$unsigned>::BITS - 1))).write_ordered(bytes);
+            }
+
+            fn read_ordered(bytes: &mut &[u8]) -> Option<Self> {
+                let unsigned = <$unsigned>::read_ordered(bytes)?;
+                Some((unsigned ^ (1 << (<$unsigned>::BITS - 1))) as $type)
+            }
+        }
+    )*};
+}
+
+unsigned_ordered_bytes!(u8, u16, u32, u64, u128);
+signed_ordered_bytes!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+fn write_escaped(key: &[u8], bytes: &mut Vec<u8>) {
+    for &byte in key {
+        bytes.push(byte);
+        if byte == 0 {
+            bytes.push(0xFF);
+        }
+    }
+    bytes.extend_from_slice(&[0x00, 0x01]);
+}
+
+impl OrderedBytes for Vec<u8> {
+    fn write_ordered(&self, bytes: &mut Vec<u8>) {
+        write_escaped(self, bytes);
+    }
+
+    fn read_ordered(bytes: &mut &[u8]) -> Option<Self> {
+        let mut key = vec![];
+        loop {
+            match *take(bytes, 1)? {
+                [0] => match *take(bytes, 1)? {
+                    [0xFF] => key.push(0),
+                    [0x01] => return Some(key),
+                    _ => return None,
+                },
+                [byte] => key.push(byte),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+impl OrderedBytes for String {
+    fn write_ordered(&self, bytes: &mut Vec<u8>) {
+        write_escaped(self.as_bytes(), bytes);
+    }
+
+    fn read_ordered(bytes: &mut &[u8]) -> Option<Self> {
+        String::from_utf8(Vec::read_ordered(bytes)?).ok()
+    }
+}
+
+macro_rules! tuple_ordered_bytes {
+    ($($name:ident),*) => {
+        impl<$($name: OrderedBytes),*> OrderedBytes for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn write_ordered(&self, bytes: &mut Vec<u8>) {
+                let ($($name,)*) = self;
+                $($name.write_ordered(bytes);)*
+            }
+
+            fn read_ordered(bytes: &mut &[u8]) -> Option<Self> {
+                Some(($($name::read_ordered(bytes)?,)*))
+            }
+        }
+    };
+}
+
+tuple_ordered_bytes!(A, B);
+tuple_ordered_bytes!(A, B, C);
+tuple_ordered_bytes!(A, B, C, D);
+
+#[cfg(test)]
+mod test {
+    use super::{decode, encode, OrderedBytes};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::fmt::Debug;
+
+    // Encoded keys compare as the keys, and decode back to them.
+    fn check_order<K: OrderedBytes + Ord + Debug>(mut keys: Vec<K>) {
+        keys.sort();
+        let encoded: Vec<_> = keys.iter().map(encode).collect();
+        for (window, key) in encoded.windows(2).zip(&keys) {
+            assert!(window[0] <= window[1], "{key:?}");
+        }
+        for (bytes, key) in encoded.iter().zip(&keys) {
+            assert_eq!(decode::<K>(bytes).as_ref(), Some(key));
+        }
+    }
+
+    #[test]
+    fn integers() {
+        let mut rng = StdRng::seed_from_u64(0);
+        check_order((0..1000).map(|_| rng.gen::<u64>()).collect());
+        check_order((0..1000).map(|_| rng.gen::<i32>()).collect());
+        check_order(vec![i64::MIN, -1, 0, 1, i64::MAX]);
+        check_order(vec![i8::MIN, -1, 0, 1, i8::MAX]);
+    }
+
+    #[test]
+    fn strings() {
+        let strings = [
+            "", "\0", "\0\0", "\0\x01", "\x01", "a", "a\0", "a\0b", "ab", "b",
+        ];
+        check_order(strings.iter().map(|s| s.to_string()).collect());
+        assert_eq!(encode(&"a\0".to_string()), b"a\0\xFF\0\x01");
+    }
+
+    #[test]
+    fn tuples() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let names = ["", "a", "a\0", "ab", "b"];
+        check_order(
+            (0..1000)
+                .map(|_| {
+                    let name = names[rng.gen_range(0..names.len())].to_string();
+                    (name, rng.gen_range(-3..3i64), rng.gen_range(0..3u8))
+                })
+                .collect(),
+        );
+
+        // The encoding of a tuple starts with the encoding of its prefix.
+        let key = ("tenant".to_string(), 42u32);
+        assert!(encode(&key).starts_with(&encode(&key.0)));
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        assert_eq!(decode::<u32>(&[1, 2, 3]), None);
+        assert_eq!(decode::<u8>(&[1, 2]), None);
+        assert_eq!(decode::<String>(b"a\0"), None);
+        assert_eq!(decode::<String>(b"a\0\x02"), None);
+        assert_eq!(decode::<String>(b"\xFF\0\x01"), None);
+    }
+}
@@ -0,0 +1,489 @@
+use parking_lot::lock_api::{ArcRwLockReadGuard, ArcRwLockWriteGuard};
+use parking_lot::{RawRwLock, RwLock};
+use std::sync::Arc;
+
+// A B+ Tree that can be shared between threads.
+//
+// Every node is protected by its own latch (`RwLock`). Operations walk
+// down the tree using latch crabbing:
+//
+// - `get` takes a read latch on the child before releasing the parent.
+// - `insert` and `remove` take write latches all the way down, but release
+//   every ancestor as soon as the current node is "safe", which means the
+//   operation can't split or merge it, so the change can't propagate
+//   upward.
+//
+// The root node is never replaced. When it splits, its content is moved
+// into two new children. When it is left with a single child, the content
+// of that child is pulled back up. Hence, the root latch is the only entry
+// point of the tree and there is no separate latch for the root pointer.
+type Latch = Arc<RwLock<Node>>;
+type ReadGuard = ArcRwLockReadGuard<RawRwLock, Node>;
+type WriteGuard = ArcRwLockWriteGuard<RawRwLock, Node>;
+
+pub struct ConcurrentBPlusTree {
+    root: Latch,
+    max_degree: usize,
+}
+
+struct Node {
+    keys: Vec<u32>,        // At most max_degree - 1 keys
+    values: Vec<u32>,      // Only in leaf node.
+    childrens: Vec<Latch>, // At most max_degree children
+    is_leaf: bool,
+}
+
+impl Node {
+    pub fn new(is_leaf: bool) -> Self {
+        Node {
+            keys: vec![],
+            values: vec![],
+            childrens: Vec::new(),
+            is_leaf,
+        }
+    }
+
+    // Index of the child that might contain the key.
+    //
+    // Keys equal to a separator live in the right subtree.
+    fn child_index(&self, key: &u32) -> usize {
+        match self.keys.binary_search(key) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        }
+    }
+
+    // Inserting a key into a safe node can't cause it to split.
+    fn is_safe_for_insert(&self, max_degree: usize) -> bool {
+        self.keys.len() + 1 < max_degree
+    }
+
+    // Removing a key from a safe node can't cause it to underflow.
+    fn is_safe_for_remove(&self, max_degree: usize) -> bool {
+        self.keys.len() > min_key(max_degree)
+    }
+
+    // Split a node that has `max_degree` keys, keeping the left half in
+    // place and returning the separator with the new right node.
+    fn split(&mut self, max_degree: usize) -> (u32, Node) {
+        let breakpoint = max_degree / 2;
+        let mut right_node = Node::new(self.is_leaf);
+
+        if self.is_leaf {
+            // Leaf node keep a copy of the separator in the right node.
+            right_node.keys = self.keys.split_off(breakpoint);
+            right_node.values = self.values.split_off(breakpoint);
+            (right_node.keys[0], right_node)
+        } else {
+            // Internal node move the separator up to the parent.
+            right_node.keys = self.keys.split_off(breakpoint + 1);
+            right_node.childrens = self.childrens.split_off(breakpoint + 1);
+            let separator = self.keys.pop().unwrap();
+            (separator, right_node)
+        }
+    }
+}
+
+fn min_key(max_degree: usize) -> usize {
+    (max_degree - 1) / 2
+}
+
+fn new_latch(node: Node) -> Latch {
+    Arc::new(RwLock::new(node))
+}
+
+// Split the full child at `index` of the parent.
+fn split_child(parent: &mut WriteGuard, child: &mut WriteGuard, index: usize, max_degree: usize) {
+    let (separator, right_node) = child.split(max_degree);
+    parent.keys.insert(index, separator);
+    parent.childrens.insert(index + 1, new_latch(right_node));
+}
+
+// Split the root in place:
+//
+//   [1, 2, 3, 4]
+//
+// become:
+//
+//       [3]
+//   [1, 2]  [3, 4]
+fn split_root(root: &mut WriteGuard, max_degree: usize) {
+    let mut left_node = std::mem::replace(&mut **root, Node::new(false));
+    let (separator, right_node) = left_node.split(max_degree);
+
+    root.keys.push(separator);
+    root.childrens.push(new_latch(left_node));
+    root.childrens.push(new_latch(right_node));
+}
+
+// Fix the child at `index` which has less than the minimum number of keys,
+// by borrowing a key from one of its immediate siblings, or merging with
+// one of them if both of them only have the minimum number of keys.
+fn rebalance(parent: &mut WriteGuard, child: &mut WriteGuard, index: usize, max_degree: usize) {
+    let min_key = min_key(max_degree);
+
+    if index > 0 {
+        let mut left = parent.childrens[index - 1].write_arc();
+
+        if left.keys.len() > min_key {
+            if child.is_leaf {
+                //    [3]            [2]
+                // [1, 2]  []  ->  [1]  [2]
+                let key = left.keys.pop().unwrap();
+                let value = left.values.pop().unwrap();
+                child.keys.insert(0, key);
+                child.values.insert(0, value);
+                parent.keys[index - 1] = key;
+            } else {
+                let key = std::mem::replace(&mut parent.keys[index - 1], left.keys.pop().unwrap());
+                child.keys.insert(0, key);
+                child.childrens.insert(0, left.childrens.pop().unwrap());
+            }
+
+            return;
+        }
+
+        if index + 1 == parent.childrens.len() {
+            // Merge child into the left sibling:
+            //
+            //    [2, 3]               [2]
+            // [1]  [2]  []  ->  [1]  [2]
+            let key = parent.keys.remove(index - 1);
+            parent.childrens.remove(index);
+
+            if !child.is_leaf {
+                left.keys.push(key);
+            }
+            left.keys.append(&mut child.keys);
+            left.values.append(&mut child.values);
+            left.childrens.append(&mut child.childrens);
+
+            return;
+        }
+    }
+
+    let mut right = parent.childrens[index + 1].write_arc();
+
+    if right.keys.len() > min_key {
+        if child.is_leaf {
+            //      [3]              [4]
+            // []  [3, 4]  ->  [3]  [4]
+            child.keys.push(right.keys.remove(0));
+            child.values.push(right.values.remove(0));
+            parent.keys[index] = right.keys[0];
+        } else {
+            let key = std::mem::replace(&mut parent.keys[index], right.keys.remove(0));
+            child.keys.push(key);
+            child.childrens.push(right.childrens.remove(0));
+        }
+    } else {
+        // Merge the right sibling into child:
+        //
+        //    [2, 3]             [3]
+        // []  [2]  [3]  ->  [2]  [3]
+        let key = parent.keys.remove(index);
+        parent.childrens.remove(index + 1);
+
+        if !child.is_leaf {
+            child.keys.push(key);
+        }
+        child.keys.append(&mut right.keys);
+        child.values.append(&mut right.values);
+        child.childrens.append(&mut right.childrens);
+    }
+}
+
+impl ConcurrentBPlusTree {
+    pub fn new(numbers: Vec<u32>, max_degree: usize) -> Self {
+        assert!(max_degree >= 3, "max_degree must be at least 3");
+
+        let tree = Self {
+            root: new_latch(Node::new(true)),
+            max_degree,
+        };
+
+        for i in numbers {
+            tree.insert(i);
+        }
+
+        tree
+    }
+
+    pub fn insert(&self, key: u32) {
+        let mut path: Vec<WriteGuard> = vec![self.root.write_arc()];
+
+        loop {
+            let node = path.last().unwrap();
+            if node.is_leaf {
+                break;
+            }
+
+            let child = node.childrens[node.child_index(&key)].write_arc();
+            if child.is_safe_for_insert(self.max_degree) {
+                path.clear();
+            }
+            path.push(child);
+        }
+
+        let leaf = path.last_mut().unwrap();
+        match leaf.keys.binary_search(&key) {
+            // Ignore if key is duplicated first
+            Ok(_index) => return,
+            Err(index) => {
+                leaf.keys.insert(index, key);
+                leaf.values.insert(index, key);
+            }
+        }
+
+        // Only the latches of nodes that might split are still held,
+        // propagate the split upward.
+        while let Some(mut node) = path.pop() {
+            if node.keys.len() < self.max_degree {
+                break;
+            }
+
+            match path.last_mut() {
+                Some(parent) => {
+                    let index = parent.child_index(&key);
+                    split_child(parent, &mut node, index, self.max_degree);
+                }
+                None => split_root(&mut node, self.max_degree),
+            }
+        }
+    }
+
+    pub fn remove(&self, key: &u32) -> Option<u32> {
+        let mut path: Vec<WriteGuard> = vec![self.root.write_arc()];
+
+        loop {
+            let node = path.last().unwrap();
+            if node.is_leaf {
+                break;
+            }
+
+            let child = node.childrens[node.child_index(key)].write_arc();
+            if child.is_safe_for_remove(self.max_degree) {
+                path.clear();
+            }
+            path.push(child);
+        }
+
+        let leaf = path.last_mut().unwrap();
+        let result = match leaf.keys.binary_search(key) {
+            Ok(index) => {
+                leaf.keys.remove(index);
+                Some(leaf.values.remove(index))
+            }
+            Err(_) => return None,
+        };
+
+        // Separator in internal nodes are not updated, as they remain valid
+        // to route keys even if the key itself is no longer in the tree.
+        let min_key = min_key(self.max_degree);
+        while path.len() > 1 {
+            let mut node = path.pop().unwrap();
+            if node.keys.len() >= min_key {
+                break;
+            }
+
+            let parent = path.last_mut().unwrap();
+            let index = parent.child_index(key);
+            rebalance(parent, &mut node, index, self.max_degree);
+        }
+
+        // If root is only left with a single child, pull the content of the
+        // child up to the root, reducing the height of the tree.
+        if let [root] = path.as_mut_slice() {
+            let is_root = Arc::ptr_eq(WriteGuard::rwlock(root), &self.root);
+
+            if is_root && !root.is_leaf && root.keys.is_empty() {
+                let child = root.childrens.pop().unwrap();
+                let mut child = child.write_arc();
+                **root = std::mem::replace(&mut *child, Node::new(true));
+            }
+        }
+
+        result
+    }
+
+    pub fn get(&self, key: &u32) -> Option<u32> {
+        let mut node: ReadGuard = self.root.read_arc();
+
+        while !node.is_leaf {
+            let child = node.childrens[node.child_index(key)].read_arc();
+            node = child;
+        }
+
+        node.keys
+            .binary_search(key)
+            .ok()
+            .map(|index| node.values[index])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{min_key, ConcurrentBPlusTree, Latch};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::thread;
+
+    // Collect the keys in order while asserting that every node is within
+    // its bounds and that every leaf is at the same depth.
+    fn check(tree: &ConcurrentBPlusTree) -> Vec<u32> {
+        fn walk(
+            latch: &Latch,
+            tree: &ConcurrentBPlusTree,
+            depth: usize,
+            is_root: bool,
+            leaf_depth: &mut Option<usize>,
+            keys: &mut Vec<u32>,
+        ) {
+            let node = latch.read();
+            assert!(node.keys.len() < tree.max_degree);
+            if !is_root {
+                assert!(node.keys.len() >= min_key(tree.max_degree));
+            }
+
+            if node.is_leaf {
+                assert_eq!(*leaf_depth.get_or_insert(depth), depth);
+                keys.extend_from_slice(&node.keys);
+            } else {
+                assert_eq!(node.childrens.len(), node.keys.len() + 1);
+                for child in &node.childrens {
+                    walk(child, tree, depth + 1, false, leaf_depth, keys);
+                }
+            }
+        }
+
+        let mut keys = vec![];
+        walk(&tree.root, tree, 0, true, &mut None, &mut keys);
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        keys
+    }
+
+    #[test]
+    fn get_on_empty_tree() {
+        let tree = ConcurrentBPlusTree::new(vec![], 4);
+        assert_eq!(tree.get(&2), None);
+    }
+
+    #[test]
+    fn insert_and_split_on_level_5_leaf_node() {
+        let vec: Vec<u32> = (1..82).collect();
+        let tree = ConcurrentBPlusTree::new(vec.clone(), 4);
+
+        tree.insert(82);
+        assert_eq!(tree.get(&82), Some(82));
+
+        for v in vec {
+            assert_eq!(tree.get(&v), Some(v));
+        }
+        assert_eq!(check(&tree), (1..83).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn delete_all_keys_from_left_to_right() {
+        let vec: Vec<u32> = (1..200).collect();
+        let tree = ConcurrentBPlusTree::new(vec.clone(), 3);
+
+        for &v in &vec {
+            assert_eq!(tree.remove(&v), Some(v));
+            assert_eq!(tree.get(&v), None);
+            check(&tree);
+        }
+
+        assert_eq!(tree.remove(&1), None);
+        assert!(check(&tree).is_empty());
+    }
+
+    #[test]
+    fn delete_all_keys_from_right_to_left() {
+        let vec: Vec<u32> = (1..200).collect();
+        let tree = ConcurrentBPlusTree::new(vec.clone(), 5);
+
+        for &v in vec.iter().rev() {
+            assert_eq!(tree.remove(&v), Some(v));
+            check(&tree);
+        }
+
+        assert!(check(&tree).is_empty());
+    }
+
+    use rand::seq::SliceRandom;
+    use rand::{thread_rng, Rng};
+    #[test]
+    fn delete_all_keys_randomly() {
+        for max_degree in 3..8 {
+            let mut vec: Vec<u32> = (1..200).collect();
+            vec.shuffle(&mut thread_rng());
+            let tree = ConcurrentBPlusTree::new(vec.clone(), max_degree);
+
+            vec.shuffle(&mut thread_rng());
+            for &v in &vec {
+                assert_eq!(tree.remove(&v), Some(v));
+            }
+            assert!(check(&tree).is_empty());
+        }
+    }
+
+    #[test]
+    fn is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ConcurrentBPlusTree>();
+    }
+
+    // Each thread owns the keys that are equal to its id modulo the number
+    // of threads, so the result of every operation can be checked against
+    // a sequential model owned by that thread, while all the threads still
+    // split and merge the same nodes.
+    #[test]
+    fn parallel_insert_remove_and_get() {
+        const THREADS: u32 = 8;
+        const OPERATIONS: usize = 20_000;
+        const KEYS: u32 = 2_000;
+
+        for max_degree in [3, 4, 7] {
+            let tree = Arc::new(ConcurrentBPlusTree::new(vec![], max_degree));
+
+            let handles: Vec<_> = (0..THREADS)
+                .map(|id| {
+                    let tree = Arc::clone(&tree);
+                    thread::spawn(move || {
+                        let mut rng = thread_rng();
+                        let mut model = BTreeMap::new();
+
+                        for _ in 0..OPERATIONS {
+                            let key = rng.gen_range(0..KEYS / THREADS) * THREADS + id;
+                            match rng.gen_range(0..3) {
+                                0 => {
+                                    tree.insert(key);
+                                    model.insert(key, key);
+                                }
+                                1 => assert_eq!(tree.remove(&key), model.remove(&key)),
+                                _ => assert_eq!(tree.get(&key), model.get(&key).copied()),
+                            }
+
+                            // Keys owned by other threads can be read, but
+                            // the result depends on the interleaving.
+                            let other = rng.gen_range(0..KEYS);
+                            if let Some(value) = tree.get(&other) {
+                                assert_eq!(value, other);
+                            }
+                        }
+
+                        model
+                    })
+                })
+                .collect();
+
+            let mut expected: Vec<u32> = handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap().into_keys())
+                .collect();
+            expected.sort();
+
+            assert_eq!(check(&tree), expected);
+        }
+    }
+}
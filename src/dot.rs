@@ -0,0 +1,89 @@
+use std::fmt::{self, Write};
+
+// Graphviz helpers shared by the `to_dot` of every tree.
+//
+// Every node is a record, one field per key, with a port in between the keys
+// for the edge to each child:
+//
+//   node1 [label="<c0>|3|<c1>|6|<c2>"];
+//   node1:c0 -> node2;
+//
+// Nodes are numbered in pre-order, so the same tree always gives the same
+// output.
+
+// Render a whole graph, `write_nodes` writing the nodes and the edges.
+pub(crate) fn render<F>(name: &str, write_nodes: F) -> String
+where
+    F: FnOnce(&mut String) -> fmt::Result,
+{
+    let mut dot = String::new();
+    writeln!(dot, "digraph {name} {{")
+        .and_then(|_| writeln!(dot, "  node [shape=record, height=0.1];"))
+        .and_then(|_| write_nodes(&mut dot))
+        .and_then(|_| writeln!(dot, "}}"))
+        .expect("writing to a String can't fail");
+    dot
+}
+
+// Escape the characters that have a meaning inside a record label.
+pub(crate) fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>' | '"' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Label of a node with keys, with a port for each child when it has childrens.
+pub(crate) fn keys_label<I>(keys: I, has_childrens: bool) -> String
+where
+    I: IntoIterator,
+    I::Item: fmt::Display,
+{
+    let mut fields = vec![];
+    let mut port = 0;
+    for key in keys {
+        if has_childrens {
+            fields.push(format!("<c{port}>"));
+            port += 1;
+        }
+        fields.push(escape(&key.to_string()));
+    }
+    if has_childrens {
+        fields.push(format!("<c{port}>"));
+    }
+    fields.join("|")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape, keys_label, render};
+
+    #[test]
+    fn escape_record_characters() {
+        assert_eq!(escape("a|b"), "a\\|b");
+        assert_eq!(escape("{\"x\"}"), "\\{\\\"x\\\"\\}");
+        assert_eq!(escape("<1>"), "\\<1\\>");
+    }
+
+    #[test]
+    fn render_graph() {
+        let dot = render("Tree", |dot| {
+            use std::fmt::Write;
+            writeln!(dot, "  node0 [label=\"1\"];")
+        });
+        assert_eq!(
+            dot,
+            "digraph Tree {\n  node [shape=record, height=0.1];\n  node0 [label=\"1\"];\n}\n"
+        );
+    }
+
+    #[test]
+    fn ports_between_keys() {
+        assert_eq!(keys_label([3, 6], true), "<c0>|3|<c1>|6|<c2>");
+        assert_eq!(keys_label([3, 6], false), "3|6");
+    }
+}
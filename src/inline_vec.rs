@@ -0,0 +1,155 @@
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::ops::{Deref, DerefMut};
+
+// A vector of at most `N` items stored inline, e.g. in a node instead of in a
+// separate heap allocation:
+//
+//   items: [ 1 | 2 | 3 | ? | ? ]   len 3, capacity 5
+//
+// Only the first `len` items are initialized. Inserting or removing an item
+// shifts the ones after it with a single `copy_within`, and `split_off` or
+// `append` copy a whole range at once. The items are `Copy`, so none of them
+// needs to be dropped. It derefs to a slice for everything else, e.g.
+// `binary_search` or `iter`.
+pub struct InlineVec<T: Copy, const N: usize> {
+    len: usize,
+    items: [MaybeUninit<T>; N],
+}
+
+impl<T: Copy, const N: usize> InlineVec<T, N> {
+    pub fn new() -> Self {
+        InlineVec {
+            len: 0,
+            items: [MaybeUninit::uninit(); N],
+        }
+    }
+
+    pub fn from_slice(items: &[T]) -> Self {
+        let mut vec = Self::new();
+        vec.extend_from_slice(items);
+        vec
+    }
+
+    pub fn push(&mut self, item: T) {
+        assert!(self.len < N, "inline vec is full");
+        self.items[self.len] = MaybeUninit::new(item);
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let item = *self.last()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    pub fn insert(&mut self, index: usize, item: T) {
+        assert!(index <= self.len, "index out of bounds");
+        assert!(self.len < N, "inline vec is full");
+        self.items.copy_within(index..self.len, index + 1);
+        self.items[index] = MaybeUninit::new(item);
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        let item = self[index];
+        self.items.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        item
+    }
+
+    // Move the items from `at` to a new vector.
+    pub fn split_off(&mut self, at: usize) -> Self {
+        let other = Self::from_slice(&self[at..]);
+        self.len = at;
+        other
+    }
+
+    // Move every item of `other` at the end.
+    pub fn append(&mut self, other: &mut Self) {
+        self.extend_from_slice(other);
+        other.len = 0;
+    }
+
+    pub fn extend_from_slice(&mut self, items: &[T]) {
+        assert!(self.len + items.len() <= N, "inline vec is full");
+        for (slot, &item) in self.items[self.len..].iter_mut().zip(items) {
+            *slot = MaybeUninit::new(item);
+        }
+        self.len += items.len();
+    }
+}
+
+impl<T: Copy, const N: usize> Deref for InlineVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // Safety: the first `len` items are initialized.
+        unsafe { std::slice::from_raw_parts(self.items.as_ptr().cast(), self.len) }
+    }
+}
+
+impl<T: Copy, const N: usize> DerefMut for InlineVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // Safety: the first `len` items are initialized.
+        unsafe { std::slice::from_raw_parts_mut(self.items.as_mut_ptr().cast(), self.len) }
+    }
+}
+
+impl<'a, T: Copy, const N: usize> IntoIterator for &'a InlineVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Copy, const N: usize> Default for InlineVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy + fmt::Debug, const N: usize> fmt::Debug for InlineVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::InlineVec;
+
+    #[test]
+    fn insert_and_remove() {
+        let mut vec: InlineVec<u32, 4> = InlineVec::new();
+        vec.push(1);
+        vec.push(3);
+        vec.insert(1, 2);
+        vec.insert(0, 0);
+        assert_eq!(*vec, [0, 1, 2, 3]);
+
+        assert_eq!(vec.remove(1), 1);
+        assert_eq!(vec.pop(), Some(3));
+        assert_eq!(*vec, [0, 2]);
+        assert_eq!(vec.binary_search(&2), Ok(1));
+    }
+
+    #[test]
+    fn split_off_and_append() {
+        let mut left: InlineVec<u32, 5> = InlineVec::from_slice(&[1, 2, 3, 4, 5]);
+        let mut right = left.split_off(2);
+        assert_eq!((&*left, &*right), (&[1, 2][..], &[3, 4, 5][..]));
+
+        left.append(&mut right);
+        assert_eq!((&*left, right.is_empty()), (&[1, 2, 3, 4, 5][..], true));
+    }
+
+    #[test]
+    #[should_panic]
+    fn push_over_capacity() {
+        let mut vec: InlineVec<u32, 1> = InlineVec::from_slice(&[1]);
+        vec.push(2);
+    }
+}
@@ -1,3 +1,24 @@
+mod arena;
 pub mod binary_search_tree;
 pub mod bplustree;
 pub mod btree;
+pub mod bytes_bplustree;
+pub mod comparator;
+pub mod composite;
+pub mod concurrent_bplustree;
+mod dot;
+pub mod inline_vec;
+pub mod mvcc;
+pub mod olc_bplustree;
+pub mod persistent_btree;
+mod range;
+pub mod recorder;
+pub mod render;
+pub mod secondary_index;
+pub mod serialization;
+pub mod simd;
+pub mod stats;
+pub mod summary;
+
+#[cfg(feature = "serde")]
+mod serde_impls;
@@ -0,0 +1,430 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::bplustree::BPlusTree;
+
+// Multi-version concurrency control on top of a B+ Tree.
+//
+// Every key stores a chain of versions, from the oldest to the newest. A
+// version is visible to the transactions that started in between the commit
+// that created it (`begin`) and the commit that replaced or removed it
+// (`end`).
+//
+// A transaction reads from the snapshot of the last commit before it began,
+// and buffer its writes until it commits. If another transaction committed a
+// write to one of the same keys in the meantime, the commit fails (first
+// committer wins), which gives snapshot isolation.
+//
+// Once a version is no longer visible to any active transaction, it is
+// garbage collected, and keys without any version left are removed from the
+// tree.
+pub struct MvccBPlusTree<K = u32, V = K> {
+    state: Mutex<State<K, V>>,
+}
+
+pub struct Transaction<'a, K: Ord + Clone + 'static, V: Clone> {
+    tree: &'a MvccBPlusTree<K, V>,
+    start: u64,
+    writes: BTreeMap<K, Option<V>>, // None when the key is removed.
+}
+
+// Returned by `commit` when another transaction committed a write to the
+// same key after this transaction began.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Conflict;
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "write-write conflict with a concurrent transaction")
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+struct Version<V> {
+    begin: u64,
+    end: Option<u64>, // None while it is the latest version.
+    value: V,
+}
+
+impl<V> Version<V> {
+    fn is_visible(&self, timestamp: u64) -> bool {
+        self.begin <= timestamp && self.end.is_none_or(|end| timestamp < end)
+    }
+}
+
+struct State<K, V> {
+    tree: BPlusTree<K, Vec<Version<V>>>,
+    clock: u64,                   // Timestamp of the last commit.
+    active: BTreeMap<u64, usize>, // Number of active transactions per start.
+    garbage: BTreeSet<K>,         // Keys with versions that ended.
+}
+
+impl<K: Ord + Clone + 'static, V: Clone> State<K, V> {
+    fn read(&self, key: &K, timestamp: u64) -> Option<V> {
+        let chain = self.tree.get(key)?;
+
+        chain
+            .iter()
+            .rev()
+            .find(|version| version.begin <= timestamp)
+            .filter(|version| version.is_visible(timestamp))
+            .map(|version| version.value.clone())
+    }
+
+    fn has_conflict(&self, key: &K, start: u64) -> bool {
+        self.tree
+            .get(key)
+            .and_then(|chain| chain.last())
+            .is_some_and(|version| {
+                version.begin > start || version.end.is_some_and(|end| end > start)
+            })
+    }
+
+    fn write(&mut self, key: K, value: Option<V>, timestamp: u64) {
+        let chain = match self.tree.get_mut(&key) {
+            Some(chain) => chain,
+            None => {
+                if let Some(value) = value {
+                    let version = Version {
+                        begin: timestamp,
+                        end: None,
+                        value,
+                    };
+                    self.tree.insert(key, vec![version]);
+                }
+                return;
+            }
+        };
+
+        if let Some(latest) = chain.last_mut().filter(|version| version.end.is_none()) {
+            latest.end = Some(timestamp);
+            self.garbage.insert(key.clone());
+        }
+
+        if let Some(value) = value {
+            chain.push(Version {
+                begin: timestamp,
+                end: None,
+                value,
+            });
+        }
+    }
+
+    // Remove the versions that ended before the oldest active transaction
+    // began, returning the number of versions removed.
+    fn collect_garbage(&mut self) -> usize {
+        let oldest = self.active.keys().next().copied().unwrap_or(self.clock);
+        let mut removed = 0;
+
+        for key in std::mem::take(&mut self.garbage) {
+            let chain = match self.tree.get_mut(&key) {
+                Some(chain) => chain,
+                None => continue,
+            };
+
+            let count = chain.len();
+            chain.retain(|version| version.end.is_none_or(|end| end > oldest));
+            removed += count - chain.len();
+
+            if chain.is_empty() {
+                self.tree.remove(&key);
+            } else if chain.iter().any(|version| version.end.is_some()) {
+                self.garbage.insert(key);
+            }
+        }
+
+        removed
+    }
+}
+
+impl<K: Ord + Clone + 'static, V: Clone> MvccBPlusTree<K, V> {
+    pub fn new(max_degree: usize) -> Self {
+        MvccBPlusTree {
+            state: Mutex::new(State {
+                tree: BPlusTree::with_max_degree(max_degree),
+                clock: 0,
+                active: BTreeMap::new(),
+                garbage: BTreeSet::new(),
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State<K, V>> {
+        self.state.lock().unwrap()
+    }
+
+    pub fn begin(&self) -> Transaction<'_, K, V> {
+        let mut state = self.state();
+        let start = state.clock;
+        *state.active.entry(start).or_insert(0) += 1;
+
+        Transaction {
+            tree: self,
+            start,
+            writes: BTreeMap::new(),
+        }
+    }
+
+    // Garbage collection already happen every time a transaction ends.
+    pub fn gc(&self) -> usize {
+        self.state().collect_garbage()
+    }
+}
+
+impl<'a, K: Ord + Clone + 'static, V: Clone> Transaction<'a, K, V> {
+    pub fn get(&self, key: &K) -> Option<V> {
+        match self.writes.get(key) {
+            Some(write) => write.clone(),
+            None => self.tree.state().read(key, self.start),
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.writes.insert(key, Some(value));
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.get(key);
+        self.writes.insert(key.clone(), None);
+        value
+    }
+
+    pub fn commit(mut self) -> Result<(), Conflict> {
+        let mut state = self.tree.state();
+
+        if self
+            .writes
+            .keys()
+            .any(|key| state.has_conflict(key, self.start))
+        {
+            return Err(Conflict);
+        }
+
+        if self.writes.is_empty() {
+            return Ok(());
+        }
+
+        let timestamp = state.clock + 1;
+        state.clock = timestamp;
+
+        for (key, value) in std::mem::take(&mut self.writes) {
+            state.write(key, value, timestamp);
+        }
+
+        Ok(())
+    }
+
+    // Discard the buffered writes, which is also what happen when a
+    // transaction is dropped without committing.
+    pub fn rollback(self) {}
+}
+
+impl<'a, K: Ord + Clone + 'static, V: Clone> Drop for Transaction<'a, K, V> {
+    fn drop(&mut self) {
+        // Don't panic again while unwinding from a poisoned lock.
+        let mut state = match self.tree.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+
+        if let Some(count) = state.active.get_mut(&self.start) {
+            *count -= 1;
+            if *count == 0 {
+                state.active.remove(&self.start);
+            }
+        }
+
+        state.collect_garbage();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Conflict, MvccBPlusTree};
+
+    fn versions(tree: &MvccBPlusTree, key: &u32) -> usize {
+        tree.state().tree.get(key).map_or(0, |chain| chain.len())
+    }
+
+    #[test]
+    fn read_your_own_writes() {
+        let tree = MvccBPlusTree::new(4);
+        let mut tx = tree.begin();
+
+        tx.insert(1, 10);
+        assert_eq!(tx.get(&1), Some(10));
+        assert_eq!(tx.remove(&1), Some(10));
+        assert_eq!(tx.get(&1), None);
+        assert_eq!(tx.commit(), Ok(()));
+    }
+
+    #[test]
+    fn writes_are_only_visible_after_commit() {
+        let tree = MvccBPlusTree::new(4);
+        let mut writer = tree.begin();
+        writer.insert(1, 10);
+
+        let reader = tree.begin();
+        assert_eq!(reader.get(&1), None);
+        writer.commit().unwrap();
+
+        // Still reading from the snapshot taken when it began.
+        assert_eq!(reader.get(&1), None);
+        assert_eq!(tree.begin().get(&1), Some(10));
+    }
+
+    #[test]
+    fn snapshot_isolation() {
+        let tree = MvccBPlusTree::new(4);
+        let mut setup = tree.begin();
+        for i in 1..50 {
+            setup.insert(i, i);
+        }
+        setup.commit().unwrap();
+
+        let reader = tree.begin();
+        let mut writer = tree.begin();
+        for i in 1..50 {
+            writer.insert(i, i * 100);
+        }
+        writer.remove(&25);
+        writer.commit().unwrap();
+
+        for i in 1..50 {
+            assert_eq!(reader.get(&i), Some(i));
+        }
+
+        let reader = tree.begin();
+        assert_eq!(reader.get(&1), Some(100));
+        assert_eq!(reader.get(&25), None);
+    }
+
+    #[test]
+    fn write_write_conflict_abort_the_second_committer() {
+        let tree = MvccBPlusTree::new(4);
+        let mut tx1 = tree.begin();
+        let mut tx2 = tree.begin();
+
+        tx1.insert(1, 10);
+        tx2.insert(1, 20);
+
+        assert_eq!(tx1.commit(), Ok(()));
+        assert_eq!(tx2.commit(), Err(Conflict));
+        assert_eq!(tree.begin().get(&1), Some(10));
+    }
+
+    #[test]
+    fn remove_conflict_with_concurrent_update() {
+        let tree = MvccBPlusTree::new(4);
+        let mut setup = tree.begin();
+        setup.insert(1, 10);
+        setup.commit().unwrap();
+
+        let mut tx1 = tree.begin();
+        let mut tx2 = tree.begin();
+        tx1.remove(&1);
+        tx2.insert(1, 20);
+
+        assert_eq!(tx2.commit(), Ok(()));
+        assert_eq!(tx1.commit(), Err(Conflict));
+        assert_eq!(tree.begin().get(&1), Some(20));
+    }
+
+    #[test]
+    fn disjoint_writes_do_not_conflict() {
+        let tree = MvccBPlusTree::new(4);
+        let mut tx1 = tree.begin();
+        let mut tx2 = tree.begin();
+
+        tx1.insert(1, 10);
+        tx2.insert(2, 20);
+
+        assert_eq!(tx1.commit(), Ok(()));
+        assert_eq!(tx2.commit(), Ok(()));
+
+        let reader = tree.begin();
+        assert_eq!(reader.get(&1), Some(10));
+        assert_eq!(reader.get(&2), Some(20));
+    }
+
+    #[test]
+    fn rollback_discard_writes() {
+        let tree = MvccBPlusTree::new(4);
+        let mut tx = tree.begin();
+        tx.insert(1, 10);
+        tx.rollback();
+
+        assert_eq!(tree.begin().get(&1), None);
+    }
+
+    #[test]
+    fn garbage_collect_versions_not_visible_to_active_transactions() {
+        let tree = MvccBPlusTree::new(4);
+        for value in 0..5 {
+            let mut tx = tree.begin();
+            tx.insert(1, value);
+            tx.commit().unwrap();
+        }
+
+        let reader = tree.begin();
+        let mut tx = tree.begin();
+        tx.insert(1, 5);
+        tx.commit().unwrap();
+
+        // The reader still need the version before the last commit.
+        assert_eq!(tree.gc(), 0);
+        assert_eq!(versions(&tree, &1), 2);
+        assert_eq!(reader.get(&1), Some(4));
+
+        drop(reader);
+        assert_eq!(versions(&tree, &1), 1);
+        assert_eq!(tree.begin().get(&1), Some(5));
+
+        let mut tx = tree.begin();
+        tx.remove(&1);
+        tx.commit().unwrap();
+        assert_eq!(versions(&tree, &1), 0);
+    }
+
+    use rand::{thread_rng, Rng};
+    use std::collections::BTreeMap;
+    #[test]
+    fn random_transactions_against_model() {
+        let mut rng = thread_rng();
+        let tree = MvccBPlusTree::new(4);
+        let mut model: BTreeMap<u32, u32> = BTreeMap::new();
+
+        for _ in 0..500 {
+            let mut tx = tree.begin();
+            let mut writes = BTreeMap::new();
+
+            for _ in 0..rng.gen_range(1..10) {
+                let key = rng.gen_range(0..100);
+                if rng.gen_bool(0.7) {
+                    let value = rng.gen();
+                    tx.insert(key, value);
+                    writes.insert(key, Some(value));
+                } else {
+                    tx.remove(&key);
+                    writes.insert(key, None);
+                }
+            }
+
+            if rng.gen_bool(0.8) {
+                tx.commit().unwrap();
+                for (key, value) in writes {
+                    match value {
+                        Some(value) => model.insert(key, value),
+                        None => model.remove(&key),
+                    };
+                }
+            }
+
+            let reader = tree.begin();
+            for key in 0..100 {
+                assert_eq!(reader.get(&key), model.get(&key).copied());
+            }
+        }
+    }
+}
@@ -0,0 +1,840 @@
+use std::ops::{Bound, RangeBounds};
+use std::ptr;
+use std::sync::atomic::{
+    fence, AtomicBool, AtomicPtr, AtomicU32, AtomicU64, AtomicUsize, Ordering,
+};
+
+use crossbeam_epoch::{self as epoch, Guard};
+
+// A B+ Tree that can be shared between threads using optimistic lock
+// coupling (OLC).
+//
+// Instead of a latch, every node has a version counter. Readers never write
+// to a node: they remember the version of a node, read its content and then
+// check that the version didn't change. If it did, a writer modified the node
+// while we were reading it, and the operation restart from the root.
+//
+// Writers upgrade the version they read into a write lock, which also bumps
+// the version once they are done, so every optimistic reader of the node
+// will restart.
+//
+// Since readers might read a node while it is being modified, every field of
+// a node is an atomic.
+//
+// Nodes are split eagerly on the way down when they are full, so there is
+// always room in the parent for the separator of a split child.
+//
+// A leaf is never left empty, except for the root. Removing the last key of
+// a leaf unlinks it from its parent under the write lock of both, along with
+// the ancestors it was the only child of:
+//
+//   remove 5          [4, 8]                       [4]
+//                   /    |    \        =>        /     \
+//             [1, 2]   [ ]    [8, 9]        [1, 2]     [8, 9]
+//                       |
+//                      [5]
+//
+// The unlinked nodes stay locked, so every reader still on them restarts,
+// and are freed once no thread can read them anymore: every operation pins
+// the current epoch of `crossbeam_epoch`, and the nodes are only freed after
+// every thread pinned when they were unlinked is done.
+//
+// The leaves aren't linked to each other, so unlinking a leaf only changes
+// its parent. A range scan goes down to the next leaf from the root again,
+// starting from the smallest separator above the last leaf.
+pub struct OlcBPlusTree {
+    root: Box<Node>,
+    max_degree: usize,
+}
+
+// Returned when a version check failed and the operation needs to restart.
+struct Restart;
+
+const LOCKED: u64 = 0b10;
+
+struct VersionLock(AtomicU64);
+
+impl VersionLock {
+    fn read_lock(&self) -> Result<u64, Restart> {
+        let version = self.0.load(Ordering::Acquire);
+
+        if version & LOCKED == LOCKED {
+            std::hint::spin_loop();
+            Err(Restart)
+        } else {
+            Ok(version)
+        }
+    }
+
+    fn validate(&self, version: u64) -> Result<(), Restart> {
+        fence(Ordering::Acquire);
+
+        if self.0.load(Ordering::Relaxed) == version {
+            Ok(())
+        } else {
+            Err(Restart)
+        }
+    }
+
+    fn upgrade(&self, version: u64) -> Result<(), Restart> {
+        self.0
+            .compare_exchange(
+                version,
+                version + LOCKED,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .map_err(|_| Restart)?;
+
+        // Make sure no reader can observe our writes without also observing
+        // that the node is locked.
+        fence(Ordering::Release);
+        Ok(())
+    }
+
+    // Clear the locked bit and increment the version at the same time.
+    fn write_unlock(&self) {
+        self.0.fetch_add(LOCKED, Ordering::Release);
+    }
+}
+
+struct Node {
+    lock: VersionLock,
+    is_leaf: AtomicBool,
+    count: AtomicUsize,
+    keys: Box<[AtomicU32]>,            // At most max_degree - 1 keys
+    values: Box<[AtomicU32]>,          // Only in leaf node.
+    childrens: Box<[AtomicPtr<Node>]>, // At most max_degree children
+}
+
+impl Node {
+    pub fn new(is_leaf: bool, max_degree: usize) -> Self {
+        Node {
+            lock: VersionLock(AtomicU64::new(0)),
+            is_leaf: AtomicBool::new(is_leaf),
+            count: AtomicUsize::new(0),
+            keys: (1..max_degree).map(|_| AtomicU32::new(0)).collect(),
+            values: (1..max_degree).map(|_| AtomicU32::new(0)).collect(),
+            childrens: (0..max_degree)
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect(),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.is_leaf.load(Ordering::Relaxed)
+    }
+
+    // Might be out of date while reading optimistically, but never out of
+    // bound.
+    fn len(&self) -> usize {
+        self.count.load(Ordering::Relaxed).min(self.keys.len())
+    }
+
+    fn is_full(&self) -> bool {
+        self.len() == self.keys.len()
+    }
+
+    fn key(&self, index: usize) -> u32 {
+        self.keys[index].load(Ordering::Relaxed)
+    }
+
+    fn value(&self, index: usize) -> u32 {
+        self.values[index].load(Ordering::Relaxed)
+    }
+
+    fn child(&self, index: usize) -> *mut Node {
+        self.childrens[index].load(Ordering::Relaxed)
+    }
+
+    fn set_key(&self, index: usize, key: u32) {
+        self.keys[index].store(key, Ordering::Relaxed);
+    }
+
+    fn set_value(&self, index: usize, value: u32) {
+        self.values[index].store(value, Ordering::Relaxed);
+    }
+
+    fn set_child(&self, index: usize, child: *mut Node) {
+        self.childrens[index].store(child, Ordering::Relaxed);
+    }
+
+    // Index of the first key that is not less than the key.
+    fn lower_bound(&self, key: u32) -> usize {
+        let (mut low, mut high) = (0, self.len());
+
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.key(mid) < key {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        low
+    }
+
+    // Index of the child that might contain the key.
+    //
+    // Keys equal to a separator live in the right subtree.
+    fn child_index(&self, key: u32) -> usize {
+        let index = self.lower_bound(key);
+
+        if index < self.len() && self.key(index) == key {
+            index + 1
+        } else {
+            index
+        }
+    }
+
+    // Following functions require the node to be write locked.
+
+    fn insert_entry(&self, index: usize, key: u32, value: u32) {
+        let len = self.len();
+        for i in (index..len).rev() {
+            self.set_key(i + 1, self.key(i));
+            self.set_value(i + 1, self.value(i));
+        }
+
+        self.set_key(index, key);
+        self.set_value(index, value);
+        self.count.store(len + 1, Ordering::Relaxed);
+    }
+
+    fn remove_entry(&self, index: usize) -> u32 {
+        let len = self.len();
+        let value = self.value(index);
+        for i in index + 1..len {
+            self.set_key(i - 1, self.key(i));
+            self.set_value(i - 1, self.value(i));
+        }
+
+        self.count.store(len - 1, Ordering::Relaxed);
+        value
+    }
+
+    // Insert the separator and the right child of a split child.
+    fn insert_child(&self, index: usize, key: u32, child: *mut Node) {
+        let len = self.len();
+        for i in (index..len).rev() {
+            self.set_key(i + 1, self.key(i));
+            self.set_child(i + 2, self.child(i + 1));
+        }
+
+        self.set_key(index, key);
+        self.set_child(index + 1, child);
+        self.count.store(len + 1, Ordering::Relaxed);
+    }
+
+    // Remove the child at the index along with a separator next to it: the
+    // one on its left, so its left sibling now covers its keys, or the one
+    // on its right for the first child.
+    fn remove_child(&self, index: usize) {
+        let len = self.len();
+        for i in index.max(1)..len {
+            self.set_key(i - 1, self.key(i));
+        }
+        for i in index + 1..=len {
+            self.set_child(i - 1, self.child(i));
+        }
+
+        self.set_child(len, ptr::null_mut());
+        self.count.store(len - 1, Ordering::Relaxed);
+    }
+
+    // Move the upper half of a full node to a new right node, which is only
+    // reachable once the parent points to it.
+    fn split(&self, max_degree: usize) -> (u32, *mut Node) {
+        let len = self.len();
+        let breakpoint = len / 2;
+        let right_node = Node::new(self.is_leaf(), max_degree);
+
+        let separator = if self.is_leaf() {
+            for i in breakpoint..len {
+                right_node.set_key(i - breakpoint, self.key(i));
+                right_node.set_value(i - breakpoint, self.value(i));
+            }
+            right_node.count.store(len - breakpoint, Ordering::Relaxed);
+            self.key(breakpoint)
+        } else {
+            for i in breakpoint + 1..len {
+                right_node.set_key(i - breakpoint - 1, self.key(i));
+            }
+            for i in breakpoint + 1..=len {
+                right_node.set_child(i - breakpoint - 1, self.child(i));
+            }
+            right_node
+                .count
+                .store(len - breakpoint - 1, Ordering::Relaxed);
+            self.key(breakpoint)
+        };
+
+        let right_node = Box::into_raw(Box::new(right_node));
+        self.count.store(breakpoint, Ordering::Relaxed);
+
+        (separator, right_node)
+    }
+}
+
+impl Drop for OlcBPlusTree {
+    fn drop(&mut self) {
+        fn free(node: &Node) {
+            if !node.is_leaf() {
+                for i in 0..=node.len() {
+                    // Safety: every child is allocated by `split` and only
+                    // owned by its parent, the unlinked ones aren't in the
+                    // tree anymore.
+                    let child = unsafe { Box::from_raw(node.child(i)) };
+                    free(&child);
+                }
+            }
+        }
+
+        free(&self.root);
+    }
+}
+
+impl OlcBPlusTree {
+    pub fn new(numbers: Vec<u32>, max_degree: usize) -> Self {
+        assert!(max_degree >= 4, "max_degree must be at least 4");
+
+        let tree = Self {
+            root: Box::new(Node::new(true, max_degree)),
+            max_degree,
+        };
+
+        for i in numbers {
+            tree.insert(i);
+        }
+
+        tree
+    }
+
+    // The nodes read by an operation are only freed once it is done, see
+    // the comment at the top.
+    pub fn insert(&self, key: u32) {
+        let _guard = epoch::pin();
+        while self.try_insert(key).is_err() {}
+    }
+
+    pub fn remove(&self, key: &u32) -> Option<u32> {
+        let guard = epoch::pin();
+        loop {
+            if let Ok(result) = self.try_remove(*key, &guard) {
+                return result;
+            }
+        }
+    }
+
+    pub fn get(&self, key: &u32) -> Option<u32> {
+        let _guard = epoch::pin();
+        loop {
+            if let Ok(result) = self.try_get(*key) {
+                return result;
+            }
+        }
+    }
+
+    pub fn range<R: RangeBounds<u32>>(&self, range: R) -> Vec<(u32, u32)> {
+        let _guard = epoch::pin();
+        let mut result = vec![];
+        let mut start = range.start_bound().cloned();
+
+        // Entries that are validated are kept on restart, and the scan
+        // continue after the last of them.
+        while self
+            .try_range(&mut start, range.end_bound(), &mut result)
+            .is_err()
+        {}
+
+        result
+    }
+
+    // Number of nodes in the tree, only exact while no other thread changes
+    // it.
+    pub fn node_count(&self) -> usize {
+        fn count(node: &Node) -> usize {
+            if node.is_leaf() {
+                return 1;
+            }
+            let childrens = (0..=node.len()).map(|i| node.child(i));
+            // Safety: the epoch is pinned.
+            1 + childrens
+                .filter(|child| !child.is_null())
+                .map(|child| count(unsafe { &*child }))
+                .sum::<usize>()
+        }
+
+        let _guard = epoch::pin();
+        count(&self.root)
+    }
+
+    // Split the root in place, so the root never changes:
+    //
+    //   [1, 2, 3]
+    //
+    // become:
+    //
+    //      [2]
+    //   [1]  [2, 3]
+    fn split_root(&self) {
+        let root = &self.root;
+        let left_node = Node::new(root.is_leaf(), self.max_degree);
+        let len = root.len();
+
+        for i in 0..len {
+            left_node.set_key(i, root.key(i));
+            left_node.set_value(i, root.value(i));
+        }
+        for i in 0..=len {
+            left_node.set_child(i, root.child(i));
+        }
+        left_node.count.store(len, Ordering::Relaxed);
+
+        let (separator, right_node) = left_node.split(self.max_degree);
+        let left_node = Box::into_raw(Box::new(left_node));
+
+        root.is_leaf.store(false, Ordering::Relaxed);
+        root.set_key(0, separator);
+        root.set_child(0, left_node);
+        root.set_child(1, right_node);
+        root.count.store(1, Ordering::Relaxed);
+    }
+
+    // Walk down to the leaf that might contain the key, returning it with
+    // its version and the smallest separator above it, where the next leaf
+    // starts.
+    //
+    // When `split_full` is true, full node on the way are split.
+    fn find_leaf(&self, key: u32, split_full: bool) -> Result<(&Node, u64, Option<u32>), Restart> {
+        let mut node: &Node = &self.root;
+        let mut version = node.lock.read_lock()?;
+        let mut parent: Option<(&Node, u64)> = None;
+        let mut upper = None;
+
+        loop {
+            if split_full && node.is_full() {
+                if let Some((parent, parent_version)) = parent {
+                    parent.lock.upgrade(parent_version)?;
+                }
+
+                if node.lock.upgrade(version).is_err() {
+                    if let Some((parent, _)) = parent {
+                        parent.lock.write_unlock();
+                    }
+                    return Err(Restart);
+                }
+
+                match parent {
+                    Some((parent, _)) => {
+                        let (separator, right_node) = node.split(self.max_degree);
+                        parent.insert_child(parent.lower_bound(separator), separator, right_node);
+                        parent.lock.write_unlock();
+                    }
+                    None => self.split_root(),
+                }
+                node.lock.write_unlock();
+
+                return Err(Restart);
+            }
+
+            if node.is_leaf() {
+                return Ok((node, version, upper));
+            }
+
+            let index = node.child_index(key);
+            if index < node.len() {
+                upper = Some(node.key(index));
+            }
+            let child = node.child(index);
+            if child.is_null() {
+                return Err(Restart);
+            }
+
+            // Safety: the epoch is pinned, so the child isn't freed even if
+            // it is unlinked meanwhile.
+            let child = unsafe { &*child };
+            let child_version = child.lock.read_lock()?;
+
+            // Make sure the child is still the one covering the key, as it
+            // might have been split before we read its version.
+            node.lock.validate(version)?;
+
+            parent = Some((node, version));
+            node = child;
+            version = child_version;
+        }
+    }
+
+    fn try_insert(&self, key: u32) -> Result<(), Restart> {
+        let (leaf, version, _) = self.find_leaf(key, true)?;
+        leaf.lock.upgrade(version)?;
+
+        let index = leaf.lower_bound(key);
+        // Ignore if key is duplicated first
+        if index >= leaf.len() || leaf.key(index) != key {
+            leaf.insert_entry(index, key, key);
+        }
+
+        leaf.lock.write_unlock();
+        Ok(())
+    }
+
+    fn try_remove(&self, key: u32, guard: &Guard) -> Result<Option<u32>, Restart> {
+        // Walk down like `find_leaf`, keeping each ancestor with its version
+        // and the index of the child taken.
+        let mut path: Vec<(&Node, u64, usize)> = vec![];
+        let mut leaf: &Node = &self.root;
+        let mut version = leaf.lock.read_lock()?;
+        while !leaf.is_leaf() {
+            let index = leaf.child_index(key);
+            let child = leaf.child(index);
+            if child.is_null() {
+                return Err(Restart);
+            }
+
+            // Safety: the epoch is pinned.
+            let child = unsafe { &*child };
+            let child_version = child.lock.read_lock()?;
+            leaf.lock.validate(version)?;
+
+            path.push((leaf, version, index));
+            leaf = child;
+            version = child_version;
+        }
+
+        let index = leaf.lower_bound(key);
+        if index >= leaf.len() || leaf.key(index) != key {
+            leaf.lock.validate(version)?;
+            return Ok(None);
+        }
+
+        if leaf.len() > 1 || path.is_empty() {
+            leaf.lock.upgrade(version)?;
+            let value = leaf.remove_entry(index);
+            leaf.lock.write_unlock();
+            return Ok(Some(value));
+        }
+
+        // The leaf would be left empty. It is unlinked from the closest
+        // ancestor with other childrens, along with the ancestors in between
+        // which only have it below them. Without such an ancestor, the root
+        // becomes an empty leaf instead.
+        let anchor = path.iter().rposition(|(node, _, _)| node.len() > 0);
+        let top = anchor.unwrap_or(0);
+
+        // Lock from the top, each node with the version it was read with, so
+        // none changed since then.
+        let mut locked = vec![];
+        for &(node, version, _) in &path[top..] {
+            if node.lock.upgrade(version).is_err() {
+                locked
+                    .iter()
+                    .for_each(|node: &&Node| node.lock.write_unlock());
+                return Err(Restart);
+            }
+            locked.push(node);
+        }
+        if leaf.lock.upgrade(version).is_err() {
+            locked.iter().for_each(|node| node.lock.write_unlock());
+            return Err(Restart);
+        }
+        let value = leaf.value(index);
+
+        let (top, _, index) = path[top];
+        if anchor.is_some() {
+            top.remove_child(index);
+        } else {
+            top.set_child(0, ptr::null_mut());
+            top.count.store(0, Ordering::Relaxed);
+            top.is_leaf.store(true, Ordering::Relaxed);
+        }
+        top.lock.write_unlock();
+
+        // Never unlocked, the version of an unlinked node never validates.
+        for node in locked.into_iter().skip(1).chain([leaf]) {
+            let node = node as *const Node as *mut Node;
+            // Safety: the node was allocated by `split` and can't be reached
+            // from the tree anymore, only by the threads already pinned.
+            unsafe { guard.defer_unchecked(move || drop(Box::from_raw(node))) };
+        }
+        Ok(Some(value))
+    }
+
+    fn try_get(&self, key: u32) -> Result<Option<u32>, Restart> {
+        let (leaf, version, _) = self.find_leaf(key, false)?;
+
+        let index = leaf.lower_bound(key);
+        let result = if index < leaf.len() && leaf.key(index) == key {
+            Some(leaf.value(index))
+        } else {
+            None
+        };
+
+        leaf.lock.validate(version)?;
+        Ok(result)
+    }
+
+    // Scan a leaf at a time, each found from the root at the start of the
+    // range left to scan. The entries of a leaf are kept once validated.
+    fn try_range(
+        &self,
+        start: &mut Bound<u32>,
+        end: Bound<&u32>,
+        result: &mut Vec<(u32, u32)>,
+    ) -> Result<(), Restart> {
+        loop {
+            let mut entries = vec![];
+            let first_key = match *start {
+                Bound::Included(key) | Bound::Excluded(key) => key,
+                Bound::Unbounded => 0,
+            };
+            let (leaf, version, upper) = self.find_leaf(first_key, false)?;
+            let mut is_done = false;
+
+            for index in 0..leaf.len() {
+                let key = leaf.key(index);
+
+                // The separator might have been removed since we read it, so
+                // the leaf also covers the keys after it. Those are read with
+                // the next leaf, to keep the result sorted.
+                if upper.is_some_and(|upper| key >= upper) {
+                    break;
+                }
+
+                let after_start = match *start {
+                    Bound::Included(start) => key >= start,
+                    Bound::Excluded(start) => key > start,
+                    Bound::Unbounded => true,
+                };
+                let before_end = match end {
+                    Bound::Included(&end) => key <= end,
+                    Bound::Excluded(&end) => key < end,
+                    Bound::Unbounded => true,
+                };
+
+                if !before_end {
+                    is_done = true;
+                    break;
+                }
+                if after_start {
+                    entries.push((key, leaf.value(index)));
+                }
+            }
+
+            leaf.lock.validate(version)?;
+
+            // Entries of this leaf are consistent, keep them. The next leaf
+            // starts at the separator above this one, even if this one was
+            // split since.
+            result.append(&mut entries);
+            match upper {
+                Some(upper) if !is_done => *start = Bound::Included(upper),
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OlcBPlusTree;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn get_on_empty_tree() {
+        let tree = OlcBPlusTree::new(vec![], 4);
+        assert_eq!(tree.get(&2), None);
+        assert!(tree.range(..).is_empty());
+    }
+
+    #[test]
+    fn insert_and_split_on_level_5_leaf_node() {
+        let vec: Vec<u32> = (1..82).collect();
+        let tree = OlcBPlusTree::new(vec.clone(), 4);
+
+        tree.insert(82);
+        assert_eq!(tree.get(&82), Some(82));
+
+        for v in vec {
+            assert_eq!(tree.get(&v), Some(v));
+        }
+    }
+
+    #[test]
+    fn range_with_all_bounds() {
+        let tree = OlcBPlusTree::new((1..100).rev().collect(), 5);
+        let keys =
+            |entries: Vec<(u32, u32)>| entries.into_iter().map(|(k, _)| k).collect::<Vec<u32>>();
+
+        assert_eq!(keys(tree.range(..)), (1..100).collect::<Vec<u32>>());
+        assert_eq!(keys(tree.range(10..20)), (10..20).collect::<Vec<u32>>());
+        assert_eq!(keys(tree.range(10..=20)), (10..=20).collect::<Vec<u32>>());
+        assert_eq!(keys(tree.range(90..)), (90..100).collect::<Vec<u32>>());
+        assert_eq!(keys(tree.range(..5)), (1..5).collect::<Vec<u32>>());
+        assert!(tree.range(200..).is_empty());
+    }
+
+    use rand::seq::SliceRandom;
+    use rand::{thread_rng, Rng};
+    #[test]
+    fn delete_all_keys_randomly() {
+        for max_degree in 4..8 {
+            let mut vec: Vec<u32> = (1..200).collect();
+            vec.shuffle(&mut thread_rng());
+            let tree = OlcBPlusTree::new(vec.clone(), max_degree);
+
+            vec.shuffle(&mut thread_rng());
+            for &v in &vec {
+                assert_eq!(tree.remove(&v), Some(v));
+                assert_eq!(tree.get(&v), None);
+            }
+            assert!(tree.range(..).is_empty());
+            assert_eq!(tree.node_count(), 1);
+        }
+    }
+
+    // Keys inserted at one end and removed at the other, e.g. time buckets.
+    // The leaves left behind are freed, so the tree doesn't grow.
+    #[test]
+    fn sliding_window_frees_nodes() {
+        const WINDOW: u32 = 1_000;
+
+        let tree = OlcBPlusTree::new((0..WINDOW).collect(), 4);
+        let nodes = tree.node_count();
+        for i in 0..100 * WINDOW {
+            tree.insert(i + WINDOW);
+            assert_eq!(tree.remove(&i), Some(i));
+        }
+
+        let keys: Vec<u32> = tree.range(..).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, (100 * WINDOW..101 * WINDOW).collect::<Vec<u32>>());
+        assert!(
+            tree.node_count() <= 2 * nodes,
+            "{} nodes",
+            tree.node_count()
+        );
+    }
+
+    // Same from several threads at once, each sliding its own window, while
+    // others scan the whole tree. A scan isn't a snapshot, it can see keys
+    // inserted after it passed the removed ones, so only the order is checked.
+    #[test]
+    fn parallel_sliding_windows() {
+        const THREADS: u32 = 4;
+        const WINDOW: u32 = 200;
+        const STEPS: u32 = 20_000;
+
+        let tree = Arc::new(OlcBPlusTree::new(vec![], 4));
+        let writers: Vec<_> = (0..THREADS)
+            .map(|id| {
+                let tree = Arc::clone(&tree);
+                thread::spawn(move || {
+                    let key = |i: u32| i * THREADS + id;
+                    for i in 0..STEPS {
+                        tree.insert(key(i));
+                        if i >= WINDOW {
+                            assert_eq!(tree.remove(&key(i - WINDOW)), Some(key(i - WINDOW)));
+                        }
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let tree = Arc::clone(&tree);
+                thread::spawn(move || {
+                    for _ in 0..200 {
+                        let entries = tree.range(..);
+                        assert!(entries.windows(2).all(|w| w[0].0 < w[1].0));
+                        assert!(entries.iter().all(|&(key, value)| key == value));
+                    }
+                })
+            })
+            .collect();
+        for handle in writers.into_iter().chain(readers) {
+            handle.join().unwrap();
+        }
+
+        let mut expected: Vec<u32> = (0..THREADS)
+            .flat_map(|id| (STEPS - WINDOW..STEPS).map(move |i| i * THREADS + id))
+            .collect();
+        expected.sort();
+        let keys: Vec<u32> = tree.range(..).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, expected);
+        // Without freeing them, there would be a leaf for every 3 keys ever
+        // inserted.
+        assert!(tree.node_count() < 2 * (THREADS * WINDOW) as usize);
+    }
+
+    #[test]
+    fn is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<OlcBPlusTree>();
+    }
+
+    // Same as the latched variant: each thread owns a partition of the keys,
+    // and check the result of its operations against its own model.
+    #[test]
+    fn parallel_insert_remove_get_and_range() {
+        const THREADS: u32 = 8;
+        const OPERATIONS: usize = 20_000;
+        const KEYS: u32 = 2_000;
+
+        for max_degree in [4, 7] {
+            let tree = Arc::new(OlcBPlusTree::new(vec![], max_degree));
+
+            let handles: Vec<_> = (0..THREADS)
+                .map(|id| {
+                    let tree = Arc::clone(&tree);
+                    thread::spawn(move || {
+                        let mut rng = thread_rng();
+                        let mut model = BTreeMap::new();
+
+                        for _ in 0..OPERATIONS {
+                            let key = rng.gen_range(0..KEYS / THREADS) * THREADS + id;
+                            match rng.gen_range(0..4) {
+                                0 => {
+                                    tree.insert(key);
+                                    model.insert(key, key);
+                                }
+                                1 => assert_eq!(tree.remove(&key), model.remove(&key)),
+                                2 => assert_eq!(tree.get(&key), model.get(&key).copied()),
+                                _ => {
+                                    // Only the keys owned by this thread are
+                                    // deterministic, but the whole range must
+                                    // still be sorted.
+                                    let entries = tree.range(key..key + 100);
+                                    assert!(entries.windows(2).all(|w| w[0].0 < w[1].0));
+
+                                    let owned: Vec<(u32, u32)> = entries
+                                        .into_iter()
+                                        .filter(|(k, _)| k % THREADS == id)
+                                        .collect();
+                                    let expected: Vec<(u32, u32)> = model
+                                        .range(key..key + 100)
+                                        .map(|(&k, &v)| (k, v))
+                                        .collect();
+                                    assert_eq!(owned, expected);
+                                }
+                            }
+                        }
+
+                        model
+                    })
+                })
+                .collect();
+
+            let mut expected: Vec<(u32, u32)> = handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap().into_iter())
+                .collect();
+            expected.sort();
+
+            assert_eq!(tree.range(..), expected);
+        }
+    }
+}
@@ -0,0 +1,513 @@
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+// A B Tree where nodes are shared between versions of the tree.
+//
+// `insert` and `remove` never modify a node that is shared: every node on the
+// path from the root to the modified node is copied first (path copying),
+// through `Arc::make_mut`, while the untouched subtrees are shared with the
+// previous version.
+//
+// Hence, `snapshot` only need to clone the root, and the snapshot will not
+// see any of the later changes.
+#[derive(Clone)]
+pub struct PersistentBTree {
+    root: Option<Arc<Node>>,
+}
+
+// An immutable, point in time view of a `PersistentBTree`.
+#[derive(Clone)]
+pub struct Snapshot {
+    root: Option<Arc<Node>>,
+}
+
+#[derive(Clone)]
+struct Node {
+    keys: Vec<u32>,            // At least t - 1 keys, at most 2t - 1 keys
+    childrens: Vec<Arc<Node>>, // At least t children, at most 2t children
+    is_leaf: bool,
+}
+
+const MINIMUM_DEGREE: usize = 2; // t
+const MAX_DEGREE: usize = 2 * MINIMUM_DEGREE - 1;
+
+impl Node {
+    pub fn new(is_leaf: bool) -> Self {
+        Node {
+            keys: vec![],
+            childrens: vec![],
+            is_leaf,
+        }
+    }
+
+    pub fn search(&self, key: &u32) -> Option<&u32> {
+        match self.keys.binary_search(key) {
+            Ok(index) => self.keys.get(index),
+            Err(_) if self.is_leaf => None,
+            Err(index) => self.childrens[index].search(key),
+        }
+    }
+
+    fn child_mut(&mut self, index: usize) -> &mut Node {
+        Arc::make_mut(&mut self.childrens[index])
+    }
+
+    // Split the full child at index, moving its median key up:
+    //
+    //      [4]               [2, 4]
+    //  [1, 2, 3]  [5]  ->  [1]  [3]  [5]
+    pub fn split_child(&mut self, index: usize) {
+        let child = self.child_mut(index);
+
+        let mut right_node = Node::new(child.is_leaf);
+        right_node.keys = child.keys.split_off(MINIMUM_DEGREE);
+        if !child.is_leaf {
+            right_node.childrens = child.childrens.split_off(MINIMUM_DEGREE);
+        }
+        let key = child.keys.pop().unwrap();
+
+        self.keys.insert(index, key);
+        self.childrens.insert(index + 1, Arc::new(right_node));
+    }
+
+    pub fn insert_non_full(&mut self, key: u32) {
+        match self.keys.binary_search(&key) {
+            // Ignore if key is duplicated first
+            Ok(_) => (),
+            Err(index) if self.is_leaf => self.keys.insert(index, key),
+            Err(mut index) => {
+                if self.childrens[index].keys.len() == MAX_DEGREE {
+                    self.split_child(index);
+
+                    match key.cmp(&self.keys[index]) {
+                        std::cmp::Ordering::Equal => return,
+                        std::cmp::Ordering::Greater => index += 1,
+                        std::cmp::Ordering::Less => (),
+                    }
+                }
+
+                self.child_mut(index).insert_non_full(key);
+            }
+        }
+    }
+
+    // Merge the child at index + 1 and the key at index into the child at
+    // index:
+    //
+    //      [2, 4]            [4]
+    //  [1]  [3]  [5]  ->  [1, 2, 3]  [5]
+    pub fn merge_childs(&mut self, index: usize) {
+        let key = self.keys.remove(index);
+        let right = self.childrens.remove(index + 1);
+        let right = Arc::try_unwrap(right).unwrap_or_else(|right| (*right).clone());
+
+        let left = self.child_mut(index);
+        left.keys.push(key);
+        left.keys.extend(right.keys);
+        left.childrens.extend(right.childrens);
+    }
+
+    fn borrow_from_prev(&mut self, index: usize) {
+        let sibling = self.child_mut(index - 1);
+        let key = sibling.keys.pop().unwrap();
+        let child = sibling.childrens.pop();
+
+        let key = std::mem::replace(&mut self.keys[index - 1], key);
+        let node = self.child_mut(index);
+        node.keys.insert(0, key);
+        if let Some(child) = child {
+            node.childrens.insert(0, child);
+        }
+    }
+
+    fn borrow_from_next(&mut self, index: usize) {
+        let sibling = self.child_mut(index + 1);
+        let key = sibling.keys.remove(0);
+        let child = (!sibling.is_leaf).then(|| sibling.childrens.remove(0));
+
+        let key = std::mem::replace(&mut self.keys[index], key);
+        let node = self.child_mut(index);
+        node.keys.push(key);
+        if let Some(child) = child {
+            node.childrens.push(child);
+        }
+    }
+
+    // Make sure the child at index has at least t keys before descending
+    // into it, returning the index of the child that now cover the key.
+    fn fill(&mut self, index: usize) -> usize {
+        if index > 0 && self.childrens[index - 1].keys.len() >= MINIMUM_DEGREE {
+            self.borrow_from_prev(index);
+            index
+        } else if index < self.keys.len() && self.childrens[index + 1].keys.len() >= MINIMUM_DEGREE
+        {
+            self.borrow_from_next(index);
+            index
+        } else if index < self.keys.len() {
+            self.merge_childs(index);
+            index
+        } else {
+            self.merge_childs(index - 1);
+            index - 1
+        }
+    }
+
+    // Require the key to be in the subtree.
+    pub fn remove(&mut self, key: &u32) -> u32 {
+        match self.keys.binary_search(key) {
+            Ok(index) if self.is_leaf => self.keys.remove(index),
+            Ok(index) => {
+                if self.childrens[index].keys.len() >= MINIMUM_DEGREE {
+                    // Replace the key with its predecessor.
+                    let predecessor = self.childrens[index].max();
+                    self.keys[index] = predecessor;
+                    self.child_mut(index).remove(&predecessor);
+                    *key
+                } else if self.childrens[index + 1].keys.len() >= MINIMUM_DEGREE {
+                    // Replace the key with its successor.
+                    let successor = self.childrens[index + 1].min();
+                    self.keys[index] = successor;
+                    self.child_mut(index + 1).remove(&successor);
+                    *key
+                } else {
+                    self.merge_childs(index);
+                    self.child_mut(index).remove(key)
+                }
+            }
+            Err(mut index) => {
+                if self.childrens[index].keys.len() < MINIMUM_DEGREE {
+                    index = self.fill(index);
+                }
+
+                self.child_mut(index).remove(key)
+            }
+        }
+    }
+
+    fn min(&self) -> u32 {
+        let mut node = self;
+        while !node.is_leaf {
+            node = &node.childrens[0];
+        }
+        node.keys[0]
+    }
+
+    fn max(&self) -> u32 {
+        let mut node = self;
+        while !node.is_leaf {
+            node = node.childrens.last().unwrap();
+        }
+        *node.keys.last().unwrap()
+    }
+}
+
+// In order iterator over the keys within a range.
+//
+// Only the nodes on the path to the start of the range and the nodes within
+// the range are visited.
+pub struct Iter<'a> {
+    // Node with the index of the next key to yield, after the child at the
+    // same index.
+    stack: Vec<(&'a Node, usize)>,
+    end: Bound<u32>,
+}
+
+impl<'a> Iter<'a> {
+    fn new<R: RangeBounds<u32>>(root: Option<&'a Arc<Node>>, range: R) -> Self {
+        let mut iter = Iter {
+            stack: vec![],
+            end: range.end_bound().cloned(),
+        };
+
+        let mut node = root.map(|node| node.as_ref());
+        while let Some(n) = node {
+            let index = match range.start_bound() {
+                Bound::Included(key) => n.keys.partition_point(|k| k < key),
+                Bound::Excluded(key) => n.keys.partition_point(|k| k <= key),
+                Bound::Unbounded => 0,
+            };
+
+            iter.stack.push((n, index));
+            node = n.childrens.get(index).map(|node| node.as_ref());
+        }
+
+        iter
+    }
+
+    fn push_leftmost(&mut self, mut node: &'a Node) {
+        loop {
+            self.stack.push((node, 0));
+            match node.childrens.first() {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, index) = self.stack.last_mut()?;
+            let node: &'a Node = node;
+
+            if *index < node.keys.len() {
+                let key = &node.keys[*index];
+                *index += 1;
+
+                let is_within_range = match self.end {
+                    Bound::Included(end) => *key <= end,
+                    Bound::Excluded(end) => *key < end,
+                    Bound::Unbounded => true,
+                };
+                if !is_within_range {
+                    self.stack.clear();
+                    return None;
+                }
+
+                if let Some(child) = node.childrens.get(*index) {
+                    self.push_leftmost(child);
+                }
+
+                return Some(key);
+            }
+
+            self.stack.pop();
+        }
+    }
+}
+
+impl Default for PersistentBTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PersistentBTree {
+    pub fn new() -> PersistentBTree {
+        PersistentBTree { root: None }
+    }
+
+    pub fn insert(&mut self, key: u32) {
+        if let Some(node) = self.root.as_mut() {
+            if node.keys.len() == MAX_DEGREE {
+                let mut new_root = Node::new(false);
+                new_root.childrens.push(self.root.take().unwrap());
+                new_root.split_child(0);
+                new_root.insert_non_full(key);
+                self.root = Some(Arc::new(new_root));
+            } else {
+                Arc::make_mut(node).insert_non_full(key);
+            }
+        } else {
+            let mut node = Node::new(true);
+            node.insert_non_full(key);
+            self.root = Some(Arc::new(node));
+        }
+    }
+
+    pub fn remove(&mut self, key: &u32) -> Option<u32> {
+        // Check first, so we don't copy the path of a key that doesn't exist.
+        self.get(key)?;
+
+        let node = Arc::make_mut(self.root.as_mut().unwrap());
+        let result = node.remove(key);
+
+        if node.keys.is_empty() {
+            self.root = node.childrens.pop();
+        }
+
+        Some(result)
+    }
+
+    pub fn get(&self, key: &u32) -> Option<&u32> {
+        self.root.as_ref().and_then(|node| node.search(key))
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::new(self.root.as_ref(), ..)
+    }
+
+    pub fn range<R: RangeBounds<u32>>(&self, range: R) -> Iter<'_> {
+        Iter::new(self.root.as_ref(), range)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn get(&self, key: &u32) -> Option<&u32> {
+        self.root.as_ref().and_then(|node| node.search(key))
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::new(self.root.as_ref(), ..)
+    }
+
+    pub fn range<R: RangeBounds<u32>>(&self, range: R) -> Iter<'_> {
+        Iter::new(self.root.as_ref(), range)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Node, PersistentBTree, MAX_DEGREE, MINIMUM_DEGREE};
+    use std::collections::BTreeSet;
+    use std::sync::Arc;
+
+    fn check(node: &Node, is_root: bool) -> usize {
+        assert!(node.keys.len() <= MAX_DEGREE);
+        if !is_root {
+            assert!(node.keys.len() >= MINIMUM_DEGREE - 1);
+        }
+
+        if node.is_leaf {
+            assert!(node.childrens.is_empty());
+            1
+        } else {
+            assert_eq!(node.childrens.len(), node.keys.len() + 1);
+            let heights: Vec<usize> = node.childrens.iter().map(|c| check(c, false)).collect();
+            assert!(heights.iter().all(|&h| h == heights[0]));
+            heights[0] + 1
+        }
+    }
+
+    #[test]
+    fn basics() {
+        let mut tree = PersistentBTree::new();
+        for i in [2, 7, 8, 9, 4, 6, 1, 5, 3, 10, 11, 14, 16, 17, 18] {
+            tree.insert(i);
+        }
+
+        assert_eq!(tree.get(&2), Some(&2));
+        assert_eq!(tree.get(&12), None);
+        assert_eq!(tree.remove(&7), Some(7));
+        assert_eq!(tree.remove(&7), None);
+        assert_eq!(tree.get(&7), None);
+
+        let keys: Vec<u32> = tree.iter().copied().collect();
+        assert_eq!(keys, vec![1, 2, 3, 4, 5, 6, 8, 9, 10, 11, 14, 16, 17, 18]);
+    }
+
+    #[test]
+    fn snapshot_is_not_affected_by_later_changes() {
+        let mut tree = PersistentBTree::new();
+        for i in 1..100 {
+            tree.insert(i);
+        }
+
+        let snapshot = tree.snapshot();
+        for i in (1..100).step_by(2) {
+            assert_eq!(tree.remove(&i), Some(i));
+        }
+        for i in 100..150 {
+            tree.insert(i);
+        }
+
+        assert_eq!(
+            snapshot.iter().copied().collect::<Vec<u32>>(),
+            (1..100).collect::<Vec<u32>>()
+        );
+        assert_eq!(snapshot.get(&1), Some(&1));
+        assert_eq!(snapshot.get(&120), None);
+        assert_eq!(tree.get(&1), None);
+        assert_eq!(tree.get(&120), Some(&120));
+    }
+
+    #[test]
+    fn snapshot_share_untouched_nodes() {
+        fn nodes(node: &Arc<Node>, result: &mut Vec<*const Node>) {
+            result.push(Arc::as_ptr(node));
+            for child in &node.childrens {
+                nodes(child, result);
+            }
+        }
+
+        let mut tree = PersistentBTree::new();
+        for i in 1..100 {
+            tree.insert(i);
+        }
+
+        let snapshot = tree.snapshot();
+        tree.insert(100);
+
+        let (mut before, mut after) = (vec![], vec![]);
+        nodes(snapshot.root.as_ref().unwrap(), &mut before);
+        nodes(tree.root.as_ref().unwrap(), &mut after);
+
+        // Only the nodes on the path to 100, and the nodes created by
+        // splitting them, are new.
+        let height = check(tree.root.as_ref().unwrap(), true);
+        let copied = after.iter().filter(|node| !before.contains(node)).count();
+        assert!(copied <= 2 * height + 1);
+        assert!(after.len() > 2 * height + 1);
+    }
+
+    #[test]
+    fn range_with_all_bounds() {
+        let mut tree = PersistentBTree::new();
+        for i in (1..100).rev() {
+            tree.insert(i);
+        }
+        let snapshot = tree.snapshot();
+        let collect = |iter: super::Iter| iter.copied().collect::<Vec<u32>>();
+
+        assert_eq!(
+            collect(snapshot.range(10..20)),
+            (10..20).collect::<Vec<u32>>()
+        );
+        assert_eq!(
+            collect(snapshot.range(10..=20)),
+            (10..=20).collect::<Vec<u32>>()
+        );
+        assert_eq!(
+            collect(snapshot.range(90..)),
+            (90..100).collect::<Vec<u32>>()
+        );
+        assert_eq!(collect(snapshot.range(..5)), (1..5).collect::<Vec<u32>>());
+        assert_eq!(
+            collect(snapshot.range((std::ops::Bound::Excluded(10), std::ops::Bound::Excluded(15)))),
+            (11..15).collect::<Vec<u32>>()
+        );
+        assert!(collect(snapshot.range(200..)).is_empty());
+    }
+
+    use rand::{thread_rng, Rng};
+    #[test]
+    fn random_operations_against_snapshots() {
+        let mut rng = thread_rng();
+        let mut tree = PersistentBTree::new();
+        let mut model = BTreeSet::new();
+        let mut snapshots = vec![];
+
+        for i in 0..5000 {
+            let key = rng.gen_range(0..500);
+            if rng.gen_bool(0.5) {
+                tree.insert(key);
+                model.insert(key);
+            } else {
+                assert_eq!(tree.remove(&key), model.take(&key));
+            }
+
+            if i % 500 == 0 {
+                snapshots.push((tree.snapshot(), model.clone()));
+            }
+        }
+
+        if let Some(root) = &tree.root {
+            check(root, true);
+        }
+        assert!(tree.iter().eq(model.iter()));
+
+        for (snapshot, model) in &snapshots {
+            assert!(snapshot.iter().eq(model.iter()));
+            assert!(snapshot.range(100..200).eq(model.range(100..200)));
+        }
+    }
+}
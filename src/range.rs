@@ -0,0 +1,103 @@
+use std::ops::{Bound, RangeBounds};
+
+use crate::comparator::Comparator;
+
+// Shared by the `range` of every tree. A range iterator walks the tree from
+// both ends: from the first key in the range going forward, and from the
+// last key in the range going backward, each found by going down the tree
+// and skipping the subtrees outside the bounds. The keys are yielded from
+// either end until one end reaches the other:
+//
+//   keys     1  3  4  6  8  9
+//   range       [3 ....... 8]
+//   front ->    3  4
+//                        8  <- back
+//
+// The front then stops at 6 or the back at 4, whichever moves next, so every
+// key is yielded once. The ends compare keys by address, not with the
+// comparator, since both walk over the same keys in the nodes.
+pub(crate) struct Ends<'a, K> {
+    start: Bound<K>,
+    end: Bound<K>,
+    front: Option<&'a K>, // Last key yielded from the front.
+    back: Option<&'a K>,  // Last key yielded from the back.
+    done: bool,
+}
+
+impl<'a, K: Clone> Ends<'a, K> {
+    pub(crate) fn new<R: RangeBounds<K>>(range: &R) -> Self {
+        Ends {
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+            front: None,
+            back: None,
+            done: false,
+        }
+    }
+}
+
+impl<'a, K> Ends<'a, K> {
+    pub(crate) fn before_start<C: Comparator<K>>(&self, comparator: &C, key: &K) -> bool {
+        match &self.start {
+            Bound::Included(start) => comparator.compare(key, start).is_lt(),
+            Bound::Excluded(start) => comparator.compare(key, start).is_le(),
+            Bound::Unbounded => false,
+        }
+    }
+
+    pub(crate) fn after_end<C: Comparator<K>>(&self, comparator: &C, key: &K) -> bool {
+        match &self.end {
+            Bound::Included(end) => comparator.compare(key, end).is_gt(),
+            Bound::Excluded(end) => comparator.compare(key, end).is_ge(),
+            Bound::Unbounded => false,
+        }
+    }
+
+    pub(crate) fn unbounded_start(&self) -> bool {
+        matches!(self.start, Bound::Unbounded)
+    }
+
+    pub(crate) fn unbounded_end(&self) -> bool {
+        matches!(self.end, Bound::Unbounded)
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.done
+    }
+
+    // The next key from the front, unless the front went past the end of the
+    // range or reached the back, which ends the iteration.
+    pub(crate) fn front<C: Comparator<K>>(&mut self, comparator: &C, key: Option<&'a K>) -> bool {
+        let reached = |key: &K| {
+            self.back.is_some_and(|back| std::ptr::eq(back, key)) || self.after_end(comparator, key)
+        };
+        match key {
+            Some(key) if !self.done && !reached(key) => {
+                self.front = Some(key);
+                true
+            }
+            _ => {
+                self.done = true;
+                false
+            }
+        }
+    }
+
+    // Same as `front` from the back.
+    pub(crate) fn back<C: Comparator<K>>(&mut self, comparator: &C, key: Option<&'a K>) -> bool {
+        let reached = |key: &K| {
+            self.front.is_some_and(|front| std::ptr::eq(front, key))
+                || self.before_start(comparator, key)
+        };
+        match key {
+            Some(key) if !self.done && !reached(key) => {
+                self.back = Some(key);
+                true
+            }
+            _ => {
+                self.done = true;
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::fmt::{self, Write};
+
+use crate::dot;
+
+// Record every internal step of an operation on a B Tree, e.g. a split or a
+// merge, as a frame holding the whole tree after the step:
+//
+//   let mut recorder = Recorder::new();
+//   tree.remove_recorded(&7, &mut recorder);
+//   println!("{}", recorder.to_json());
+//
+// The tree only report the subtree that changed, along with its path from the
+// root (the index of the child taken at each level). The recorder then splice
+// it in a copy of the previous frame, hence every change to the tree must be
+// reported for the frames to be right.
+#[derive(Default)]
+pub struct Recorder {
+    frames: Vec<Frame>,
+}
+
+pub struct Frame {
+    pub operation: String,  // e.g. "remove 7"
+    pub step: &'static str, // e.g. "merge_childs", "start" for the first frame.
+    pub path: Vec<usize>,   // Path from the root to the changed subtree.
+    pub tree: Shape,
+}
+
+// Keys of a node and its childrens, detached from the tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Shape {
+    pub keys: Vec<u32>,
+    pub childrens: Vec<Shape>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn steps(&self) -> Vec<&'static str> {
+        self.frames.iter().map(|frame| frame.step).collect()
+    }
+
+    fn push(&mut self, operation: &str, step: &'static str, path: &[usize], subtree: Shape) {
+        let tree = match self.frames.last() {
+            Some(frame) if !path.is_empty() => {
+                let mut tree = frame.tree.clone();
+                let mut node = &mut tree;
+                for &index in path {
+                    node = &mut node.childrens[index];
+                }
+                *node = subtree;
+                tree
+            }
+            _ => subtree,
+        };
+
+        self.frames.push(Frame {
+            operation: operation.to_string(),
+            step,
+            path: path.to_vec(),
+            tree,
+        });
+    }
+
+    // {"frames": [{"operation": "...", "step": "...", "path": [...],
+    //              "tree": {"keys": [...], "childrens": [...]}}, ...]}
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        self.write_json(&mut json)
+            .expect("writing to a String can't fail");
+        json
+    }
+
+    fn write_json<W: Write>(&self, json: &mut W) -> fmt::Result {
+        write!(json, "{{\"frames\":[")?;
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i > 0 {
+                write!(json, ",")?;
+            }
+            write!(
+                json,
+                "{{\"operation\":\"{}\",\"step\":\"{}\",\"path\":{:?},\"tree\":",
+                frame.operation, frame.step, frame.path
+            )?;
+            frame.tree.write_json(json)?;
+            write!(json, "}}")?;
+        }
+        write!(json, "]}}")
+    }
+
+    // One Graphviz graph per frame, labelled with the step, and with the
+    // subtree that changed highlighted.
+    pub fn to_dot_frames(&self) -> Vec<String> {
+        self.frames
+            .iter()
+            .map(|frame| {
+                dot::render("Frame", |dot| {
+                    let label = dot::escape(&format!("{}: {}", frame.operation, frame.step));
+                    writeln!(dot, "  label=\"{label}\";")?;
+                    let highlight = (frame.step != "start").then_some(frame.path.as_slice());
+                    frame.tree.write_dot(dot, &mut 0, highlight)?;
+                    Ok(())
+                })
+            })
+            .collect()
+    }
+}
+
+impl Shape {
+    fn write_json<W: Write>(&self, json: &mut W) -> fmt::Result {
+        write!(json, "{{\"keys\":{:?},\"childrens\":[", self.keys)?;
+        for (i, child) in self.childrens.iter().enumerate() {
+            if i > 0 {
+                write!(json, ",")?;
+            }
+            child.write_json(json)?;
+        }
+        write!(json, "]}}")
+    }
+
+    // `highlight` is the path left to the highlighted node, if it is in this
+    // subtree.
+    fn write_dot<W: Write>(
+        &self,
+        dot: &mut W,
+        next_id: &mut usize,
+        highlight: Option<&[usize]>,
+    ) -> Result<usize, fmt::Error> {
+        let id = *next_id;
+        *next_id += 1;
+
+        let label = dot::keys_label(&self.keys, !self.childrens.is_empty());
+        let style = match highlight {
+            Some([]) => ", style=filled, fillcolor=lightblue",
+            _ => "",
+        };
+        writeln!(dot, "  node{id} [label=\"{label}\"{style}];")?;
+
+        for (i, child) in self.childrens.iter().enumerate() {
+            let highlight = match highlight {
+                Some([index, rest @ ..]) if *index == i => Some(rest),
+                _ => None,
+            };
+            let child_id = child.write_dot(dot, next_id, highlight)?;
+            writeln!(dot, "  node{id}:c{i} -> node{child_id};")?;
+        }
+        Ok(id)
+    }
+}
+
+// Handed down by the tree while it walks down during an operation, to know
+// the path of the node a step happen on.
+pub(crate) struct Trace<'a> {
+    recorder: Option<&'a mut Recorder>,
+    operation: String,
+    path: Vec<usize>,
+}
+
+impl<'a> Trace<'a> {
+    pub(crate) fn off() -> Self {
+        Trace {
+            recorder: None,
+            operation: String::new(),
+            path: vec![],
+        }
+    }
+
+    // Start recording an operation, from the tree as it is before.
+    pub(crate) fn on(recorder: &'a mut Recorder, operation: String, tree: Shape) -> Self {
+        recorder.push(&operation, "start", &[], tree);
+        Trace {
+            recorder: Some(recorder),
+            operation,
+            path: vec![],
+        }
+    }
+
+    pub(crate) fn enter(&mut self, index: usize) {
+        self.path.push(index);
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.path.pop();
+    }
+
+    // Record the subtree at the current path after a step. Take a closure so
+    // the subtree is only copied when recording.
+    pub(crate) fn record<F: FnOnce() -> Shape>(&mut self, step: &'static str, subtree: F) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.push(&self.operation, step, &self.path, subtree());
+        }
+    }
+
+    // Record the whole tree, for the steps that replace the root.
+    pub(crate) fn record_root(&mut self, step: &'static str, tree: Option<Shape>) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            let tree = tree.unwrap_or(Shape {
+                keys: vec![],
+                childrens: vec![],
+            });
+            recorder.push(&self.operation, step, &[], tree);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Recorder, Shape, Trace};
+
+    fn leaf(keys: &[u32]) -> Shape {
+        Shape {
+            keys: keys.to_vec(),
+            childrens: vec![],
+        }
+    }
+
+    #[test]
+    fn splice_subtree_in_previous_frame() {
+        let mut recorder = Recorder::new();
+        let tree = Shape {
+            keys: vec![3],
+            childrens: vec![leaf(&[1, 2]), leaf(&[4])],
+        };
+
+        let mut trace = Trace::on(&mut recorder, "insert 5".to_string(), tree);
+        trace.enter(1);
+        trace.record("insert_into_leaf", || leaf(&[4, 5]));
+        trace.leave();
+
+        let frames = recorder.frames();
+        assert_eq!(frames[1].path, vec![1]);
+        assert_eq!(frames[1].tree.childrens[0], leaf(&[1, 2]));
+        assert_eq!(frames[1].tree.childrens[1], leaf(&[4, 5]));
+    }
+
+    #[test]
+    fn trace_off_does_not_copy() {
+        let mut trace = Trace::off();
+        trace.record("split_child", || panic!("nothing to record"));
+    }
+
+    #[test]
+    fn export() {
+        let mut recorder = Recorder::new();
+        let tree = Shape {
+            keys: vec![2],
+            childrens: vec![leaf(&[1]), leaf(&[3])],
+        };
+        let mut trace = Trace::on(&mut recorder, "remove 3".to_string(), tree);
+        trace.enter(1);
+        trace.record("remove_from_leaf", || leaf(&[]));
+
+        assert_eq!(
+            recorder.to_json(),
+            concat!(
+                r#"{"frames":[{"operation":"remove 3","step":"start","path":[],"#,
+                r#""tree":{"keys":[2],"childrens":[{"keys":[1],"childrens":[]},"#,
+                r#"{"keys":[3],"childrens":[]}]}},"#,
+                r#"{"operation":"remove 3","step":"remove_from_leaf","path":[1],"#,
+                r#""tree":{"keys":[2],"childrens":[{"keys":[1],"childrens":[]},"#,
+                r#"{"keys":[],"childrens":[]}]}}]}"#
+            )
+        );
+
+        let frames = recorder.to_dot_frames();
+        assert_eq!(frames.len(), 2);
+        assert!(frames[1].contains("  label=\"remove 3: remove_from_leaf\";\n"));
+        assert!(frames[1].contains("  node2 [label=\"\", style=filled, fillcolor=lightblue];\n"));
+        assert!(!frames[0].contains("fillcolor"));
+    }
+}
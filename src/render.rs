@@ -0,0 +1,182 @@
+use std::fmt;
+
+// Text rendering shared by every tree. Each subtree is laid out as a block of
+// lines, then the childrens blocks are put side by side under their parent,
+// which is centered above them:
+//
+//            [4, 7]
+//      ┌────────┼────────┐
+//   [1, 2]   [5, 6]   [8, 9]
+//
+// Widths are counted in chars, so keys formatted with non ASCII characters
+// are still aligned as long as they are single width.
+
+const GAP: usize = 3;
+
+pub(crate) struct Block {
+    lines: Vec<Vec<char>>,
+    width: usize,
+    center: usize, // Column of the node label center.
+}
+
+impl Block {
+    pub(crate) fn leaf(label: String) -> Self {
+        Block::node(label, vec![])
+    }
+
+    pub(crate) fn node(label: String, childrens: Vec<Block>) -> Self {
+        let label: Vec<char> = label.chars().collect();
+
+        if childrens.is_empty() {
+            return Block {
+                width: label.len(),
+                center: label.len() / 2,
+                lines: vec![label],
+            };
+        }
+
+        // Place the childrens side by side.
+        let mut offsets = vec![];
+        let mut width = 0;
+        for child in &childrens {
+            offsets.push(width);
+            width += child.width + GAP;
+        }
+        width -= GAP;
+
+        let centers: Vec<usize> = childrens
+            .iter()
+            .zip(&offsets)
+            .map(|(child, offset)| offset + child.center)
+            .collect();
+        let first = centers[0];
+        let last = centers[centers.len() - 1];
+        let mut center = if centers.len() % 2 == 1 {
+            centers[centers.len() / 2]
+        } else {
+            (first + last) / 2
+        };
+
+        // Shift everything right when the label overflow on the left.
+        let shift = (label.len() / 2).saturating_sub(center);
+        center += shift;
+        let start = center - label.len() / 2;
+        width = (width + shift).max(start + label.len());
+
+        let mut lines = vec![vec![' '; width], vec![' '; width]];
+        lines[0][start..start + label.len()].copy_from_slice(&label);
+
+        let connector = &mut lines[1];
+        if centers.len() == 1 {
+            connector[center] = '│';
+        } else {
+            for c in &mut connector[first + shift..=last + shift] {
+                *c = '─';
+            }
+            connector[first + shift] = '┌';
+            connector[last + shift] = '┐';
+            for &middle in &centers[1..centers.len() - 1] {
+                connector[middle + shift] = '┬';
+            }
+            connector[center] = match connector[center] {
+                '┬' => '┼',
+                _ => '┴',
+            };
+        }
+
+        let height = childrens
+            .iter()
+            .map(|child| child.lines.len())
+            .max()
+            .unwrap();
+        for row in 0..height {
+            let mut line = vec![' '; width];
+            for (child, offset) in childrens.iter().zip(&offsets) {
+                if let Some(child_line) = child.lines.get(row) {
+                    let start = offset + shift;
+                    line[start..start + child_line.len()].copy_from_slice(child_line);
+                }
+            }
+            lines.push(line);
+        }
+
+        Block {
+            lines,
+            width,
+            center,
+        }
+    }
+}
+
+// A tree laid out as text. It implements `Display`, so it can be written in
+// any `fmt::Write` or `io::Write` with `write!`, or used in `format!`.
+pub struct Rendered {
+    lines: Vec<String>,
+}
+
+impl From<Option<Block>> for Rendered {
+    fn from(block: Option<Block>) -> Self {
+        let lines = block.map_or(vec![], |block| {
+            block
+                .lines
+                .into_iter()
+                .map(|line| line.into_iter().collect::<String>().trim_end().to_string())
+                .collect()
+        });
+        Rendered { lines }
+    }
+}
+
+impl fmt::Display for Rendered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Block, Rendered};
+
+    fn render(block: Block) -> String {
+        Rendered::from(Some(block)).to_string()
+    }
+
+    #[test]
+    fn center_parent_above_childrens() {
+        let leaves = ["[1, 2]", "[5, 6]", "[8, 9]"].map(|label| Block::leaf(label.to_string()));
+        let block = Block::node("[4, 7]".to_string(), leaves.into());
+
+        assert_eq!(
+            render(block),
+            "         [4, 7]
+   ┌────────┼────────┐
+[1, 2]   [5, 6]   [8, 9]
+"
+        );
+    }
+
+    #[test]
+    fn shift_childrens_under_a_wide_label() {
+        let block = Block::node(
+            "a long label".to_string(),
+            vec![Block::leaf("x".to_string())],
+        );
+        assert_eq!(render(block), "a long label\n      │\n      x\n");
+    }
+
+    #[test]
+    fn childrens_of_different_heights() {
+        let left = Block::node("2".to_string(), vec![Block::leaf("1".to_string())]);
+        let block = Block::node("3".to_string(), vec![left, Block::leaf("4".to_string())]);
+
+        assert_eq!(render(block), "  3\n┌─┴─┐\n2   4\n│\n1\n");
+    }
+
+    #[test]
+    fn empty_tree() {
+        assert_eq!(Rendered::from(None).to_string(), "");
+    }
+}
@@ -0,0 +1,233 @@
+use std::ops::{Bound, RangeBounds};
+
+use crate::bplustree::BPlusTree;
+
+// Records by primary key, along with a secondary index of an attribute of
+// the records, e.g. users by id indexed by their country. The attribute is
+// computed from the record by `attribute`, and isn't unique, so the index is
+// a tree of `(attribute, primary key)`:
+//
+//   primary  1 => {fr}  2 => {us}  3 => {fr}
+//   index    (fr, 1) (fr, 3) (us, 2)
+//
+// and the primary keys of an attribute are the keys with that prefix, see
+// `src/composite.rs`. Every change of the records goes through the methods
+// below, which keep the index up to date.
+pub struct IndexedTree<K, V, A, F> {
+    primary: BPlusTree<K, V>,
+    index: BPlusTree<(A, K), ()>,
+    attribute: F,
+}
+
+impl<K, V, A, F> IndexedTree<K, V, A, F>
+where
+    K: Ord + Clone + 'static,
+    A: Ord + Clone + 'static,
+    F: Fn(&V) -> A,
+{
+    pub fn new(max_degree: usize, attribute: F) -> Self {
+        Self {
+            primary: BPlusTree::with_max_degree(max_degree),
+            index: BPlusTree::with_max_degree(max_degree),
+            attribute,
+        }
+    }
+
+    // Insert the record, or replace it when there is already a record with
+    // this key, returning the previous one.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let previous = self.remove(&key);
+        self.index
+            .insert(((self.attribute)(&value), key.clone()), ());
+        self.primary.insert(key, value);
+        previous
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.primary.remove(key)?;
+        self.index.remove(&((self.attribute)(&value), key.clone()));
+        Some(value)
+    }
+
+    // Change the record in place, moving it in the index when its attribute
+    // changes. False when there is no record with this key.
+    pub fn update<U: FnOnce(&mut V)>(&mut self, key: &K, update: U) -> bool {
+        let Some(value) = self.primary.get_mut(key) else {
+            return false;
+        };
+        let before = (self.attribute)(value);
+        update(value);
+        let after = (self.attribute)(value);
+
+        if before != after {
+            self.index.remove(&(before, key.clone()));
+            self.index.insert((after, key.clone()), ());
+        }
+        true
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.primary.get(key)
+    }
+
+    // Primary keys of the records with this attribute, in order.
+    pub fn lookup<'a>(&'a self, attribute: &'a A) -> impl Iterator<Item = &'a K> {
+        self.index.range_prefix(attribute).map(|((_, key), _)| key)
+    }
+
+    // Primary keys of the records with an attribute in the range, ordered by
+    // attribute then by primary key.
+    pub fn lookup_range<R: RangeBounds<A>>(&self, range: R) -> impl Iterator<Item = &K> {
+        let start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+
+        self.index
+            .seek(move |(attribute, _)| match &start {
+                Bound::Included(start) => attribute < start,
+                Bound::Excluded(start) => attribute <= start,
+                Bound::Unbounded => false,
+            })
+            .take_while(move |((attribute, _), _)| match &end {
+                Bound::Included(end) => attribute <= end,
+                Bound::Excluded(end) => attribute < end,
+                Bound::Unbounded => true,
+            })
+            .map(|((_, key), _)| key)
+    }
+
+    // Every record in order of primary key.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.primary.iter()
+    }
+
+    // Check both trees, and that the index has an entry for each record with
+    // its current attribute, and no other.
+    pub fn validate(&self) -> Result<(), String>
+    where
+        K: std::fmt::Debug,
+        A: std::fmt::Debug,
+    {
+        self.primary.validate()?;
+        self.index.validate()?;
+
+        let mut entries: Vec<_> = self
+            .primary
+            .iter()
+            .map(|(key, value)| ((self.attribute)(value), key))
+            .collect();
+        entries.sort();
+        let mut index = self
+            .index
+            .iter()
+            .map(|((attribute, key), _)| (attribute, key));
+        for (attribute, key) in &entries {
+            match index.next() {
+                Some(entry) if entry == (attribute, *key) => {}
+                entry => {
+                    return Err(format!(
+                        "index has {entry:?} instead of {:?}",
+                        (attribute, key)
+                    ))
+                }
+            }
+        }
+        match index.next() {
+            Some(entry) => Err(format!("index has {entry:?} without a record")),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IndexedTree;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq)]
+    struct User {
+        name: String,
+        country: &'static str,
+        age: u32,
+    }
+
+    fn user(name: &str, country: &'static str, age: u32) -> User {
+        User {
+            name: name.to_string(),
+            country,
+            age,
+        }
+    }
+
+    #[test]
+    fn lookup_by_attribute() {
+        let mut users = IndexedTree::new(4, |user: &User| user.country);
+        users.insert(1, user("ada", "uk", 36));
+        users.insert(2, user("grace", "us", 85));
+        users.insert(3, user("alan", "uk", 41));
+        users.insert(4, user("edsger", "nl", 72));
+
+        assert_eq!(users.lookup(&"uk").collect::<Vec<_>>(), [&1, &3]);
+        assert_eq!(users.lookup(&"fr").count(), 0);
+        assert_eq!(
+            users.lookup_range("nl"..="uk").collect::<Vec<_>>(),
+            [&4, &1, &3]
+        );
+        assert_eq!(users.lookup_range(.."uk").collect::<Vec<_>>(), [&4]);
+
+        // Replacing and updating a record move it in the index.
+        let previous = users.insert(3, user("alan", "us", 41));
+        assert_eq!(previous, Some(user("alan", "uk", 41)));
+        assert!(users.update(&4, |user| user.country = "us"));
+        assert!(users.update(&2, |user| user.age += 1));
+        assert!(!users.update(&5, |user| user.age += 1));
+        assert_eq!(users.lookup(&"us").collect::<Vec<_>>(), [&2, &3, &4]);
+        assert_eq!(users.get(&2).map(|user| user.age), Some(86));
+
+        assert_eq!(
+            users.remove(&3).map(|user| user.name),
+            Some("alan".to_string())
+        );
+        assert_eq!(users.remove(&3), None);
+        assert_eq!(users.lookup(&"us").collect::<Vec<_>>(), [&2, &4]);
+        assert_eq!(users.validate(), Ok(()));
+    }
+
+    #[test]
+    fn random_changes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut tree = IndexedTree::new(4, |value: &u32| value % 10);
+        let mut map = BTreeMap::new();
+
+        for _ in 0..2000 {
+            let key = rng.gen_range(0..300u32);
+            match rng.gen_range(0..3) {
+                0 => {
+                    let value = rng.gen_range(0..1_000_000);
+                    assert_eq!(tree.insert(key, value), map.insert(key, value));
+                }
+                1 => assert_eq!(tree.remove(&key), map.remove(&key)),
+                _ => {
+                    let found = tree.update(&key, |value| *value += 1);
+                    assert_eq!(found, map.get_mut(&key).map(|value| *value += 1).is_some());
+                }
+            }
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree.iter().eq(map.iter()));
+
+        for digit in 0..10 {
+            let expected = map.iter().filter(|(_, value)| *value % 10 == digit);
+            let expected: Vec<_> = expected.map(|(key, _)| key).collect();
+            assert_eq!(tree.lookup(&digit).collect::<Vec<_>>(), expected);
+        }
+        let mut expected: Vec<_> = map
+            .iter()
+            .filter(|(_, value)| (3..7).contains(&(*value % 10)))
+            .collect();
+        expected.sort_by_key(|&(key, value)| (value % 10, *key));
+        let expected: Vec<_> = expected.into_iter().map(|(key, _)| key).collect();
+        assert_eq!(tree.lookup_range(3..7).collect::<Vec<_>>(), expected);
+    }
+}
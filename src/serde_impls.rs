@@ -0,0 +1,175 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::binary_search_tree::BSTree;
+use crate::bplustree::{BPlusTree, DEFAULT_MAX_DEGREE};
+use crate::btree::BTree;
+
+// Trees are serialized as their content in order, not their shape: a B+ Tree
+// as a map, and the trees without values as a sequence of keys. Hence the
+// serialized tree doesn't depend on the order of the inserts and removes, and
+// can be read into any other ordered collection.
+//
+// Deserializing collect every entry first, then build the tree with
+// `bulk_load`. A B+ Tree is built with `DEFAULT_MAX_DEGREE`, as the max degree
+// is not part of the serialized tree.
+
+// Don't trust the size hint to preallocate everything.
+const MAX_PREALLOCATED: usize = 4096;
+
+impl<K: Serialize + Ord + Clone, V: Serialize> Serialize for BPlusTree<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for BPlusTree<K, V>
+where
+    K: Deserialize<'de> + Ord + Clone + 'static,
+    V: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(EntriesVisitor(PhantomData))
+    }
+}
+
+struct EntriesVisitor<K, V>(PhantomData<(K, V)>);
+
+impl<'de, K, V> Visitor<'de> for EntriesVisitor<K, V>
+where
+    K: Deserialize<'de> + Ord + Clone + 'static,
+    V: Deserialize<'de>,
+{
+    type Value = BPlusTree<K, V>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0).min(MAX_PREALLOCATED));
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+
+        Ok(BPlusTree::bulk_load(entries, DEFAULT_MAX_DEGREE))
+    }
+}
+
+impl Serialize for BTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for BTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let keys = deserializer.deserialize_seq(KeysVisitor(PhantomData))?;
+        Ok(BTree::bulk_load(keys))
+    }
+}
+
+impl Serialize for BSTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for BSTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = deserializer.deserialize_seq(KeysVisitor(PhantomData))?;
+        Ok(BSTree::bulk_load(values))
+    }
+}
+
+// Collect the keys of the trees without values, the tree then sort them.
+struct KeysVisitor<K>(PhantomData<K>);
+
+impl<'de, K: Deserialize<'de>> Visitor<'de> for KeysVisitor<K> {
+    type Value = Vec<K>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of keys")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut keys = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(MAX_PREALLOCATED));
+        while let Some(key) = seq.next_element()? {
+            keys.push(key);
+        }
+
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::binary_search_tree::BSTree;
+    use crate::bplustree::BPlusTree;
+    use crate::btree::BTree;
+
+    #[test]
+    fn bplustree_as_a_map() {
+        let mut tree = BPlusTree::with_max_degree(3);
+        for i in [5u32, 1, 4, 2, 3] {
+            tree.insert(i, format!("v{i}"));
+        }
+        tree.remove(&4);
+
+        let json = serde_json::to_string(&tree).unwrap();
+        assert_eq!(json, r#"{"1":"v1","2":"v2","3":"v3","5":"v5"}"#);
+
+        let tree: BPlusTree<u32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(tree.get(&3), Some(&"v3".to_string()));
+        assert_eq!(tree.get(&4), None);
+        assert_eq!(serde_json::to_string(&tree).unwrap(), json);
+    }
+
+    #[test]
+    fn deserialized_bplustree_can_still_be_modified() {
+        let json = serde_json::to_string(&BPlusTree::new((0..500).collect(), 4)).unwrap();
+        let mut tree: BPlusTree<u32> = serde_json::from_str(&json).unwrap();
+
+        for i in (0..500).step_by(2) {
+            assert_eq!(tree.remove(&i), Some(i));
+        }
+        tree.insert(1000, 1000);
+
+        let keys: Vec<u32> = tree.iter().map(|(k, _)| *k).collect();
+        let expected: Vec<u32> = (1..500).step_by(2).chain([1000]).collect();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn btree_as_a_sequence() {
+        let mut tree = BTree::new();
+        for i in [8, 3, 5, 1, 9, 2] {
+            tree.insert(i);
+        }
+
+        let json = serde_json::to_string(&tree).unwrap();
+        assert_eq!(json, "[1,2,3,5,8,9]");
+
+        let mut tree: BTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(tree.get(&5), Some(&5));
+        tree.insert(4);
+        assert_eq!(serde_json::to_string(&tree).unwrap(), "[1,2,3,4,5,8,9]");
+    }
+
+    #[test]
+    fn bstree_as_a_sequence() {
+        let tree: BSTree = serde_json::from_str("[4, -1, 7, 2]").unwrap();
+        assert_eq!(tree.get(&-1), Some(&-1));
+        assert_eq!(serde_json::to_string(&tree).unwrap(), "[-1,2,4,7]");
+    }
+
+    #[test]
+    fn reject_unexpected_input() {
+        assert!(serde_json::from_str::<BPlusTree<u32>>(r#"{"a":1}"#).is_err());
+        assert!(serde_json::from_str::<BTree>("[1,-2]").is_err());
+        assert!(serde_json::from_str::<BSTree>("{}").is_err());
+    }
+}
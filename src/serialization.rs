@@ -0,0 +1,234 @@
+use std::io::{self, Read, Write};
+
+// Binary format shared by every tree:
+//
+//   magic        4 bytes, "TREE"
+//   version      u8
+//   kind         u8, which tree wrote it
+//   degree       u32, minimum degree of a B Tree, max degree of a B+ Tree
+//   key codec    u8
+//   value codec  u8, 0 when the tree has no values
+//   body         tree specific node records, in pre-order
+//
+// Every integer is little endian. The node records keep the exact shape of
+// the tree, so reading it back doesn't need to insert the keys one by one.
+pub const MAGIC: [u8; 4] = *b"TREE";
+pub const VERSION: u8 = 1;
+
+pub(crate) const NO_VALUE: u8 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(clippy::enum_variant_names)]
+pub(crate) enum Kind {
+    BSTree = 1,
+    BTree = 2,
+    BPlusTree = 3,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    UnexpectedKind(u8),
+    UnexpectedCodec(u8),
+    UnexpectedDegree(u32),
+    Corrupted(&'static str),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{error}"),
+            Error::InvalidMagic => write!(f, "not a serialized tree"),
+            Error::UnsupportedVersion(version) => write!(f, "unsupported version {version}"),
+            Error::UnexpectedKind(kind) => write!(f, "serialized tree is of kind {kind}"),
+            Error::UnexpectedCodec(codec) => write!(f, "serialized tree use codec {codec}"),
+            Error::UnexpectedDegree(degree) => write!(f, "serialized tree has degree {degree}"),
+            Error::Corrupted(reason) => write!(f, "corrupted tree: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+// How keys and values are written, identified by `ID` in the header so a
+// tree can't be read back with different types.
+pub trait Codec: Sized {
+    const ID: u8;
+
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, Error>;
+}
+
+macro_rules! integer_codec {
+    ($type:ty, $id:expr) => {
+        impl Codec for $type {
+            const ID: u8 = $id;
+
+            fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+                writer.write_all(&self.to_le_bytes())
+            }
+
+            fn decode<R: Read>(reader: &mut R) -> Result<Self, Error> {
+                let mut bytes = [0; std::mem::size_of::<$type>()];
+                reader.read_exact(&mut bytes)?;
+                Ok(<$type>::from_le_bytes(bytes))
+            }
+        }
+    };
+}
+
+integer_codec!(u32, 1);
+integer_codec!(i32, 2);
+integer_codec!(u64, 3);
+integer_codec!(i64, 4);
+
+// Length prefixed bytes.
+impl Codec for Vec<u8> {
+    const ID: u8 = 5;
+
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_len(writer, self.len())?;
+        writer.write_all(self)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, Error> {
+        let len = read_len(reader)?;
+        let mut bytes = vec![];
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+
+        if bytes.len() != len {
+            return Err(Error::Corrupted("truncated bytes"));
+        }
+        Ok(bytes)
+    }
+}
+
+impl Codec for String {
+    const ID: u8 = 6;
+
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_len(writer, self.len())?;
+        writer.write_all(self.as_bytes())
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, Error> {
+        let bytes = Vec::<u8>::decode(reader)?;
+        String::from_utf8(bytes).map_err(|_| Error::Corrupted("invalid utf-8 string"))
+    }
+}
+
+pub(crate) fn write_header<W: Write>(
+    writer: &mut W,
+    kind: Kind,
+    degree: u32,
+    key_codec: u8,
+    value_codec: u8,
+) -> io::Result<()> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[VERSION, kind as u8])?;
+    degree.encode(writer)?;
+    writer.write_all(&[key_codec, value_codec])
+}
+
+// Check the header against what the caller expect, returning the degree.
+pub(crate) fn read_header<R: Read>(
+    reader: &mut R,
+    kind: Kind,
+    key_codec: u8,
+    value_codec: u8,
+) -> Result<u32, Error> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(Error::InvalidMagic);
+    }
+
+    let version = read_u8(reader)?;
+    if version != VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    let actual_kind = read_u8(reader)?;
+    if actual_kind != kind as u8 {
+        return Err(Error::UnexpectedKind(actual_kind));
+    }
+
+    let degree = u32::decode(reader)?;
+
+    for expected in [key_codec, value_codec] {
+        let codec = read_u8(reader)?;
+        if codec != expected {
+            return Err(Error::UnexpectedCodec(codec));
+        }
+    }
+
+    Ok(degree)
+}
+
+pub(crate) fn write_u8<W: Write>(writer: &mut W, value: u8) -> io::Result<()> {
+    writer.write_all(&[value])
+}
+
+pub(crate) fn read_u8<R: Read>(reader: &mut R) -> Result<u8, Error> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+pub(crate) fn write_len<W: Write>(writer: &mut W, len: usize) -> io::Result<()> {
+    (len as u32).encode(writer)
+}
+
+pub(crate) fn read_len<R: Read>(reader: &mut R) -> Result<usize, Error> {
+    Ok(u32::decode(reader)? as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_header, write_header, Codec, Error, Kind};
+
+    #[test]
+    fn codec_round_trip() {
+        let mut bytes = vec![];
+        42u32.encode(&mut bytes).unwrap();
+        (-42i64).encode(&mut bytes).unwrap();
+        "hello".to_string().encode(&mut bytes).unwrap();
+
+        let mut reader = bytes.as_slice();
+        assert_eq!(u32::decode(&mut reader).unwrap(), 42);
+        assert_eq!(i64::decode(&mut reader).unwrap(), -42);
+        assert_eq!(String::decode(&mut reader).unwrap(), "hello");
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn reject_unexpected_header() {
+        let mut bytes = vec![];
+        write_header(&mut bytes, Kind::BTree, 2, u32::ID, 0).unwrap();
+
+        assert!(read_header(&mut bytes.as_slice(), Kind::BTree, u32::ID, 0).is_ok());
+        assert!(matches!(
+            read_header(&mut bytes.as_slice(), Kind::BPlusTree, u32::ID, 0),
+            Err(Error::UnexpectedKind(2))
+        ));
+        assert!(matches!(
+            read_header(&mut bytes.as_slice(), Kind::BTree, i32::ID, 0),
+            Err(Error::UnexpectedCodec(1))
+        ));
+
+        bytes[0] = b'X';
+        assert!(matches!(
+            read_header(&mut bytes.as_slice(), Kind::BTree, u32::ID, 0),
+            Err(Error::InvalidMagic)
+        ));
+    }
+}
@@ -0,0 +1,257 @@
+use std::any::Any;
+
+// Search the sorted keys of a node with SIMD instead of a binary search,
+// comparing the key with 4 keys at once:
+//
+//   keys:  [ 3 | 5 | 8 | 9 ][ 12 | 15 | 20 | 21 ]   key 13
+//   less:  [ 1 | 1 | 1 | 1 ][  1 |  0 |  0 |  0 ]
+//                                 ^ lower bound 5
+//
+// The keys are sorted, so the number of keys less than the key is its lower
+// bound. Scanning every chunk of a wide node is slower than a binary search,
+// so the keys are first narrowed down with a branchless binary search to a
+// window of at most `WINDOW` keys, which is then scanned:
+//
+//   [ . . . . . . . . . . . . . . . . . . . . . . . . . . . . . . . ]
+//                   [ . . . . . . . . . . . . . . . ]  window
+//
+// Nodes of fewer keys than a window are searched with `binary_search`. Both
+// return the same: `Ok` with the index of the key, or `Err` with the index to
+// insert it at.
+//
+// 32 bits keys are compared with SSE2, always there on x86_64, so the scan is
+// inlined without checking for it. 64 bits keys need AVX2, detected at
+// runtime. Other architectures, and 64 bits keys without AVX2, search the
+// window with `partition_point`.
+
+// Dispatch to the SIMD search of `u32` and `i32` keys in nodes of at least
+// `SIMD_KEYS` keys, and to `binary_search` for any other node. The
+// `node_search` benchmark only shows the SIMD search winning there: on par
+// or slower for nodes of up to 191 keys, and slower for `u64` keys at any
+// size even with AVX2, so those keep `binary_search`.
+#[inline]
+pub(crate) fn search<K: Ord + 'static>(keys: &[K], key: &K) -> Result<usize, usize> {
+    if keys.len() < SIMD_KEYS {
+        return keys.binary_search(key);
+    }
+    let any = key as &dyn Any;
+    // Safety: the casts are from `[K]` to the same type, checked by the
+    // downcast of the key.
+    if let Some(&key) = any.downcast_ref::<u32>() {
+        search_u32(unsafe { &*(keys as *const [K] as *const [u32]) }, key)
+    } else if let Some(&key) = any.downcast_ref::<i32>() {
+        search_i32(unsafe { &*(keys as *const [K] as *const [i32]) }, key)
+    } else {
+        keys.binary_search(key)
+    }
+}
+
+// Keys of a full node of max degree 256, the smallest node the SIMD search
+// is faster on.
+const SIMD_KEYS: usize = 255;
+
+#[inline]
+pub fn search_u32(keys: &[u32], key: u32) -> Result<usize, usize> {
+    if keys.len() < WINDOW {
+        return keys.binary_search(&key);
+    }
+    found(keys, key, lower_bound_u32(keys, key))
+}
+
+#[inline]
+pub fn search_u64(keys: &[u64], key: u64) -> Result<usize, usize> {
+    if keys.len() < WINDOW {
+        return keys.binary_search(&key);
+    }
+    found(keys, key, lower_bound_u64(keys, key))
+}
+
+#[inline]
+pub fn search_i32(keys: &[i32], key: i32) -> Result<usize, usize> {
+    if keys.len() < WINDOW {
+        return keys.binary_search(&key);
+    }
+    found(keys, key, lower_bound_i32(keys, key))
+}
+
+// Number of keys scanned with SIMD once a binary search narrowed them down,
+// four chunks of 32 bits keys.
+const WINDOW: usize = 16;
+
+// Narrow the keys, at least `WINDOW` of them, down to the window of `WINDOW`
+// keys holding the lower bound, returning where it starts. The halves are
+// picked without a branch, so there is no misprediction to pay for on random
+// keys. The window is then widened back to `WINDOW` keys: the keys it gains
+// before the lower bound are less than the key, the ones after it aren't.
+#[inline]
+fn narrow<T: Ord>(keys: &[T], key: &T) -> usize {
+    let (mut start, mut len) = (0, keys.len());
+    while len > WINDOW {
+        let half = len / 2;
+        start += half * (keys[start + half - 1] < *key) as usize;
+        len -= half;
+    }
+    start.min(keys.len() - WINDOW)
+}
+
+#[inline]
+fn found<T: PartialEq>(keys: &[T], key: T, index: usize) -> Result<usize, usize> {
+    match keys.get(index) {
+        Some(other) if *other == key => Ok(index),
+        _ => Err(index),
+    }
+}
+
+// SSE2 and AVX2 only compare signed integers, so the unsigned keys are
+// compared with their sign bit flipped, which keeps them in the same order.
+#[inline]
+fn lower_bound_u32(keys: &[u32], key: u32) -> usize {
+    let start = narrow(keys, &key);
+    let keys = &keys[start..start + WINDOW];
+    #[cfg(target_arch = "x86_64")]
+    {
+        // Safety: `u32` and `i32` have the same layout.
+        let signed = unsafe { &*(keys as *const [u32] as *const [i32]) };
+        start + x86::lower_bound_32(signed, key as i32, i32::MIN)
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        start + keys.partition_point(|&other| other < key)
+    }
+}
+
+#[inline]
+fn lower_bound_i32(keys: &[i32], key: i32) -> usize {
+    let start = narrow(keys, &key);
+    let keys = &keys[start..start + WINDOW];
+    #[cfg(target_arch = "x86_64")]
+    {
+        start + x86::lower_bound_32(keys, key, 0)
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        start + keys.partition_point(|&other| other < key)
+    }
+}
+
+#[inline]
+fn lower_bound_u64(keys: &[u64], key: u64) -> usize {
+    let start = narrow(keys, &key);
+    let keys = &keys[start..start + WINDOW];
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // Safety: AVX2 is available, and `u64` and `i64` have the same
+        // layout.
+        return start
+            + unsafe {
+                let signed = &*(keys as *const [u64] as *const [i64]);
+                x86::lower_bound_64_avx2(signed, key as i64, i64::MIN)
+            };
+    }
+    start + keys.partition_point(|&other| other < key)
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::WINDOW;
+
+    // Lower bound of the keys of a window compared after a xor with `bias`,
+    // `i32::MIN` to compare them as unsigned. Every chunk is compared and the
+    // keys less than the key counted, without a branch to mispredict on the
+    // chunk holding it.
+    #[inline]
+    pub(super) fn lower_bound_32(keys: &[i32], key: i32, bias: i32) -> usize {
+        let window: &[i32; WINDOW] = keys.try_into().unwrap();
+        // Safety: SSE2 is always available on x86_64, and the loads are of
+        // whole chunks within the window.
+        unsafe {
+            let biased = _mm_set1_epi32(bias);
+            let needle = _mm_xor_si128(_mm_set1_epi32(key), biased);
+            window
+                .chunks_exact(4)
+                .map(|chunk| {
+                    let chunk = _mm_loadu_si128(chunk.as_ptr().cast());
+                    let less = _mm_cmpgt_epi32(needle, _mm_xor_si128(chunk, biased));
+                    _mm_movemask_ps(_mm_castsi128_ps(less)).count_ones() as usize
+                })
+                .sum()
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn lower_bound_64_avx2(keys: &[i64], key: i64, bias: i64) -> usize {
+        let biased = _mm256_set1_epi64x(bias);
+        let needle = _mm256_xor_si256(_mm256_set1_epi64x(key), biased);
+
+        keys.chunks_exact(4)
+            .map(|chunk| {
+                let chunk = _mm256_loadu_si256(chunk.as_ptr().cast());
+                let less = _mm256_cmpgt_epi64(needle, _mm256_xor_si256(chunk, biased));
+                _mm256_movemask_pd(_mm256_castsi256_pd(less)).count_ones() as usize
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{search, search_i32, search_u32, search_u64};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn same_as_binary_search() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // Every length around the chunk sizes, with keys at both ends of the
+        // range of each type.
+        for len in (0..40).chain([100, 255, 500]) {
+            let mut keys: Vec<u32> = (0..len).map(|_| rng.gen()).collect();
+            keys.extend([0, u32::MAX]);
+            keys.sort_unstable();
+            keys.dedup();
+            for key in keys.clone().into_iter().chain([1, u32::MAX - 1, rng.gen()]) {
+                assert_eq!(search_u32(&keys, key), keys.binary_search(&key));
+            }
+
+            let mut keys: Vec<u64> = (0..len).map(|_| rng.gen()).collect();
+            keys.extend([0, u64::MAX]);
+            keys.sort_unstable();
+            keys.dedup();
+            for key in keys.clone().into_iter().chain([1, u64::MAX - 1, rng.gen()]) {
+                assert_eq!(search_u64(&keys, key), keys.binary_search(&key));
+            }
+
+            let mut keys: Vec<i32> = (0..len).map(|_| rng.gen()).collect();
+            keys.extend([i32::MIN, -1, 0, i32::MAX]);
+            keys.sort_unstable();
+            keys.dedup();
+            for key in keys.clone().into_iter().chain([1, i32::MIN + 1, rng.gen()]) {
+                assert_eq!(search_i32(&keys, key), keys.binary_search(&key));
+            }
+        }
+    }
+
+    #[test]
+    fn dispatch_on_the_key_type() {
+        // Nodes narrow or wide enough for the SIMD search.
+        for len in [20usize, 300] {
+            let keys: Vec<u32> = (0..len as u32).map(|i| i * 2).collect();
+            assert_eq!(search(&keys, &10), Ok(5));
+            assert_eq!(search(&keys, &411), Err(206.min(len)));
+
+            let keys: Vec<i32> = (0..len as i32).map(|i| i * 2 - 100).collect();
+            assert_eq!(search(&keys, &-90), Ok(5));
+            assert_eq!(search(&keys, &-101), Err(0));
+
+            let keys: Vec<u64> = (0..len as u64).map(|i| i * 2).collect();
+            assert_eq!(search(&keys, &10), Ok(5));
+            assert_eq!(search(&keys, &11), Err(6));
+        }
+
+        let keys: Vec<String> = ["a", "c"].map(String::from).to_vec();
+        assert_eq!(search(&keys, &"b".to_string()), Err(1));
+    }
+}
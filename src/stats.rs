@@ -0,0 +1,159 @@
+use std::fmt;
+
+// Shape and memory footprint of a tree, as returned by `stats()` on every
+// tree. A BSTree node holds a single key, so it is always full, and it never
+// split, merge or borrow.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub height: usize, // Number of levels, 0 for an empty tree.
+    pub internal_nodes: usize,
+    pub leaf_nodes: usize,
+    pub keys: usize,
+    pub min_keys: usize, // Keys in the node with the fewest, 0 for an empty tree.
+    pub max_keys: usize,
+    // Number of nodes by fill factor, the keys over the most keys a node can
+    // hold, by steps of 10%: `fill_factors[3]` are the nodes 30% to 40% full.
+    // Full nodes are counted in the last one. Nodes limited in bytes instead
+    // of keys are as full as their bytes over the most bytes they can hold.
+    pub fill_factors: [usize; 10],
+    pub heap_bytes: HeapBytes,
+    pub counters: Counters,
+}
+
+// Bytes allocated for the nodes, counting the capacity of the vectors and not
+// only their length. Keys and values that own heap memory themselves, e.g. a
+// `String`, only count for their `size_of`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapBytes {
+    pub keys: usize,
+    pub values: usize,
+    pub childrens: usize,
+    pub nodes: usize, // Nodes allocated on their own, e.g. in a `Box` or an arena.
+}
+
+// Structural changes since the tree was created, either empty, from
+// `bulk_load` or read back with `read_from`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    pub splits: u64,
+    pub merges: u64,
+    pub borrows: u64,
+}
+
+impl Stats {
+    pub fn nodes(&self) -> usize {
+        self.internal_nodes + self.leaf_nodes
+    }
+
+    pub fn average_keys(&self) -> f64 {
+        match self.nodes() {
+            0 => 0.0,
+            nodes => self.keys as f64 / nodes as f64,
+        }
+    }
+
+    // Count a node holding `keys` out of at most `capacity`.
+    pub(crate) fn add_node(&mut self, keys: usize, capacity: usize, is_leaf: bool) {
+        self.add_sized_node(keys, keys, capacity, is_leaf);
+    }
+
+    // Count a node holding `keys` in `size` bytes out of at most `capacity`.
+    pub(crate) fn add_sized_node(
+        &mut self,
+        keys: usize,
+        size: usize,
+        capacity: usize,
+        is_leaf: bool,
+    ) {
+        if self.nodes() == 0 {
+            self.min_keys = keys;
+        }
+        self.min_keys = self.min_keys.min(keys);
+        self.max_keys = self.max_keys.max(keys);
+        self.keys += keys;
+        self.fill_factors[(size * 10 / capacity).min(9)] += 1;
+
+        if is_leaf {
+            self.leaf_nodes += 1;
+        } else {
+            self.internal_nodes += 1;
+        }
+    }
+}
+
+impl HeapBytes {
+    pub fn total(&self) -> usize {
+        self.keys + self.values + self.childrens + self.nodes
+    }
+
+    // Count the buffer of a vector.
+    pub(crate) fn of<T>(vec: &Vec<T>) -> usize {
+        vec.capacity() * std::mem::size_of::<T>()
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "height {}, {} internal nodes, {} leaves, {} keys",
+            self.height, self.internal_nodes, self.leaf_nodes, self.keys
+        )?;
+        writeln!(
+            f,
+            "keys per node: min {}, max {}, average {:.2}",
+            self.min_keys,
+            self.max_keys,
+            self.average_keys()
+        )?;
+
+        write!(f, "fill factors:")?;
+        for (i, nodes) in self.fill_factors.iter().enumerate() {
+            if *nodes > 0 {
+                write!(f, " {}-{}%: {nodes}", i * 10, (i + 1) * 10)?;
+            }
+        }
+        writeln!(f)?;
+
+        let bytes = &self.heap_bytes;
+        writeln!(
+            f,
+            "heap bytes: {} (keys {}, values {}, childrens {}, nodes {})",
+            bytes.total(),
+            bytes.keys,
+            bytes.values,
+            bytes.childrens,
+            bytes.nodes
+        )?;
+
+        let counters = &self.counters;
+        writeln!(
+            f,
+            "splits {}, merges {}, borrows {}",
+            counters.splits, counters.merges, counters.borrows
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Stats;
+
+    #[test]
+    fn add_nodes() {
+        let mut stats = Stats::default();
+        assert_eq!(stats.average_keys(), 0.0);
+
+        stats.add_node(2, 3, false);
+        stats.add_node(3, 3, true);
+        stats.add_node(1, 3, true);
+
+        assert_eq!(
+            (stats.internal_nodes, stats.leaf_nodes, stats.nodes()),
+            (1, 2, 3)
+        );
+        assert_eq!((stats.min_keys, stats.max_keys, stats.keys), (1, 3, 6));
+        assert_eq!(stats.average_keys(), 2.0);
+        assert_eq!(stats.fill_factors, [0, 0, 0, 1, 0, 0, 1, 0, 0, 1]);
+    }
+}
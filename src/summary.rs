@@ -0,0 +1,135 @@
+use std::ops::Add;
+
+// Summary of the entries of a subtree, e.g. the sum of their values. Each
+// internal node of a `BPlusTree` keeps the summary of each of its childrens,
+// so `BPlusTree::aggregate` combines the summaries of the childrens entirely
+// in a range instead of visiting their entries:
+//
+//                   [5]
+//            6 /          \ 18
+//           [3]            [7]
+//        3 /   \ 3     11 /   \ 7
+//      [1, 2]   [3]   [5, 6]   [7]
+//
+//   aggregate(2..=6) = 2 + 3 + 11
+//
+// A summary is a monoid: `combine` is associative, with `identity` on either
+// side, so the summaries can be combined in any grouping. They are combined
+// in the order of the keys, so `combine` doesn't have to be commutative. The
+// default `()` keeps no summary.
+pub trait Summary<K, V>: Clone {
+    fn identity() -> Self;
+    fn combine(&self, other: &Self) -> Self;
+
+    // Summary of a single entry.
+    fn entry(key: &K, value: &V) -> Self;
+}
+
+impl<K, V> Summary<K, V> for () {
+    fn identity() -> Self {}
+
+    fn combine(&self, _: &Self) -> Self {}
+
+    fn entry(_: &K, _: &V) -> Self {}
+}
+
+// Number of entries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Count(pub usize);
+
+impl<K, V> Summary<K, V> for Count {
+    fn identity() -> Self {
+        Count(0)
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Count(self.0 + other.0)
+    }
+
+    fn entry(_: &K, _: &V) -> Self {
+        Count(1)
+    }
+}
+
+// Sum of the values, 0 without entries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sum<V>(pub V);
+
+impl<K, V: Clone + Default + Add<Output = V>> Summary<K, V> for Sum<V> {
+    fn identity() -> Self {
+        Sum(V::default())
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Sum(self.0.clone() + other.0.clone())
+    }
+
+    fn entry(_: &K, value: &V) -> Self {
+        Sum(value.clone())
+    }
+}
+
+// Smallest value, None without entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Min<V>(pub Option<V>);
+
+impl<K, V: Clone + Ord> Summary<K, V> for Min<V> {
+    fn identity() -> Self {
+        Min(None)
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Min(Some(a.min(b).clone())),
+            (a, b) => Min(a.clone().or(b.clone())),
+        }
+    }
+
+    fn entry(_: &K, value: &V) -> Self {
+        Min(Some(value.clone()))
+    }
+}
+
+// Largest value, None without entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Max<V>(pub Option<V>);
+
+impl<K, V: Clone + Ord> Summary<K, V> for Max<V> {
+    fn identity() -> Self {
+        Max(None)
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Max(Some(a.max(b).clone())),
+            (a, b) => Max(a.clone().or(b.clone())),
+        }
+    }
+
+    fn entry(_: &K, value: &V) -> Self {
+        Max(Some(value.clone()))
+    }
+}
+
+// Every summary of the tuple at once, e.g. `(Count, Sum<u64>)` for the mean.
+macro_rules! tuple_summary {
+    ($($name:ident $index:tt),*) => {
+        impl<K, V, $($name: Summary<K, V>),*> Summary<K, V> for ($($name,)*) {
+            fn identity() -> Self {
+                ($($name::identity(),)*)
+            }
+
+            fn combine(&self, other: &Self) -> Self {
+                ($(self.$index.combine(&other.$index),)*)
+            }
+
+            fn entry(key: &K, value: &V) -> Self {
+                ($($name::entry(key, value),)*)
+            }
+        }
+    };
+}
+
+tuple_summary!(A 0, B 1);
+tuple_summary!(A 0, B 1, C 2);
+tuple_summary!(A 0, B 1, C 2, D 3);